@@ -0,0 +1,74 @@
+//! Blocking API for callers without an async runtime
+//!
+//! [`BlockingMailer`] owns a current-thread runtime and drives the async
+//! [`MailerService`] on it. Call it from ordinary threads only; blocking
+//! calls panic inside an async context.
+
+use std::sync::Arc;
+use tokio::runtime::{Builder, Runtime};
+
+use crate::models::{Email, EmailAddress};
+use crate::services::{
+    MailTransport, MailerService,
+    mailer::{DeliveryOutcome, MailerConfig, MailerError, ProcessResult},
+};
+
+/// Blocking wrapper around [`MailerService`]
+pub struct BlockingMailer {
+    mailer: Arc<MailerService>,
+    runtime: Runtime,
+}
+
+impl BlockingMailer {
+    /// Wrap a new mailer service
+    pub fn new() -> std::io::Result<Self> {
+        Self::from_mailer(Arc::new(MailerService::new()))
+    }
+
+    /// Wrap an existing mailer service, e.g. one shared with async code
+    pub fn from_mailer(mailer: Arc<MailerService>) -> std::io::Result<Self> {
+        let runtime = Builder::new_current_thread().enable_all().build()?;
+        Ok(Self { mailer, runtime })
+    }
+
+    /// Underlying async service
+    pub fn mailer(&self) -> &Arc<MailerService> {
+        &self.mailer
+    }
+
+    /// Configure mailer
+    pub fn configure(&self, config: MailerConfig) {
+        self.runtime.block_on(self.mailer.configure(config))
+    }
+
+    /// Register system templates
+    pub fn initialize(&self) {
+        self.runtime.block_on(self.mailer.initialize())
+    }
+
+    /// Use a custom transport for delivery
+    pub fn set_transport(&self, transport: impl MailTransport + 'static) {
+        self.runtime.block_on(self.mailer.set_transport(transport))
+    }
+
+    /// Send email immediately
+    pub fn send(&self, email: Email) -> Result<DeliveryOutcome, MailerError> {
+        self.runtime.block_on(self.mailer.send(email))
+    }
+
+    /// Send email using template
+    pub fn send_template(
+        &self,
+        template_slug: &str,
+        to: EmailAddress,
+        data: serde_json::Value,
+        topic: Option<&str>,
+    ) -> Result<DeliveryOutcome, MailerError> {
+        self.runtime.block_on(self.mailer.send_template(template_slug, to, data, topic))
+    }
+
+    /// Process up to `batch_size` queued emails
+    pub fn process_queue(&self, batch_size: usize) -> ProcessResult {
+        self.runtime.block_on(self.mailer.process_queue(batch_size))
+    }
+}
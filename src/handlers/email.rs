@@ -3,8 +3,9 @@
 use std::sync::Arc;
 use serde::{Deserialize, Serialize};
 
-use crate::models::{Email, EmailAddress, EmailBuilder, EmailPriority, Attachment};
-use crate::services::MailerService;
+use crate::models::{EmailAddress, EmailPriority, Attachment};
+use crate::services::{MailerService, mailer::{DeliveryDisposition, TemplateRecipients}};
+use super::HandlerError;
 
 #[derive(Debug, Deserialize)]
 pub struct SendEmailRequest {
@@ -33,6 +34,14 @@ pub struct SendTemplateRequest {
     pub to: String,
     pub to_name: Option<String>,
     pub data: serde_json::Value,
+    #[serde(default)]
+    pub topic: Option<String>,
+    #[serde(default)]
+    pub cc: Option<Vec<String>>,
+    #[serde(default)]
+    pub bcc: Option<Vec<String>>,
+    #[serde(default)]
+    pub reply_to: Option<String>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -83,7 +92,7 @@ impl EmailHandler {
     }
 
     /// Send email
-    pub async fn send(&self, request: SendEmailRequest) -> Result<SendResponse, String> {
+    pub async fn send(&self, request: SendEmailRequest) -> Result<SendResponse, HandlerError> {
         // Build email
         let mut builder = self.mailer.builder().await
             .subject(&request.subject);
@@ -142,54 +151,55 @@ impl EmailHandler {
                 let content = base64::Engine::decode(
                     &base64::engine::general_purpose::STANDARD,
                     &att.content_base64
-                ).map_err(|e| format!("Invalid attachment encoding: {}", e))?;
+                ).map_err(|e| HandlerError::BadRequest(format!("Invalid attachment encoding: {}", e)))?;
 
                 let attachment = Attachment::new(&att.filename, &att.content_type, content);
                 builder = builder.attach(attachment);
             }
         }
 
-        let email = builder.build().map_err(|e| e)?;
-        let email_id = email.id.to_string();
-
-        // Queue or send
-        match self.mailer.queue_email(email).await {
-            Ok(item) => Ok(SendResponse {
-                success: true,
-                message: "Email queued for delivery".to_string(),
-                email_id: Some(email_id),
-                queue_id: Some(item.id.to_string()),
-            }),
-            Err(e) => Ok(SendResponse {
-                success: false,
-                message: e.to_string(),
-                email_id: Some(email_id),
-                queue_id: None,
-            }),
-        }
+        let email = builder.build().map_err(HandlerError::BadRequest)?;
+
+        // Queue or send, as configured
+        let outcome = self.mailer.deliver(email).await?;
+
+        Ok(SendResponse {
+            success: true,
+            message: match outcome.disposition {
+                DeliveryDisposition::Sent { .. } => "Email sent".to_string(),
+                DeliveryDisposition::Queued { .. } => "Email queued for delivery".to_string(),
+            },
+            email_id: Some(outcome.email_id.to_string()),
+            queue_id: outcome.queue_id().map(|id| id.to_string()),
+        })
     }
 
     /// Send using template
-    pub async fn send_template(&self, request: SendTemplateRequest) -> Result<SendResponse, String> {
+    pub async fn send_template(&self, request: SendTemplateRequest) -> Result<SendResponse, HandlerError> {
         let to = match request.to_name {
             Some(name) => EmailAddress::with_name(&request.to, &name),
             None => EmailAddress::new(&request.to),
         };
 
-        match self.mailer.send_template(&request.template, to, request.data).await {
-            Ok(()) => Ok(SendResponse {
-                success: true,
-                message: "Email sent/queued successfully".to_string(),
-                email_id: None,
-                queue_id: None,
-            }),
-            Err(e) => Ok(SendResponse {
-                success: false,
-                message: e.to_string(),
-                email_id: None,
-                queue_id: None,
-            }),
+        let mut recipients = TemplateRecipients::new(to);
+        for addr in request.cc.unwrap_or_default() {
+            recipients = recipients.cc(addr);
+        }
+        for addr in request.bcc.unwrap_or_default() {
+            recipients = recipients.bcc(addr);
         }
+        if let Some(reply_to) = request.reply_to {
+            recipients = recipients.reply_to(reply_to);
+        }
+
+        let outcome = self.mailer.send_template_with(&request.template, recipients, request.data, request.topic.as_deref()).await?;
+
+        Ok(SendResponse {
+            success: true,
+            message: "Email sent/queued successfully".to_string(),
+            email_id: Some(outcome.email_id.to_string()),
+            queue_id: outcome.queue_id().map(|id| id.to_string()),
+        })
     }
 
     /// Send bulk using template
@@ -215,9 +225,10 @@ impl EmailHandler {
 
         for (index, result) in results.into_iter().enumerate() {
             match result {
-                Ok(()) => {
-                    queued += 1;
-                }
+                Ok(outcome) => match outcome.disposition {
+                    DeliveryDisposition::Sent { .. } => sent += 1,
+                    DeliveryDisposition::Queued { .. } => queued += 1,
+                },
                 Err(e) => {
                     errors.push(BulkError {
                         index,
@@ -239,24 +250,18 @@ impl EmailHandler {
     }
 
     /// Test email configuration
-    pub async fn test(&self, to: &str) -> Result<SendResponse, String> {
-        match self.mailer.quick_send(
+    pub async fn test(&self, to: &str) -> Result<SendResponse, HandlerError> {
+        let outcome = self.mailer.quick_send(
             to,
             "Test Email from RustMail",
             "This is a test email to verify your email configuration is working correctly.",
-        ).await {
-            Ok(()) => Ok(SendResponse {
-                success: true,
-                message: "Test email sent successfully".to_string(),
-                email_id: None,
-                queue_id: None,
-            }),
-            Err(e) => Ok(SendResponse {
-                success: false,
-                message: e.to_string(),
-                email_id: None,
-                queue_id: None,
-            }),
-        }
+        ).await?;
+
+        Ok(SendResponse {
+            success: true,
+            message: "Test email sent successfully".to_string(),
+            email_id: Some(outcome.email_id.to_string()),
+            queue_id: outcome.queue_id().map(|id| id.to_string()),
+        })
     }
 }
@@ -0,0 +1,77 @@
+//! Handler errors
+
+use crate::services::{mailer::MailerError, queue::QueueError, template::TemplateError};
+
+/// Error returned by handlers, distinguishing the cases the HTTP layer maps
+/// to different status codes
+#[derive(Debug, thiserror::Error)]
+pub enum HandlerError {
+    #[error("Not found: {0}")]
+    NotFound(String),
+    #[error("Bad request: {0}")]
+    BadRequest(String),
+    #[error("Conflict: {0}")]
+    Conflict(String),
+    #[error("Too many requests: {0}")]
+    TooManyRequests(String),
+    #[error("Internal error: {0}")]
+    Internal(String),
+}
+
+impl HandlerError {
+    /// HTTP status code for this error
+    pub fn status_code(&self) -> u16 {
+        match self {
+            Self::NotFound(_) => 404,
+            Self::BadRequest(_) => 400,
+            Self::Conflict(_) => 409,
+            Self::TooManyRequests(_) => 429,
+            Self::Internal(_) => 500,
+        }
+    }
+}
+
+impl From<uuid::Error> for HandlerError {
+    fn from(e: uuid::Error) -> Self {
+        Self::BadRequest(format!("Invalid ID: {}", e))
+    }
+}
+
+impl From<TemplateError> for HandlerError {
+    fn from(e: TemplateError) -> Self {
+        match e {
+            TemplateError::NotFound(_) | TemplateError::LayoutNotFound(_) => Self::NotFound(e.to_string()),
+            TemplateError::SlugExists(_) => Self::Conflict(e.to_string()),
+            TemplateError::Invalid(_) | TemplateError::MissingVariable(_) => Self::BadRequest(e.to_string()),
+            TemplateError::RenderError(_) | TemplateError::Render { .. } => Self::Internal(e.to_string()),
+        }
+    }
+}
+
+impl From<QueueError> for HandlerError {
+    fn from(e: QueueError) -> Self {
+        match e {
+            QueueError::NotFound(_) => Self::NotFound(e.to_string()),
+            // Operations invalid for the item's current status
+            QueueError::Invalid(_) => Self::Conflict(e.to_string()),
+            QueueError::Duplicate(_) => Self::Conflict(e.to_string()),
+            // Back pressure: the client should retry once the queue drains
+            QueueError::QueueFull => Self::TooManyRequests(e.to_string()),
+        }
+    }
+}
+
+impl From<MailerError> for HandlerError {
+    fn from(e: MailerError) -> Self {
+        match e {
+            MailerError::Template(e) => e.into(),
+            MailerError::Queue(e) => e.into(),
+            MailerError::Invalid(_) => Self::BadRequest(e.to_string()),
+            MailerError::Suppressed(_) => Self::Conflict(e.to_string()),
+            MailerError::RateLimited(_) => Self::TooManyRequests(e.to_string()),
+            MailerError::Smtp(_) | MailerError::Configuration(_) | MailerError::Halted(_) => {
+                Self::Internal(e.to_string())
+            }
+        }
+    }
+}
@@ -5,8 +5,9 @@ use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 use chrono::{DateTime, Utc};
 
-use crate::models::{EmailLog, EmailEvent, LogFilter, LogStats};
+use crate::models::{EmailLog, EmailEvent, LogFilter, Paginated, Redaction};
 use crate::services::LogService;
+use super::HandlerError;
 
 #[derive(Debug, Deserialize)]
 pub struct LogQuery {
@@ -20,6 +21,8 @@ pub struct LogQuery {
     pub errors_only: Option<bool>,
     pub limit: Option<u32>,
     pub offset: Option<u32>,
+    pub redact_recipients: Option<bool>,
+    pub redact_subjects: Option<bool>,
 }
 
 #[derive(Debug, Serialize)]
@@ -74,7 +77,7 @@ impl LogHandler {
     }
 
     /// Query logs
-    pub async fn query(&self, query: LogQuery) -> Vec<LogEntryResponse> {
+    pub async fn query(&self, query: LogQuery) -> Paginated<LogEntryResponse> {
         let filter = LogFilter {
             email_id: query.email_id.and_then(|s| Uuid::parse_str(&s).ok()),
             recipient: query.recipient,
@@ -88,15 +91,13 @@ impl LogHandler {
             offset: query.offset.unwrap_or(0),
         };
 
-        self.log_service.query(filter).await
-            .into_iter()
+        self.log_service.query_page(filter).await
             .map(|e| Self::to_response(&e))
-            .collect()
     }
 
     /// Get logs for email
-    pub async fn for_email(&self, email_id: &str) -> Result<Vec<LogEntryResponse>, String> {
-        let uuid = Uuid::parse_str(email_id).map_err(|e| e.to_string())?;
+    pub async fn for_email(&self, email_id: &str) -> Result<Vec<LogEntryResponse>, HandlerError> {
+        let uuid = Uuid::parse_str(email_id)?;
 
         Ok(self.log_service.get_for_email(uuid).await
             .into_iter()
@@ -162,7 +163,7 @@ impl LogHandler {
 
     /// Add to suppression list
     pub async fn suppress(&self, email: &str) {
-        self.log_service.add_to_suppression(email, crate::services::log::SuppressionReason::Manual).await;
+        self.log_service.add_to_suppression(email, crate::services::log::SuppressionReason::Manual, None).await;
     }
 
     /// Remove from suppression list
@@ -185,7 +186,12 @@ impl LogHandler {
             offset: query.offset.unwrap_or(0),
         };
 
-        self.log_service.export(filter).await
+        let redaction = Redaction {
+            mask_recipients: query.redact_recipients.unwrap_or(false),
+            mask_subjects: query.redact_subjects.unwrap_or(false),
+        };
+
+        self.log_service.export(filter, redaction).await
     }
 
     /// Clean up old logs
@@ -195,22 +201,7 @@ impl LogHandler {
     }
 
     fn parse_event(s: &str) -> Option<EmailEvent> {
-        match s.to_lowercase().as_str() {
-            "queued" => Some(EmailEvent::Queued),
-            "sent" => Some(EmailEvent::Sent),
-            "delivered" => Some(EmailEvent::Delivered),
-            "bounced" => Some(EmailEvent::Bounced),
-            "soft_bounce" => Some(EmailEvent::SoftBounce),
-            "hard_bounce" => Some(EmailEvent::HardBounce),
-            "opened" => Some(EmailEvent::Opened),
-            "clicked" => Some(EmailEvent::Clicked),
-            "spam" | "spam_complaint" => Some(EmailEvent::SpamComplaint),
-            "unsubscribed" => Some(EmailEvent::Unsubscribed),
-            "failed" => Some(EmailEvent::Failed),
-            "deferred" => Some(EmailEvent::Deferred),
-            "cancelled" => Some(EmailEvent::Cancelled),
-            _ => None,
-        }
+        s.parse().ok()
     }
 
     fn to_response(entry: &EmailLog) -> LogEntryResponse {
@@ -218,7 +209,7 @@ impl LogHandler {
             id: entry.id.to_string(),
             email_id: entry.email_id.to_string(),
             queue_id: entry.queue_id.map(|id| id.to_string()),
-            event: format!("{}", entry.event),
+            event: entry.event.as_str().to_string(),
             recipient: entry.recipient.clone(),
             subject: entry.subject.clone(),
             template_name: entry.template_name.clone(),
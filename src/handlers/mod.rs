@@ -4,8 +4,10 @@ pub mod email;
 pub mod template;
 pub mod queue;
 pub mod log;
+pub mod error;
 
 pub use email::EmailHandler;
 pub use template::TemplateHandler;
 pub use queue::QueueHandler;
 pub use log::LogHandler;
+pub use error::HandlerError;
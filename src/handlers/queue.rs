@@ -1,11 +1,13 @@
 //! Queue Handler
 
+use std::collections::HashMap;
 use std::sync::Arc;
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
-use crate::models::{QueueItem, QueueStatus, QueueStats};
+use crate::models::{Paginated, QueueFilter, QueueItem, QueueStatus};
 use crate::services::QueueService;
+use super::HandlerError;
 
 #[derive(Debug, Deserialize)]
 pub struct QueueListQuery {
@@ -13,6 +15,7 @@ pub struct QueueListQuery {
     pub limit: Option<usize>,
     pub offset: Option<usize>,
     pub search: Option<String>,
+    pub tag: Option<String>,
 }
 
 #[derive(Debug, Serialize)]
@@ -40,6 +43,10 @@ pub struct QueueStatsResponse {
     pub deferred: u64,
     pub success_rate: f64,
     pub throughput: f64,
+    /// Seconds the oldest undelivered item has been waiting
+    pub oldest_pending_age_secs: Option<i64>,
+    /// Estimated minutes to drain the backlog at the recent send rate
+    pub backlog_minutes: f64,
 }
 
 /// Queue handler
@@ -53,12 +60,19 @@ impl QueueHandler {
     }
 
     /// List queue items
-    pub async fn list(&self, query: QueueListQuery) -> Vec<QueueItemResponse> {
+    pub async fn list(&self, query: QueueListQuery) -> Paginated<QueueItemResponse> {
         let limit = query.limit.unwrap_or(50);
         let offset = query.offset.unwrap_or(0);
+        let filter = |filter: QueueFilter| QueueFilter {
+            limit: limit as u32,
+            offset: offset as u32,
+            ..filter
+        };
 
-        let items = if let Some(search) = query.search {
-            self.queue_service.search(&search, limit).await
+        let page = if let Some(search) = query.search {
+            self.queue_service.search_page(&search, limit, offset).await
+        } else if let Some(tag) = query.tag {
+            self.queue_service.query_page(filter(QueueFilter { tag: Some(tag), ..Default::default() })).await
         } else if let Some(status_str) = query.status {
             let status = match status_str.to_lowercase().as_str() {
                 "pending" => QueueStatus::Pending,
@@ -69,40 +83,45 @@ impl QueueHandler {
                 "cancelled" => QueueStatus::Cancelled,
                 _ => QueueStatus::Pending,
             };
-            self.queue_service.list_by_status(status, limit, offset).await
+            self.queue_service.query_page(filter(QueueFilter::with_status(status))).await
         } else {
-            self.queue_service.get_pending(limit).await
+            self.queue_service.pending_page(limit, offset).await
         };
 
-        items.into_iter().map(|i| Self::to_response(&i)).collect()
+        page.map(|i| Self::to_response(&i))
     }
 
     /// Get queue item
-    pub async fn get(&self, id: &str) -> Result<QueueItemResponse, String> {
-        let uuid = Uuid::parse_str(id).map_err(|e| e.to_string())?;
+    pub async fn get(&self, id: &str) -> Result<QueueItemResponse, HandlerError> {
+        let uuid = Uuid::parse_str(id)?;
 
         let item = self.queue_service.get(uuid).await
-            .ok_or_else(|| "Queue item not found".to_string())?;
+            .ok_or_else(|| HandlerError::NotFound(format!("Queue item {}", id)))?;
 
         Ok(Self::to_response(&item))
     }
 
     /// Cancel queue item
-    pub async fn cancel(&self, id: &str) -> Result<(), String> {
-        let uuid = Uuid::parse_str(id).map_err(|e| e.to_string())?;
-        self.queue_service.cancel(uuid).await.map_err(|e| e.to_string())
+    pub async fn cancel(&self, id: &str) -> Result<(), HandlerError> {
+        let uuid = Uuid::parse_str(id)?;
+        Ok(self.queue_service.cancel(uuid).await?)
     }
 
     /// Retry queue item
-    pub async fn retry(&self, id: &str) -> Result<(), String> {
-        let uuid = Uuid::parse_str(id).map_err(|e| e.to_string())?;
-        self.queue_service.retry(uuid).await.map_err(|e| e.to_string())
+    pub async fn retry(&self, id: &str) -> Result<(), HandlerError> {
+        let uuid = Uuid::parse_str(id)?;
+        Ok(self.queue_service.retry(uuid).await?)
+    }
+
+    /// Requeue all failed items
+    pub async fn retry_failed(&self) -> usize {
+        self.queue_service.retry_failed().await
     }
 
     /// Set priority
-    pub async fn set_priority(&self, id: &str, priority: i32) -> Result<(), String> {
-        let uuid = Uuid::parse_str(id).map_err(|e| e.to_string())?;
-        self.queue_service.set_priority(uuid, priority).await.map_err(|e| e.to_string())
+    pub async fn set_priority(&self, id: &str, priority: i32) -> Result<(), HandlerError> {
+        let uuid = Uuid::parse_str(id)?;
+        Ok(self.queue_service.set_priority(uuid, priority).await?)
     }
 
     /// Get queue statistics
@@ -117,9 +136,20 @@ impl QueueHandler {
             deferred: stats.deferred,
             success_rate: stats.success_rate,
             throughput: stats.throughput,
+            oldest_pending_age_secs: self.queue_service.oldest_pending_age().await
+                .map(|age| age.num_seconds()),
+            backlog_minutes: self.queue_service.backlog_pressure().await,
         }
     }
 
+    /// Get all-time item counts by status
+    pub async fn counts(&self) -> HashMap<String, u64> {
+        self.queue_service.counts_by_status().await
+            .into_iter()
+            .map(|(status, count)| (status.to_string(), count))
+            .collect()
+    }
+
     /// Get queue size
     pub async fn size(&self) -> usize {
         self.queue_service.size().await
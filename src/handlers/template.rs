@@ -4,12 +4,15 @@ use std::sync::Arc;
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
-use crate::models::{EmailTemplate, TemplateType, TemplateVariable, VariableType};
+use crate::models::{EmailTemplate, TemplateType, TemplateVariable, VariableReport, VariableType};
 use crate::services::TemplateService;
+use super::HandlerError;
 
 #[derive(Debug, Deserialize)]
 pub struct CreateTemplateRequest {
     pub name: String,
+    /// Explicit slug; derived from the name when omitted
+    pub slug: Option<String>,
     pub title: Option<String>,
     pub description: Option<String>,
     pub template_type: Option<String>,
@@ -18,6 +21,8 @@ pub struct CreateTemplateRequest {
     pub html_body: Option<String>,
     pub preheader: Option<String>,
     pub layout_id: Option<String>,
+    #[serde(default)]
+    pub use_layout: Option<bool>,
     pub variables: Option<Vec<VariableDefinition>>,
     pub default_from: Option<String>,
     pub default_reply_to: Option<String>,
@@ -109,7 +114,7 @@ impl TemplateHandler {
     }
 
     /// Create template
-    pub async fn create(&self, request: CreateTemplateRequest) -> Result<TemplateResponse, String> {
+    pub async fn create(&self, request: CreateTemplateRequest) -> Result<TemplateResponse, HandlerError> {
         let template_type = request.template_type
             .map(|t| match t.to_lowercase().as_str() {
                 "system" => TemplateType::System,
@@ -145,13 +150,19 @@ impl TemplateHandler {
             })
             .collect();
 
-        let layout_id = request.layout_id
-            .and_then(|s| Uuid::parse_str(&s).ok());
+        // Layouts may be referenced by ID or by slug
+        let (layout_id, layout_slug) = match request.layout_id {
+            Some(s) => match Uuid::parse_str(&s) {
+                Ok(id) => (Some(id), None),
+                Err(_) => (None, Some(s)),
+            },
+            None => (None, None),
+        };
 
         let template = EmailTemplate {
             id: Uuid::now_v7(),
             name: request.name.clone(),
-            slug: crate::models::template::slugify(&request.name),
+            slug: request.slug.unwrap_or_else(|| crate::models::template::slugify(&request.name)),
             title: request.title.unwrap_or(request.name),
             description: request.description,
             template_type,
@@ -160,10 +171,13 @@ impl TemplateHandler {
             html_body: request.html_body,
             preheader: request.preheader,
             layout_id,
+            use_layout: request.use_layout,
+            layout_slug,
             variables,
             default_from: request.default_from,
             default_reply_to: request.default_reply_to,
             tags: request.tags.unwrap_or_default(),
+            attachments: Vec::new(),
             active: true,
             version: 1,
             created_by: None,
@@ -171,26 +185,25 @@ impl TemplateHandler {
             updated_at: chrono::Utc::now(),
         };
 
-        self.template_service.register(template.clone()).await
-            .map_err(|e| e.to_string())?;
+        self.template_service.register(template.clone()).await?;
 
         Ok(Self::to_response(&template))
     }
 
     /// Get template by ID
-    pub async fn get(&self, id: &str) -> Result<TemplateDetailResponse, String> {
-        let uuid = Uuid::parse_str(id).map_err(|e| e.to_string())?;
+    pub async fn get(&self, id: &str) -> Result<TemplateDetailResponse, HandlerError> {
+        let uuid = Uuid::parse_str(id)?;
 
         let template = self.template_service.get(uuid).await
-            .ok_or_else(|| "Template not found".to_string())?;
+            .ok_or_else(|| HandlerError::NotFound(format!("Template {}", id)))?;
 
         Ok(Self::to_detail_response(&template))
     }
 
     /// Get template by slug
-    pub async fn get_by_slug(&self, slug: &str) -> Result<TemplateDetailResponse, String> {
+    pub async fn get_by_slug(&self, slug: &str) -> Result<TemplateDetailResponse, HandlerError> {
         let template = self.template_service.get_by_slug(slug).await
-            .ok_or_else(|| "Template not found".to_string())?;
+            .ok_or_else(|| HandlerError::NotFound(format!("Template {}", slug)))?;
 
         Ok(Self::to_detail_response(&template))
     }
@@ -204,17 +217,16 @@ impl TemplateHandler {
     }
 
     /// Delete template
-    pub async fn delete(&self, id: &str) -> Result<(), String> {
-        let uuid = Uuid::parse_str(id).map_err(|e| e.to_string())?;
-        self.template_service.delete(uuid).await.map_err(|e| e.to_string())
+    pub async fn delete(&self, id: &str) -> Result<(), HandlerError> {
+        let uuid = Uuid::parse_str(id)?;
+        Ok(self.template_service.delete(uuid).await?)
     }
 
     /// Preview template
-    pub async fn preview(&self, id: &str, request: PreviewRequest) -> Result<PreviewResponse, String> {
-        let uuid = Uuid::parse_str(id).map_err(|e| e.to_string())?;
+    pub async fn preview(&self, id: &str, request: PreviewRequest) -> Result<PreviewResponse, HandlerError> {
+        let uuid = Uuid::parse_str(id)?;
 
-        let rendered = self.template_service.render(uuid, &request.data).await
-            .map_err(|e| e.to_string())?;
+        let rendered = self.template_service.render(uuid, &request.data).await?;
 
         Ok(PreviewResponse {
             subject: rendered.subject,
@@ -224,15 +236,35 @@ impl TemplateHandler {
     }
 
     /// Extract variables from template
-    pub async fn extract_variables(&self, id: &str) -> Result<Vec<String>, String> {
-        let uuid = Uuid::parse_str(id).map_err(|e| e.to_string())?;
+    pub async fn extract_variables(&self, id: &str) -> Result<Vec<String>, HandlerError> {
+        let uuid = Uuid::parse_str(id)?;
 
         let template = self.template_service.get(uuid).await
-            .ok_or_else(|| "Template not found".to_string())?;
+            .ok_or_else(|| HandlerError::NotFound(format!("Template {}", id)))?;
 
         Ok(template.extract_variables())
     }
 
+    /// Full variable definitions of a template
+    pub async fn variables(&self, id: &str) -> Result<Vec<TemplateVariable>, HandlerError> {
+        let uuid = Uuid::parse_str(id)?;
+
+        let template = self.template_service.get(uuid).await
+            .ok_or_else(|| HandlerError::NotFound(format!("Template {}", id)))?;
+
+        Ok(template.variables)
+    }
+
+    /// Report variables used but not declared, and declared but not used
+    pub async fn variable_report(&self, id: &str) -> Result<VariableReport, HandlerError> {
+        let uuid = Uuid::parse_str(id)?;
+
+        let template = self.template_service.get(uuid).await
+            .ok_or_else(|| HandlerError::NotFound(format!("Template {}", id)))?;
+
+        Ok(template.reconcile_variables())
+    }
+
     fn to_response(template: &EmailTemplate) -> TemplateResponse {
         TemplateResponse {
             id: template.id.to_string(),
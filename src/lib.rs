@@ -66,31 +66,45 @@
 //!     println!("Sent: {}, Failed: {}", result.sent, result.failed);
 //! }
 //! ```
+//!
+//! ## Blocking API
+//!
+//! ```rust,ignore
+//! use rustmail::blocking::BlockingMailer;
+//!
+//! fn main() {
+//!     let mailer = BlockingMailer::new().unwrap();
+//!     let result = mailer.process_queue(100);
+//!     println!("Sent: {}", result.sent);
+//! }
+//! ```
 
 pub mod models;
 pub mod services;
 pub mod handlers;
 pub mod plugin;
+pub mod blocking;
 
 // Re-exports
 pub use models::{
-    Email, EmailAddress, EmailBuilder, EmailPriority, Attachment,
-    EmailTemplate, TemplateType, TemplateVariable, TemplateBuilder,
-    QueueItem, QueueStatus, QueueStats, RetryPolicy,
-    EmailLog, EmailEvent, LogFilter, LogStats,
-    BounceRecord, BounceType, ComplaintRecord,
+    Email, EmailAddress, EmailBuilder, EmailPriority, DsnRequest, Attachment, AttachmentSource, CalendarInvite, Disposition,
+    EmailTemplate, TemplateType, TemplateVariable, TemplateBuilder, TemplateAttachment, ImportMode, ImportReport,
+    QueueItem, QueueStatus, QueueStats, QueueEvent, QueueFilter, RetryPolicy, BackoffStrategy, ScheduleSpec,
+    RecipientState, RecipientStatus,
+    EmailLog, EmailEvent, UnknownEventError, LogFilter, LogStats, Redaction, TemplateUsage,
+    BounceRecord, BounceType, ComplaintRecord, Paginated,
 };
 
 pub use services::{
-    MailerService, TemplateService, QueueService, LogService,
-    SmtpTransport, SmtpConfig, TlsMode,
+    MailerService, TemplateService, QueueService, WorkerId, LogService,
+    SmtpTransport, SmtpConfig, TlsMode, TlsSettings, MinTlsVersion, MailTransport, BlobStore,
 };
 
 pub use handlers::{
-    EmailHandler, TemplateHandler, QueueHandler, LogHandler,
+    EmailHandler, TemplateHandler, QueueHandler, LogHandler, HandlerError,
 };
 
-pub use plugin::{RustMailPlugin, PluginInfo, plugin_info};
+pub use plugin::{RustMailPlugin, RustMailConfig, PluginInfo, plugin_info};
 
 /// Library version
 pub const VERSION: &str = env!("CARGO_PKG_VERSION");
@@ -175,7 +189,212 @@ mod tests {
         let att = Attachment::new("test.txt", "text/plain", vec![72, 101, 108, 108, 111]);
         assert_eq!(att.filename, "test.txt");
         assert_eq!(att.size(), 5);
-        assert!(!att.inline);
+        assert_eq!(att.disposition, Disposition::Attachment);
+    }
+
+    #[test]
+    fn test_attachment_content_sniffing() {
+        let png = vec![0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A, 0, 0, 0, 0x0D, b'I', b'H', b'D', b'R'];
+
+        let att = Attachment::new_sniffed("data", "application/octet-stream", png.clone());
+        assert_eq!(att.content_type, "image/png");
+
+        // Sniffing is opt-in and never overrides a specific declared type
+        let att = Attachment::new("data", "application/octet-stream", png.clone());
+        assert_eq!(att.content_type, "application/octet-stream");
+        let att = Attachment::new_sniffed("data", "text/plain", png);
+        assert_eq!(att.content_type, "text/plain");
+
+        let att = Attachment::new_sniffed("notes", "application/octet-stream", b"plain words".to_vec());
+        assert_eq!(att.content_type, "application/octet-stream");
+    }
+
+    /// Serve a single canned HTTP response on a local port
+    async fn serve_once(response: Vec<u8>) -> String {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut buf = [0u8; 1024];
+            let _ = socket.read(&mut buf).await;
+            socket.write_all(&response).await.unwrap();
+        });
+
+        format!("http://{}", addr)
+    }
+
+    #[tokio::test]
+    async fn test_attachment_from_url() {
+        let png: Vec<u8> = vec![0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A, 1, 2, 3, 4];
+
+        let mut response = format!(
+            "HTTP/1.1 200 OK\r\nContent-Type: image/png\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+            png.len()
+        ).into_bytes();
+        response.extend_from_slice(&png);
+        let base = serve_once(response).await;
+
+        let att = Attachment::from_url(&format!("{}/images/logo.png?v=2", base)).await.unwrap();
+        assert_eq!(att.filename, "logo.png");
+        assert_eq!(att.content_type, "image/png");
+        assert_eq!(att.bytes(), Some(png.as_slice()));
+
+        let mut response = format!(
+            "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+            png.len()
+        ).into_bytes();
+        response.extend_from_slice(&png);
+        let base = serve_once(response).await;
+
+        let err = Attachment::from_url_with_limit(&format!("{}/logo.png", base), 4).await.unwrap_err();
+        assert!(matches!(err, models::AttachmentError::TooLarge { limit: 4 }));
+    }
+
+    #[test]
+    fn test_file_attachment_streamed_at_send() {
+        use lettre::Transport;
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("report.txt");
+        std::fs::write(&path, "quarterly-report-contents").unwrap();
+
+        let att = Attachment::from_file(path.to_str().unwrap()).unwrap();
+        assert!(matches!(att.source, AttachmentSource::Path(_)));
+        assert!(att.bytes().is_none());
+        assert_eq!(att.size(), 25);
+
+        let email = EmailBuilder::new()
+            .from("sender@example.com")
+            .to("recipient@example.com")
+            .subject("Report")
+            .text("See attached")
+            .attach(att)
+            .build()
+            .unwrap();
+
+        let transport = SmtpTransport::new(SmtpConfig::default());
+        let message = transport.build_message(&email).unwrap();
+
+        let out_dir = tempfile::tempdir().unwrap();
+        let sender = lettre::FileTransport::new(out_dir.path());
+        let id = sender.send(&message).unwrap();
+
+        let written = std::fs::read_to_string(out_dir.path().join(format!("{}.eml", id))).unwrap();
+        assert!(written.contains("quarterly-report-contents"));
+        assert!(email.attachments[0].bytes().is_none());
+    }
+
+    #[test]
+    fn test_inline_images_related_nesting() {
+        let png = vec![0x89, 0x50, 0x4e, 0x47, 0x0d, 0x0a, 0x1a, 0x0a];
+        let email = EmailBuilder::new()
+            .from("sender@example.com")
+            .to("recipient@example.com")
+            .subject("Newsletter")
+            .text("Our news")
+            .html(r#"<img src="cid:logo@rustmail"><img src="cid:banner@rustmail">"#)
+            .attach(Attachment::inline("logo.png", "image/png", png.clone(), "logo@rustmail"))
+            .attach(Attachment::inline("banner.png", "image/png", png, "banner@rustmail"))
+            .attach(Attachment::new("issue.pdf", "application/pdf", b"%PDF-1.4".to_vec()))
+            .build()
+            .unwrap();
+
+        let transport = SmtpTransport::new(SmtpConfig::default());
+        let formatted = String::from_utf8(transport.build_message(&email).unwrap().formatted()).unwrap();
+
+        let content_types: Vec<&str> = formatted.lines()
+            .filter_map(|line| line.strip_prefix("Content-Type: "))
+            .map(|value| value.split(';').next().unwrap())
+            .collect();
+        assert_eq!(content_types, [
+            "multipart/mixed",
+            "multipart/related",
+            "multipart/alternative",
+            "text/plain",
+            "text/html",
+            "image/png",
+            "image/png",
+            "application/pdf",
+        ]);
+
+        // The related part closes before the PDF, which stays in the mixed layer
+        let boundary = |kind: &str| {
+            let start = formatted.find(&format!("Content-Type: {}", kind)).unwrap();
+            let rest = &formatted[start..];
+            let b = rest.find("boundary=\"").unwrap() + "boundary=\"".len();
+            rest[b..b + rest[b..].find('"').unwrap()].to_string()
+        };
+        let related_end = formatted.find(&format!("--{}--", boundary("multipart/related"))).unwrap();
+        assert!(related_end < formatted.find("application/pdf").unwrap());
+        assert!(formatted.contains("Content-ID: <logo@rustmail>"));
+        assert!(formatted.contains("Content-ID: <banner@rustmail>"));
+        assert!(formatted.contains("Content-Disposition: attachment; filename=\"issue.pdf\""));
+    }
+
+    #[tokio::test]
+    async fn test_header_injection_rejected() {
+        use crate::services::mailer::{MailerConfig, MailerError};
+
+        let evil_subject = "Hello\r\nBcc: evil@x.com";
+        let built = EmailBuilder::new()
+            .from("sender@example.com")
+            .to("recipient@example.com")
+            .subject(evil_subject)
+            .text("Body")
+            .build();
+        assert_eq!(built.unwrap_err(), "header injection detected");
+
+        let named = EmailBuilder::new()
+            .from("sender@example.com")
+            .to(EmailAddress::with_name("recipient@example.com", "Eve\nBcc: evil@x.com"))
+            .subject("Hi")
+            .text("Body")
+            .build();
+        assert!(named.is_err());
+
+        // Emails assembled field by field are caught at send and build time
+        let mut email = Email::new(
+            EmailAddress::new("sender@example.com"),
+            EmailAddress::new("recipient@example.com"),
+            evil_subject,
+        ).text("Body");
+
+        let transport = SmtpTransport::new(SmtpConfig::default());
+        assert!(transport.build_message(&email).is_err());
+
+        let mailer = MailerService::new();
+        mailer.set_transport(AcceptingTransport).await;
+        mailer.configure(MailerConfig { queue_by_default: false, ..Default::default() }).await;
+        let err = mailer.deliver(email.clone()).await.unwrap_err();
+        assert!(matches!(err, MailerError::Invalid(ref m) if m == "header injection detected"));
+        assert!(mailer.queue_email(email.clone()).await.is_err());
+
+        email.subject = "Hello".to_string();
+        assert!(mailer.deliver(email).await.is_ok());
+    }
+
+    #[test]
+    fn test_attachment_disposition_headers() {
+        let pdf = b"%PDF-1.4".to_vec();
+        let email = EmailBuilder::new()
+            .from("sender@example.com")
+            .to("recipient@example.com")
+            .subject("Invoice")
+            .text("Attached")
+            .attach(Attachment::new("invoice.pdf", "application/pdf", pdf.clone()))
+            .attach(Attachment::new("preview.pdf", "application/pdf", pdf).with_disposition(Disposition::Inline))
+            .build()
+            .unwrap();
+
+        let transport = SmtpTransport::new(SmtpConfig::default());
+        let formatted = String::from_utf8(transport.build_message(&email).unwrap().formatted()).unwrap();
+
+        assert!(formatted.contains("Content-Disposition: attachment; filename=\"invoice.pdf\""));
+        assert!(formatted.contains("Content-Disposition: inline; filename=\"preview.pdf\""));
+        assert!(!formatted.contains("multipart/related"));
     }
 
     #[test]
@@ -211,67 +430,3403 @@ mod tests {
     }
 
     #[tokio::test]
-    async fn test_queue_service() {
-        let service = QueueService::new();
+    async fn test_custom_helper() {
+        let service = TemplateService::new();
 
-        let email = EmailBuilder::new()
-            .from("test@example.com")
-            .to("recipient@example.com")
-            .subject("Test")
-            .text("Body")
+        service.register_helper(
+            "shout",
+            Box::new(|h: &handlebars::Helper,
+                      _: &handlebars::Handlebars,
+                      _: &handlebars::Context,
+                      _: &mut handlebars::RenderContext,
+                      out: &mut dyn handlebars::Output|
+             -> handlebars::HelperResult {
+                if let Some(s) = h.param(0).and_then(|p| p.value().as_str()) {
+                    out.write(&format!("{}!", s.to_uppercase()))?;
+                }
+                Ok(())
+            }),
+        ).await;
+
+        let template = TemplateBuilder::new()
+            .name("shouting")
+            .subject("Hey {{shout name}}")
+            .text("Hello")
             .build()
             .unwrap();
+        service.register(template).await.unwrap();
 
-        let item = service.enqueue(email).await.unwrap();
-        assert_eq!(item.status, QueueStatus::Pending);
+        let data = serde_json::json!({ "name": "jane" });
+        let rendered = service.render_by_slug("shouting", &data).await.unwrap();
+        assert_eq!(rendered.subject, "Hey JANE!");
+    }
 
-        let found = service.get(item.id).await;
-        assert!(found.is_some());
+    #[tokio::test]
+    async fn test_text_fallback_from_html() {
+        let service = TemplateService::new().with_text_fallback(true);
 
-        let pending = service.get_pending(10).await;
-        assert!(!pending.is_empty());
+        let template = TemplateBuilder::new()
+            .name("html-only")
+            .subject("Hello")
+            .html(r#"<html><head><style>p { color: red; }</style></head><body><h1>Hi {{name}}</h1><p>Visit <a href="https://example.com">our site</a>.</p></body></html>"#)
+            .build()
+            .unwrap();
+        service.register(template).await.unwrap();
+
+        let data = serde_json::json!({ "name": "Jane" });
+        let rendered = service.render_by_slug("html-only", &data).await.unwrap();
+        let text = rendered.text_body.unwrap();
+        assert!(text.contains("Hi Jane"));
+        assert!(text.contains("our site (https://example.com)"));
+        assert!(!text.contains("color: red"));
+        assert!(!text.contains('<'));
     }
 
     #[tokio::test]
-    async fn test_log_service() {
-        let service = LogService::new();
+    async fn test_line_item_table() {
+        let service = TemplateService::new();
 
-        let email_id = uuid::Uuid::now_v7();
-        service.log_sent(email_id, "test@example.com", "Test Subject", "smtp", None).await;
+        let template = TemplateBuilder::new()
+            .name("order-confirmation")
+            .subject("Order confirmed")
+            .html(concat!(
+                "<table>{{#each items}}<tr><td>{{name}}</td><td>{{quantity}}</td>",
+                "<td>{{currency (multiply price quantity)}}</td></tr>{{/each}}</table>",
+                "<p>Total: {{currency (sum items field=\"price\" qty=\"quantity\")}}</p>",
+            ))
+            .build()
+            .unwrap();
+        service.register(template).await.unwrap();
 
-        let logs = service.recent(10).await;
-        assert!(!logs.is_empty());
+        let data = serde_json::json!({
+            "items": [
+                { "name": "Widget", "price": 2.5, "quantity": 4 },
+                { "name": "Gadget", "price": 10.0, "quantity": 1 },
+                { "name": "Gizmo", "price": 0.75, "quantity": 2 },
+            ]
+        });
+        let html = service.render_by_slug("order-confirmation", &data).await.unwrap()
+            .html_body.unwrap();
 
-        let stats = service.stats(None, None).await;
-        assert!(stats.total_sent > 0);
+        assert!(html.contains("<tr><td>Widget</td><td>4</td><td>$10.00</td></tr>"));
+        assert!(html.contains("<tr><td>Gadget</td><td>1</td><td>$10.00</td></tr>"));
+        assert!(html.contains("<tr><td>Gizmo</td><td>2</td><td>$1.50</td></tr>"));
+        assert!(html.contains("Total: $21.50"));
     }
 
     #[tokio::test]
-    async fn test_suppression() {
-        let service = LogService::new();
+    async fn test_template_bundle_roundtrip() {
+        let service = TemplateService::new();
+        service.register_system_templates().await;
+        let slugs: Vec<String> = service.list().await.into_iter().map(|t| t.slug).collect();
+        assert!(!slugs.is_empty());
 
-        assert!(!service.is_suppressed("test@example.com").await);
+        let bundle = service.export_all().await;
+        service.clear().await;
+        assert!(service.list().await.is_empty());
 
-        service.add_to_suppression("test@example.com", crate::services::log::SuppressionReason::Manual).await;
-        assert!(service.is_suppressed("test@example.com").await);
+        let report = service.import_bundle(&bundle, ImportMode::Merge).await.unwrap();
+        assert_eq!(report.templates_imported, slugs.len());
+        assert_eq!(report.templates_skipped, 0);
 
-        service.remove_from_suppression("test@example.com").await;
-        assert!(!service.is_suppressed("test@example.com").await);
+        for slug in &slugs {
+            let template = service.get_by_slug(slug).await.unwrap();
+            let data: serde_json::Map<String, serde_json::Value> = template.variables.iter()
+                .map(|v| (v.name.clone(), serde_json::json!("value")))
+                .collect();
+            service.render_by_slug(slug, &serde_json::Value::Object(data)).await.unwrap();
+        }
+
+        // Merging again skips everything that already exists
+        let report = service.import_bundle(&bundle, ImportMode::Merge).await.unwrap();
+        assert_eq!(report.templates_imported, 0);
+        assert_eq!(report.templates_skipped, slugs.len());
+
+        let report = service.import_bundle(&bundle, ImportMode::Overwrite).await.unwrap();
+        assert_eq!(report.templates_imported, slugs.len());
+        assert_eq!(service.list().await.len(), slugs.len());
     }
 
-    #[test]
-    fn test_retry_policy() {
-        let policy = RetryPolicy::default();
+    #[tokio::test]
+    async fn test_render_error_context() {
+        let service = TemplateService::new();
 
-        assert_eq!(policy.max_attempts, 3);
-        assert!(policy.is_retryable("Connection timeout"));
-        assert!(!policy.is_retryable("Invalid recipient"));
+        let template = TemplateBuilder::new()
+            .name("welcome")
+            .subject("Welcome {{name}}")
+            .text("Hello {{name}}")
+            .html("<p>{{shout name}}</p>")
+            .build()
+            .unwrap();
+        service.register(template).await.unwrap();
 
-        let delay = policy.get_delay(0);
-        assert_eq!(delay.num_seconds(), 60);
+        let err = service.render_by_slug("welcome", &serde_json::json!({ "name": "Test" }))
+            .await
+            .unwrap_err();
+        let message = err.to_string();
+        assert!(message.starts_with("render error in 'welcome' (html): "), "{}", message);
+    }
 
-        let delay = policy.get_delay(1);
-        assert_eq!(delay.num_seconds(), 120);
+    #[tokio::test]
+    async fn test_sanitize_html_variables() {
+        let service = TemplateService::new().with_sanitize_html(true);
+
+        let template = TemplateBuilder::new()
+            .name("profile")
+            .subject("{{name}} updated their profile")
+            .html("<div>{{{bio}}}</div><p>{{name}}</p>")
+            .variable(TemplateVariable {
+                name: "bio".to_string(),
+                description: None,
+                default: None,
+                required: false,
+                example: None,
+                var_type: models::template::VariableType::Html,
+            })
+            .build()
+            .unwrap();
+        service.register(template).await.unwrap();
+
+        let data = serde_json::json!({
+            "name": "<b>Eve</b>",
+            "bio": "<p onclick=\"steal()\">Hi</p><script>alert(1)</script><em>there</em>",
+        });
+        let html = service.render_by_slug("profile", &data).await.unwrap().html_body.unwrap();
+
+        assert!(!html.contains("<script>"));
+        assert!(!html.contains("alert(1)"));
+        assert!(!html.contains("onclick"));
+        assert!(html.contains("<p>Hi</p>"));
+        assert!(html.contains("<em>there</em>"));
+        // Non-HTML variables are still escaped by Handlebars
+        assert!(html.contains("&lt;b&gt;Eve&lt;/b&gt;"));
+    }
+
+    #[tokio::test]
+    async fn test_render_limits() {
+        let service = TemplateService::new()
+            .with_max_output_bytes(10_000)
+            .with_max_iterations(5_000);
+
+        let template = TemplateBuilder::new()
+            .name("report")
+            .subject("Report")
+            .html("<ul>{{#each rows}}<li>{{this}}</li>{{/each}}</ul>")
+            .build()
+            .unwrap();
+        service.register(template).await.unwrap();
+
+        let rows: Vec<String> = (0..2_000).map(|i| format!("row number {}", i)).collect();
+        let err = service.render_by_slug("report", &serde_json::json!({ "rows": rows }))
+            .await
+            .unwrap_err();
+        assert!(matches!(err, services::template::TemplateError::RenderError(ref m) if m == "output limit exceeded"));
+
+        let rows = vec![0; 10_000];
+        let err = service.render_by_slug("report", &serde_json::json!({ "rows": rows }))
+            .await
+            .unwrap_err();
+        assert!(matches!(err, services::template::TemplateError::RenderError(ref m) if m == "iteration limit exceeded"));
+
+        // Within limits renders normally
+        let rendered = service.render_by_slug("report", &serde_json::json!({ "rows": ["a", "b"] }))
+            .await
+            .unwrap();
+        assert_eq!(rendered.html_body.unwrap(), "<ul><li>a</li><li>b</li></ul>");
+    }
+
+    #[tokio::test]
+    async fn test_preview_samples() {
+        use services::template::TemplateError;
+
+        let service = TemplateService::new();
+        let template = TemplateBuilder::new()
+            .name("Welcome")
+            .subject("Welcome, {{name}}!")
+            .text("Hi {{name}}")
+            .required_var("name", "Recipient name")
+            .build()
+            .unwrap();
+        service.register(template).await.unwrap();
+
+        let samples = std::collections::HashMap::from([
+            ("happy".to_string(), serde_json::json!({ "name": "Ada" })),
+            ("missing-var".to_string(), serde_json::json!({})),
+        ]);
+        let previews = service.preview_samples("welcome", samples).await.unwrap();
+
+        assert_eq!(previews.len(), 2);
+        assert_eq!(previews["happy"].as_ref().unwrap().subject, "Welcome, Ada!");
+        assert!(matches!(&previews["missing-var"], Err(TemplateError::MissingVariable(var)) if var == "name"));
+
+        assert!(matches!(
+            service.preview_samples("nope", Default::default()).await,
+            Err(TemplateError::NotFound(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_template_layout_opt_out() {
+        let service = TemplateService::new();
+
+        let mut layout = models::EmailLayout::new("Branded", "<div class=\"brand\">{{{content}}}</div>");
+        layout.is_default = true;
+        service.register_layout(layout).await;
+
+        let wrapped = TemplateBuilder::new()
+            .name("wrapped")
+            .subject("Wrapped")
+            .html("<p>Hello</p>")
+            .build()
+            .unwrap();
+        let standalone = TemplateBuilder::new()
+            .name("standalone")
+            .subject("Standalone")
+            .html("<html><body><p>Hello</p></body></html>")
+            .no_layout()
+            .build()
+            .unwrap();
+        service.register(wrapped).await.unwrap();
+        service.register(standalone).await.unwrap();
+
+        let data = serde_json::json!({});
+        let html = service.render_by_slug("wrapped", &data).await.unwrap().html_body.unwrap();
+        assert_eq!(html, "<div class=\"brand\"><p>Hello</p></div>");
+
+        let html = service.render_by_slug("standalone", &data).await.unwrap().html_body.unwrap();
+        assert_eq!(html, "<html><body><p>Hello</p></body></html>");
+    }
+
+    #[tokio::test]
+    async fn test_layouts_by_slug() {
+        use models::EmailLayoutBuilder;
+
+        let service = TemplateService::new();
+        for (name, html) in [
+            ("Transactional", "<main>{{{content}}}</main>"),
+            ("Newsletter", "<div class=\"news\">{{{content}}}</div>"),
+        ] {
+            let layout = EmailLayoutBuilder::new()
+                .name(name)
+                .html(html)
+                .description("Test layout")
+                .build()
+                .unwrap();
+            service.register_layout(layout).await;
+        }
+        assert!(EmailLayoutBuilder::new().name("Broken").html("<div></div>").build().is_err());
+
+        assert_eq!(service.list_layouts().await.len(), 2);
+        let newsletter = service.get_layout_by_slug("newsletter").await.unwrap();
+        assert_eq!(newsletter.name, "Newsletter");
+        assert!(service.get_layout_by_slug("missing").await.is_none());
+
+        let template = TemplateBuilder::new()
+            .name("digest")
+            .subject("Digest")
+            .html("<p>News</p>")
+            .layout_slug("newsletter")
+            .build()
+            .unwrap();
+        service.register(template).await.unwrap();
+
+        let html = service.render_by_slug("digest", &serde_json::json!({})).await.unwrap().html_body.unwrap();
+        assert_eq!(html, "<div class=\"news\"><p>News</p></div>");
+    }
+
+    #[tokio::test]
+    async fn test_handler_error_kinds() {
+        let service = std::sync::Arc::new(TemplateService::new());
+        service.register_system_templates().await;
+        let handler = TemplateHandler::new(std::sync::Arc::clone(&service));
+
+        let err = handler.get(&uuid::Uuid::now_v7().to_string()).await.unwrap_err();
+        assert!(matches!(err, HandlerError::NotFound(_)));
+        assert_eq!(err.status_code(), 404);
+
+        let err = handler.get("not-a-uuid").await.unwrap_err();
+        assert!(matches!(err, HandlerError::BadRequest(_)));
+        assert_eq!(err.status_code(), 400);
+
+        // A taken slug is a conflict rather than bad input
+        let welcome = service.get_by_slug("welcome").await.unwrap();
+        let err = handler.create(serde_json::from_value(serde_json::json!({
+            "name": "Welcome",
+            "subject": "Hi",
+            "text_body": "Hello",
+        })).unwrap()).await.unwrap_err();
+        assert!(matches!(err, HandlerError::Conflict(_)));
+        assert!(handler.get(&welcome.id.to_string()).await.is_ok());
+
+        let queue = QueueHandler::new(std::sync::Arc::new(QueueService::new()));
+        let err = queue.cancel(&uuid::Uuid::now_v7().to_string()).await.unwrap_err();
+        assert!(matches!(err, HandlerError::NotFound(_)));
+
+        let err = HandlerError::from(services::queue::QueueError::QueueFull);
+        assert_eq!(err.status_code(), 429);
+    }
+
+    #[tokio::test]
+    async fn test_date_helper_timezone() {
+        let service = TemplateService::new();
+        let template = TemplateBuilder::new()
+            .name("reminder")
+            .subject("Reminder")
+            .text(r#"{{date at "%Y-%m-%d %H:%M %z" "America/New_York"}}|{{date at "%H:%M" "+05:30"}}|{{date at "%H:%M"}}|{{date at "%H:%M" "Mars/Olympus"}}"#)
+            .build()
+            .unwrap();
+        service.register(template).await.unwrap();
+
+        let rendered = service.render_by_slug("reminder", &serde_json::json!({
+            "at": "2024-01-15T17:00:00+00:00",
+        })).await.unwrap();
+        assert_eq!(
+            rendered.text_body.unwrap(),
+            "2024-01-15 12:00 -0500|22:30|17:00|2024-01-15T17:00:00+00:00"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_template_missing_layout() {
+        use crate::services::template::TemplateError;
+
+        let template = TemplateBuilder::new()
+            .name("orphan")
+            .subject("Hi")
+            .html("<p>Body</p>")
+            .layout(uuid::Uuid::now_v7())
+            .build()
+            .unwrap();
+
+        let service = TemplateService::new();
+        service.register(template.clone()).await.unwrap();
+        let err = service.render_by_slug("orphan", &serde_json::json!({})).await.unwrap_err();
+        assert!(matches!(err, TemplateError::LayoutNotFound(_)));
+
+        // Lenient services render the body unwrapped
+        let service = TemplateService::new().with_strict_layouts(false);
+        service.register(template).await.unwrap();
+        let rendered = service.render_by_slug("orphan", &serde_json::json!({})).await.unwrap();
+        assert_eq!(rendered.html_body.as_deref(), Some("<p>Body</p>"));
+    }
+
+    #[tokio::test]
+    async fn test_template_variable_report() {
+        let service = std::sync::Arc::new(TemplateService::new());
+        let handler = TemplateHandler::new(std::sync::Arc::clone(&service));
+
+        let template = TemplateBuilder::new()
+            .name("order")
+            .subject("Order {{order_id}} for {{ user.name }}")
+            .html("{{#if shipped}}<p>On its way</p>{{else}}<p>Processing</p>{{/if}}{{{footer}}}")
+            .required_var("order_id", "Order number")
+            .required_var("user", "Customer")
+            .required_var("coupon", "Discount code")
+            .build()
+            .unwrap();
+        let id = template.id.to_string();
+        service.register(template).await.unwrap();
+
+        let variables = handler.variables(&id).await.unwrap();
+        assert_eq!(variables.len(), 3);
+        assert_eq!(variables[0].description.as_deref(), Some("Order number"));
+        assert!(variables[0].required);
+
+        let report = handler.variable_report(&id).await.unwrap();
+        assert_eq!(report.used, ["order_id", "user", "shipped", "footer"]);
+        assert_eq!(report.undeclared, ["shipped", "footer"]);
+        assert_eq!(report.unused, ["coupon"]);
+
+        // Helper arguments, subexpressions and block parameters
+        let template = TemplateBuilder::new()
+            .name("receipt")
+            .subject("Receipt for {{uppercase name}}")
+            .text(r#"{{date created_at "%Y"}} {{currency (sum subtotal shipping) code=currency_code}}
+{{#each lines as |line|}}{{line.total}} {{@index}} {{../name}}{{/each}}"#)
+            .build()
+            .unwrap();
+        assert_eq!(
+            template.extract_variables(),
+            ["name", "created_at", "subtotal", "shipping", "currency_code", "lines"]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_template_duplicate() {
+        let service = TemplateService::new();
+        service.register_system_templates().await;
+
+        let original = service.get_by_slug("welcome").await.unwrap();
+        let copy = service.duplicate(original.id, "Welcome Copy").await.unwrap();
+
+        assert_ne!(copy.id, original.id);
+        assert_eq!(copy.slug, "welcome-copy");
+        assert_eq!(copy.version, 1);
+        assert_eq!(copy.text_body, original.text_body);
+        assert_eq!(copy.html_body, original.html_body);
+        assert!(service.get_by_slug("welcome-copy").await.is_some());
+
+        // Duplicating onto an existing slug fails
+        assert!(service.duplicate(original.id, "welcome").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_template_explicit_slug() {
+        let service = TemplateService::new();
+        let welcome = |slug: &str| TemplateBuilder::new()
+            .name("Welcome")
+            .slug(slug)
+            .subject("Welcome!")
+            .text("Hello")
+            .build()
+            .unwrap();
+
+        service.register(welcome("welcome-customers")).await.unwrap();
+        service.register(welcome("welcome-staff")).await.unwrap();
+
+        let customers = service.get_by_slug("welcome-customers").await.unwrap();
+        let staff = service.get_by_slug("welcome-staff").await.unwrap();
+        assert_ne!(customers.id, staff.id);
+        assert_eq!(customers.name, staff.name);
+
+        // Slugs must be unique and slug-shaped
+        assert!(service.register(welcome("welcome-staff")).await.is_err());
+        assert!(service.register(welcome("Welcome Staff")).await.is_err());
+        assert_eq!(service.list().await.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_bulk_template_defaults() {
+        let mailer = MailerService::new();
+        mailer.configure(crate::services::mailer::MailerConfig {
+            default_from: Some(EmailAddress::new("noreply@example.com")),
+            ..Default::default()
+        }).await;
+        mailer.initialize().await;
+
+        let results = mailer.send_template_bulk_with_defaults(
+            "welcome",
+            serde_json::json!({ "site_name": "RustPress", "user_name": "Friend" }),
+            vec![(EmailAddress::new("jane@example.com"), serde_json::json!({ "user_name": "Jane" }))],
+        ).await;
+        assert!(results.iter().all(|r| r.is_ok()));
+
+        let item = mailer.queue().get_pending(1).await.pop().unwrap();
+        let text = item.email.text_body.unwrap();
+        assert!(text.contains("Welcome Jane!"));
+        assert!(text.contains("Thanks for joining RustPress"));
+    }
+
+    #[tokio::test]
+    async fn test_marketing_compliance() {
+        let service = TemplateService::new().with_marketing_compliance(true);
+
+        let marketing = TemplateBuilder::new()
+            .name("spring-sale")
+            .template_type(TemplateType::Marketing)
+            .subject("Spring Sale")
+            .html("<p>Everything 20% off!</p><p>{{physical_address}}</p>")
+            .build()
+            .unwrap();
+        assert!(service.register(marketing).await.is_err());
+
+        let compliant = TemplateBuilder::new()
+            .name("summer-sale")
+            .template_type(TemplateType::Marketing)
+            .subject("Summer Sale")
+            .html(r#"<p>Everything 30% off!</p><p>{{physical_address}}</p><a href="{{unsubscribe_url}}">Unsubscribe</a>"#)
+            .build()
+            .unwrap();
+        assert!(service.register(compliant).await.is_ok());
+
+        let transactional = TemplateBuilder::new()
+            .name("receipt")
+            .template_type(TemplateType::Transactional)
+            .subject("Your receipt")
+            .html("<p>Thanks for your order</p>")
+            .build()
+            .unwrap();
+        assert!(service.register(transactional).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_duplicate_recipients() {
+        let mailer = MailerService::new();
+
+        let email = EmailBuilder::new()
+            .from("test@example.com")
+            .to("dup@example.com")
+            .to("other@example.com")
+            .cc("Other@Example.com")
+            .bcc("DUP@example.com")
+            .bcc("hidden@example.com")
+            .subject("Test")
+            .text("Body")
+            .build()
+            .unwrap();
+        mailer.deliver(email).await.unwrap();
+
+        let queued = mailer.queue().get_pending(1).await.remove(0).email;
+        let emails = |list: &[EmailAddress]| list.iter().map(|a| a.email.clone()).collect::<Vec<_>>();
+        assert_eq!(emails(&queued.to), vec!["dup@example.com", "other@example.com"]);
+        assert!(queued.cc.is_empty());
+        assert_eq!(emails(&queued.bcc), vec!["hidden@example.com"]);
+
+        let logs = mailer.logs().get_for_recipient("dup@example.com").await;
+        assert_eq!(logs.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_attachment_limits() {
+        let mailer = MailerService::new();
+        mailer.configure(crate::services::mailer::MailerConfig {
+            max_attachments: 2,
+            ..Default::default()
+        }).await;
+
+        let build = |attachments: Vec<Attachment>| {
+            let mut builder = EmailBuilder::new()
+                .from("test@example.com")
+                .to("recipient@example.com")
+                .subject("Test")
+                .text("Body");
+            for att in attachments {
+                builder = builder.attach(att);
+            }
+            builder.build().unwrap()
+        };
+
+        let too_many = build((0..3)
+            .map(|i| Attachment::new(&format!("file{}.txt", i), "text/plain", b"data".to_vec()))
+            .collect());
+        let err = mailer.deliver(too_many).await.unwrap_err();
+        assert!(matches!(err, services::mailer::MailerError::Invalid(_)));
+
+        let unnamed = build(vec![Attachment::new("", "text/plain", b"data".to_vec())]);
+        let err = mailer.deliver(unnamed).await.unwrap_err();
+        assert!(matches!(err, services::mailer::MailerError::Invalid(_)));
+
+        let bad_type = build(vec![Attachment::new("file.txt", "not a type", b"data".to_vec())]);
+        let err = mailer.deliver(bad_type).await.unwrap_err();
+        assert!(matches!(err, services::mailer::MailerError::Invalid(_)));
+
+        let ok = build(vec![Attachment::new("file.txt", "text/plain", b"data".to_vec())]);
+        mailer.deliver(ok).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_allowed_from_domains() {
+        let mailer = MailerService::new();
+        mailer.configure(crate::services::mailer::MailerConfig {
+            allowed_from_domains: Some(vec!["example.com".to_string()]),
+            ..Default::default()
+        }).await;
+
+        let from = |address: &str| EmailBuilder::new()
+            .from(address)
+            .to("recipient@example.org")
+            .subject("Test")
+            .text("Body")
+            .build()
+            .unwrap();
+
+        mailer.deliver(from("news@example.com")).await.unwrap();
+        mailer.deliver(from("news@EXAMPLE.com")).await.unwrap();
+
+        for address in ["news@spoofed.net", "news@mail.example.com", "news@notexample.com"] {
+            let err = mailer.deliver(from(address)).await.unwrap_err();
+            assert!(matches!(err, services::mailer::MailerError::Invalid(ref msg) if msg == "sender domain not allowed"));
+        }
+
+        // Sending directly is checked too, before the transport is needed
+        let err = mailer.send(from("news@spoofed.net")).await.unwrap_err();
+        assert!(matches!(err, services::mailer::MailerError::Invalid(_)));
+    }
+
+    #[tokio::test]
+    async fn test_tracking_base_url() {
+        let mailer = MailerService::new();
+        mailer.configure(crate::services::mailer::MailerConfig {
+            site_url: "https://www.example.com".to_string(),
+            tracking_base_url: Some("https://t.example.com/".to_string()),
+            track_opens: true,
+            track_clicks: true,
+            ..Default::default()
+        }).await;
+
+        let email = EmailBuilder::new()
+            .from("test@example.com")
+            .to("recipient@example.com")
+            .subject("Test")
+            .html(r#"<html><body><a href="https://example.com/offer?a=1">Offer</a></body></html>"#)
+            .build()
+            .unwrap();
+        let email_id = email.id;
+        mailer.deliver(email).await.unwrap();
+
+        let queued = mailer.queue().get_pending(1).await.remove(0);
+        let html = queued.email.html_body.unwrap();
+        let pixel = format!(r#"<img src="https://t.example.com/rustmail/track/open/{}""#, email_id);
+        assert!(html.contains(&pixel), "{}", html);
+        assert!(html.contains(&format!(
+            r#"href="https://t.example.com/rustmail/track/click/{}?url=https%3A%2F%2Fexample.com%2Foffer%3Fa%3D1""#,
+            email_id
+        )));
+        assert!(!html.contains("www.example.com"));
+        assert!(html.ends_with("</body></html>"));
+    }
+
+    #[test]
+    fn test_rewrite_links_only_anchors() {
+        let id = uuid::Uuid::now_v7();
+        let html = concat!(
+            r#"<head><base href="https://example.com/"><link rel="stylesheet" href="https://example.com/s.css"></head>"#,
+            r#"<body><A class="btn" HREF="https://example.com/offer">Offer</A><area href="https://example.com/map"></body>"#,
+        );
+
+        let rewritten = services::tracking::rewrite_links(html, "https://t.example.com", id);
+        assert!(rewritten.contains(r#"<base href="https://example.com/">"#));
+        assert!(rewritten.contains(r#"<link rel="stylesheet" href="https://example.com/s.css">"#));
+        assert!(rewritten.contains(r#"<area href="https://example.com/map">"#));
+        assert!(rewritten.contains(&format!(
+            r#"<A class="btn" HREF="{}">"#,
+            services::tracking::click_url("https://t.example.com", id, "https://example.com/offer")
+        )));
+    }
+
+    #[test]
+    fn test_open_pixel_non_ascii() {
+        let id = uuid::Uuid::now_v7();
+        let pixel = format!(r#"<img src="https://t.example.com/rustmail/track/open/{}" width="1" height="1" alt="" style="display:none">"#, id);
+
+        let html = services::tracking::inject_open_pixel("<html><BODY>İstanbul</BODY></html>", "https://t.example.com", id);
+        assert_eq!(html, format!("<html><BODY>İstanbul{}</BODY></html>", pixel));
+
+        // Characters whose lowercase form is longer must not shift the slice
+        let html = services::tracking::inject_open_pixel("İİİ</body>", "https://t.example.com", id);
+        assert_eq!(html, format!("İİİ{}</body>", pixel));
+    }
+
+    #[tokio::test]
+    async fn test_sending_identity_pool() {
+        use crate::services::mailer::{SendingIdentityPool, SENDING_IDENTITY_META};
+
+        let mailer = MailerService::new();
+        mailer.set_identity_pool(
+            SendingIdentityPool::new()
+                .with_identity(EmailAddress::new("a@example.com"), 3)
+                .with_identity(EmailAddress::new("b@example.com"), 1),
+        ).await;
+
+        for i in 0..1000 {
+            mailer.quick_send(&format!("user{}@example.com", i), "Hi", "Hello").await.unwrap();
+        }
+
+        let items = mailer.queue().get_pending(1000).await;
+        let from_a = items.iter().filter(|i| i.email.from.email == "a@example.com").count();
+        let from_b = items.iter().filter(|i| i.email.from.email == "b@example.com").count();
+        assert_eq!(from_a + from_b, 1000);
+        assert!((700..=800).contains(&from_a));
+
+        let logs = mailer.logs().recent(1).await;
+        assert!(logs[0].metadata.get(SENDING_IDENTITY_META).is_some());
+    }
+
+    #[tokio::test]
+    async fn test_builder_with_identity_pool() {
+        use crate::services::mailer::{SendingIdentityPool, SENDING_IDENTITY_META};
+
+        let mailer = MailerService::new();
+        mailer.set_transport(AcceptingTransport).await;
+        mailer.set_identity_pool(
+            SendingIdentityPool::new().with_identity(EmailAddress::new("a@example.com"), 1),
+        ).await;
+
+        let email = mailer.builder().await
+            .to("user@example.com")
+            .subject("Hi")
+            .text("Hello")
+            .build()
+            .unwrap();
+        mailer.send(email).await.unwrap();
+        let logs = mailer.logs().get_for_recipient("user@example.com").await;
+        let sent = logs.iter().find(|log| log.event == EmailEvent::Sent).unwrap();
+        assert_eq!(sent.metadata[SENDING_IDENTITY_META], "a@example.com");
+
+        let email = mailer.builder().await
+            .to("user@example.com")
+            .subject("Hi")
+            .text("Hello")
+            .build()
+            .unwrap();
+        let item = mailer.queue_email(email).await.unwrap();
+        assert_eq!(item.email.from.email, "a@example.com");
+        assert_eq!(mailer.process_queue(10).await.sent, 1);
+    }
+
+    #[tokio::test]
+    async fn test_send_template_at() {
+        use chrono::TimeZone;
+
+        let mailer = MailerService::new();
+        mailer.configure(crate::services::mailer::MailerConfig {
+            default_from: Some(EmailAddress::new("noreply@example.com")),
+            ..Default::default()
+        }).await;
+        mailer.initialize().await;
+
+        let data = serde_json::json!({ "user_name": "Jane", "site_name": "RustPress" });
+
+        let item = mailer.send_template_at(
+            "welcome",
+            EmailAddress::new("jane@example.com"),
+            data.clone(),
+            ScheduleSpec::In(chrono::Duration::hours(2)),
+        ).await.unwrap();
+        let ahead = item.scheduled_at - chrono::Utc::now();
+        assert!(ahead > chrono::Duration::minutes(119) && ahead <= chrono::Duration::hours(2));
+        assert!(mailer.queue().get_pending(10).await.is_empty());
+
+        let local = chrono_tz::America::New_York.with_ymd_and_hms(2030, 1, 15, 9, 0, 0).unwrap();
+        let item = mailer.send_template_at(
+            "welcome",
+            EmailAddress::new("jane@example.com"),
+            data,
+            ScheduleSpec::At(local),
+        ).await.unwrap();
+        assert_eq!(item.scheduled_at, chrono::Utc.with_ymd_and_hms(2030, 1, 15, 14, 0, 0).unwrap());
+
+        let next = ScheduleSpec::NextTimeOfDay { hour: 9, tz: chrono_tz::Europe::Berlin }
+            .resolve(chrono::Utc.with_ymd_and_hms(2030, 6, 1, 12, 0, 0).unwrap())
+            .unwrap();
+        assert_eq!(next, chrono::Utc.with_ymd_and_hms(2030, 6, 2, 7, 0, 0).unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_queue_service() {
+        let service = QueueService::new();
+
+        let email = EmailBuilder::new()
+            .from("test@example.com")
+            .to("recipient@example.com")
+            .subject("Test")
+            .text("Body")
+            .build()
+            .unwrap();
+
+        let item = service.enqueue(email).await.unwrap();
+        assert_eq!(item.status, QueueStatus::Pending);
+
+        let found = service.get(item.id).await;
+        assert!(found.is_some());
+
+        let pending = service.get_pending(10).await;
+        assert!(!pending.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_queue_oldest_pending_age() {
+        let service = std::sync::Arc::new(QueueService::new());
+        assert!(service.oldest_pending_age().await.is_none());
+
+        let mut items = Vec::new();
+        for subject in ["first", "second", "third"] {
+            let email = EmailBuilder::new()
+                .from("test@example.com")
+                .to("recipient@example.com")
+                .subject(subject)
+                .text("Body")
+                .build()
+                .unwrap();
+            items.push(service.enqueue(email).await.unwrap());
+            tokio::time::sleep(std::time::Duration::from_millis(5)).await;
+        }
+
+        let now = items[0].created_at + chrono::Duration::minutes(10);
+        assert_eq!(service.oldest_pending_age_at(now).await, Some(chrono::Duration::minutes(10)));
+
+        // Once delivered, the next oldest sets the age
+        service.mark_sent(items[0].id).await.unwrap();
+        assert_eq!(service.oldest_pending_age_at(now).await, Some(now - items[1].created_at));
+
+        let handler = QueueHandler::new(std::sync::Arc::clone(&service));
+        assert_eq!(handler.stats().await.oldest_pending_age_secs, Some(0));
+    }
+
+    #[tokio::test]
+    async fn test_handler_pagination() {
+        use crate::handlers::{log::LogQuery, queue::QueueListQuery};
+
+        let queue = std::sync::Arc::new(QueueService::new());
+        for n in 0..5 {
+            let email = EmailBuilder::new()
+                .from("test@example.com")
+                .to("recipient@example.com")
+                .subject(&format!("Update {}", n))
+                .text("Body")
+                .build()
+                .unwrap();
+            queue.enqueue(email).await.unwrap();
+        }
+        let handler = QueueHandler::new(std::sync::Arc::clone(&queue));
+
+        let query: QueueListQuery = serde_json::from_value(serde_json::json!({"status": "pending", "limit": 2, "offset": 2})).unwrap();
+        let page = handler.list(query).await;
+        assert_eq!(page.items.len(), 2);
+        assert_eq!((page.total, page.limit, page.offset), (5, 2, 2));
+
+        let query: QueueListQuery = serde_json::from_value(serde_json::json!({"search": "update", "limit": 3, "offset": 4})).unwrap();
+        let page = handler.list(query).await;
+        assert_eq!((page.items.len(), page.total), (1, 5));
+
+        let logs = std::sync::Arc::new(LogService::new());
+        for n in 0..4 {
+            logs.log_sent(uuid::Uuid::now_v7(), &format!("user{}@example.com", n), "Hello", "smtp", None).await;
+        }
+        logs.log_failed(uuid::Uuid::now_v7(), "user9@example.com", "Hello", "timeout").await;
+
+        let query: LogQuery = serde_json::from_value(serde_json::json!({"event": "sent", "limit": 3})).unwrap();
+        let page = LogHandler::new(logs).query(query).await;
+        assert_eq!(page.items.len(), 3);
+        assert_eq!(page.total, 4);
+        assert!(page.items.iter().all(|entry| entry.event == "sent"));
+    }
+
+    #[tokio::test]
+    async fn test_queue_cleanup_with_archive() {
+        let service = QueueService::new();
+        let email = |subject: &str| EmailBuilder::new()
+            .from("test@example.com")
+            .to("recipient@example.com")
+            .subject(subject)
+            .text("Body")
+            .attach(Attachment::new("report.pdf", "application/pdf", b"%PDF-1.4".to_vec()))
+            .build()
+            .unwrap();
+
+        let sent = service.enqueue(email("sent")).await.unwrap();
+        let cancelled = service.enqueue(email("cancelled")).await.unwrap();
+        let pending = service.enqueue(email("pending")).await.unwrap();
+        service.mark_sent(sent.id).await.unwrap();
+        service.cancel(cancelled.id).await.unwrap();
+
+        let archived = std::sync::Mutex::new(Vec::new());
+        let count = service
+            .cleanup_with_archive(chrono::Duration::zero(), |item| archived.lock().unwrap().push(item))
+            .await;
+
+        let archived = archived.into_inner().unwrap();
+        assert_eq!(count, 2);
+        let mut ids: Vec<_> = archived.iter().map(|i| i.id).collect();
+        ids.sort();
+        let mut expected = vec![sent.id, cancelled.id];
+        expected.sort();
+        assert_eq!(ids, expected);
+
+        // Archived items carry their attachment content
+        assert!(archived.iter().all(|i| i.email.attachments[0].bytes() == Some(&b"%PDF-1.4"[..])));
+
+        assert!(service.get(sent.id).await.is_none());
+        assert!(service.get(pending.id).await.is_some());
+    }
+
+    #[tokio::test]
+    async fn test_attachment_deduplication() {
+        let service = QueueService::new();
+        let pdf = vec![0x25, 0x50, 0x44, 0x46, 0x2d, 0x31, 0x2e, 0x34];
+
+        for recipient in ["a@example.com", "b@example.com"] {
+            let email = EmailBuilder::new()
+                .from("news@example.com")
+                .to(recipient)
+                .subject("Newsletter")
+                .text("See attached")
+                .attach(Attachment::new("issue.pdf", "application/pdf", pdf.clone()))
+                .build()
+                .unwrap();
+
+            let item = service.enqueue(email).await.unwrap();
+            let att = &item.email.attachments[0];
+            assert!(att.is_stored());
+            assert_eq!(att.size(), pdf.len());
+        }
+
+        assert_eq!(service.blobs().len().await, 1);
+        assert_eq!(service.blobs().total_size().await, pdf.len());
+    }
+
+    #[tokio::test]
+    async fn test_enqueue_batch_atomic() {
+        let service = QueueService::new();
+
+        let email = |to: &str| {
+            let mut email = EmailBuilder::new()
+                .from("test@example.com")
+                .to("placeholder@example.com")
+                .subject("Test")
+                .text("Body")
+                .build()
+                .unwrap();
+            email.to = vec![EmailAddress::new(to)];
+            email
+        };
+
+        let request = |emails| models::BatchSendRequest {
+            emails,
+            scheduled_at: None,
+            priority: Some(5),
+            tags: vec!["batch".to_string()],
+            max_attempts: None,
+        };
+
+        let err = service.enqueue_batch_atomic(request(vec![
+            email("one@example.com"),
+            email("not-an-address"),
+            email("three@example.com"),
+        ])).await.unwrap_err();
+        assert!(matches!(err, services::queue::QueueError::Invalid(ref m) if m.starts_with("Email 1:")));
+        assert_eq!(service.size().await, 0);
+
+        let result = service.enqueue_batch_atomic(request(vec![
+            email("one@example.com"),
+            email("two@example.com"),
+        ])).await.unwrap();
+        assert_eq!(result.queued, 2);
+        assert_eq!(service.size().await, 2);
+
+        let item = service.get(result.queue_ids[0]).await.unwrap();
+        assert_eq!(item.priority, 5);
+        assert_eq!(item.email.tags, vec!["batch".to_string()]);
+    }
+
+    #[tokio::test]
+    async fn test_queue_counts_by_status() {
+        let service = QueueService::new();
+
+        let mut ids = Vec::new();
+        for _ in 0..7 {
+            let email = EmailBuilder::new()
+                .from("test@example.com")
+                .to("recipient@example.com")
+                .subject("Test")
+                .text("Body")
+                .build()
+                .unwrap();
+            ids.push(service.enqueue(email).await.unwrap().id);
+        }
+
+        // ids[0], ids[1] stay pending
+        service.claim(ids[2], "worker").await.unwrap();
+        service.claim(ids[3], "worker").await.unwrap();
+        service.mark_sent(ids[3]).await.unwrap();
+        service.claim(ids[4], "worker").await.unwrap();
+        service.mark_failed(ids[4], "Connection timeout").await.unwrap();
+        for _ in 0..3 {
+            service.claim(ids[5], "worker").await.unwrap();
+            service.mark_failed(ids[5], "Connection timeout").await.unwrap();
+        }
+        service.cancel(ids[6]).await.unwrap();
+
+        let counts = service.counts_by_status().await;
+        assert_eq!(counts.get(&QueueStatus::Pending), Some(&2));
+        assert_eq!(counts.get(&QueueStatus::Processing), Some(&1));
+        assert_eq!(counts.get(&QueueStatus::Sent), Some(&1));
+        assert_eq!(counts.get(&QueueStatus::Deferred), Some(&1));
+        assert_eq!(counts.get(&QueueStatus::Failed), Some(&1));
+        assert_eq!(counts.get(&QueueStatus::Cancelled), Some(&1));
+    }
+
+    #[tokio::test]
+    async fn test_queue_retry_failed_bulk() {
+        let service = QueueService::new();
+
+        let mut ids = Vec::new();
+        for _ in 0..4 {
+            let email = EmailBuilder::new()
+                .from("test@example.com")
+                .to("recipient@example.com")
+                .subject("Test")
+                .text("Body")
+                .build()
+                .unwrap();
+            ids.push(service.enqueue(email).await.unwrap().id);
+        }
+
+        for id in &ids[..3] {
+            for _ in 0..3 {
+                service.claim(*id, "worker").await.unwrap();
+                service.mark_failed(*id, "Connection timeout").await.unwrap();
+            }
+            assert_eq!(service.get(*id).await.unwrap().status, QueueStatus::Failed);
+        }
+
+        assert_eq!(service.retry_failed().await, 3);
+        for id in &ids[..3] {
+            let item = service.get(*id).await.unwrap();
+            assert_eq!(item.status, QueueStatus::Pending);
+            assert_eq!(item.attempts, 0);
+            assert!(item.last_error.is_none());
+        }
+        assert_eq!(service.retry_failed().await, 0);
+
+        assert_eq!(service.cancel_by_status(QueueStatus::Pending).await, 4);
+        assert_eq!(service.get(ids[3]).await.unwrap().status, QueueStatus::Cancelled);
+    }
+
+    #[tokio::test]
+    async fn test_queue_custom_priority_map() {
+        fn spread(priority: EmailPriority) -> i32 {
+            match priority {
+                EmailPriority::Urgent => 1000,
+                other => other.queue_priority(),
+            }
+        }
+
+        let service = QueueService::new().with_priority_map(spread);
+
+        let mut ids = Vec::new();
+        for priority in [EmailPriority::Low, EmailPriority::Normal, EmailPriority::Urgent] {
+            let email = EmailBuilder::new()
+                .from("test@example.com")
+                .to("recipient@example.com")
+                .subject("Test")
+                .text("Body")
+                .priority(priority)
+                .build()
+                .unwrap();
+            ids.push(service.enqueue(email).await.unwrap().id);
+        }
+
+        assert_eq!(service.get(ids[0]).await.unwrap().priority, -1);
+        assert_eq!(service.get(ids[1]).await.unwrap().priority, 0);
+        assert_eq!(service.get(ids[2]).await.unwrap().priority, 1000);
+
+        // Urgent mail is handed out first
+        let next = service.get_pending(1).await;
+        assert_eq!(next[0].id, ids[2]);
+    }
+
+    #[tokio::test]
+    async fn test_queue_dedupe_window() {
+        use crate::services::queue::QueueError;
+
+        let service = QueueService::new().with_dedupe_window(chrono::Duration::seconds(30));
+        let email = || EmailBuilder::new()
+            .from("test@example.com")
+            .to("recipient@example.com")
+            .subject("Password reset")
+            .text("Body")
+            .build()
+            .unwrap();
+
+        let first = service.enqueue(email()).await.unwrap();
+        let second = service.enqueue(email()).await;
+        assert!(matches!(second, Err(QueueError::Duplicate(id)) if id == first.id));
+        assert_eq!(service.stats().await.pending, 1);
+
+        // Different content is not a duplicate
+        let mut other = email();
+        other.text_body = Some("Another body".to_string());
+        assert!(service.enqueue(other).await.is_ok());
+
+        // Same body with a different attachment is not a duplicate either,
+        // though resending the same attachment is
+        let invoice = |pdf: &[u8]| EmailBuilder::new()
+            .from("billing@example.com")
+            .to("recipient@example.com")
+            .subject("Your invoice")
+            .text("Attached.")
+            .attach(Attachment::new("invoice.pdf", "application/pdf", pdf.to_vec()))
+            .build()
+            .unwrap();
+        service.enqueue(invoice(b"%PDF-1.4 march")).await.unwrap();
+        assert!(service.enqueue(invoice(b"%PDF-1.4 april")).await.is_ok());
+        assert!(matches!(service.enqueue(invoice(b"%PDF-1.4 april")).await, Err(QueueError::Duplicate(_))));
+
+        // Without a window, identical emails are queued as before
+        let plain = QueueService::new();
+        plain.enqueue(email()).await.unwrap();
+        assert!(plain.enqueue(email()).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_queue_retry_at() {
+        let service = QueueService::new();
+        let email = EmailBuilder::new()
+            .from("test@example.com")
+            .to("recipient@example.com")
+            .subject("Test")
+            .text("Body")
+            .build()
+            .unwrap();
+        let id = service.enqueue(email).await.unwrap().id;
+        for _ in 0..3 {
+            service.claim(id, "worker").await.unwrap();
+            service.mark_failed(id, "Connection timeout").await.unwrap();
+        }
+
+        let later = chrono::Utc::now() + chrono::Duration::hours(2);
+        service.retry_at(id, Some(later)).await.unwrap();
+
+        let item = service.get(id).await.unwrap();
+        assert_eq!(item.status, QueueStatus::Pending);
+        assert_eq!(item.scheduled_at, later);
+        assert!(!item.is_ready());
+        assert!(service.get_pending(10).await.is_empty());
+
+        // Only failed or cancelled items can be retried
+        assert!(service.retry_at(id, None).await.is_err());
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_queue_concurrent_claim() {
+        let service = std::sync::Arc::new(QueueService::new());
+        let email = EmailBuilder::new()
+            .from("test@example.com")
+            .to("recipient@example.com")
+            .subject("Test")
+            .text("Body")
+            .build()
+            .unwrap();
+        let id = service.enqueue(email).await.unwrap().id;
+
+        let claims: Vec<_> = ["worker-a", "worker-b"].into_iter()
+            .map(|worker| {
+                let service = service.clone();
+                tokio::spawn(async move { service.claim(id, worker).await })
+            })
+            .collect();
+
+        let mut winners = Vec::new();
+        for claim in claims {
+            if let Ok(item) = claim.await.unwrap() {
+                winners.push(item.worker_id);
+            }
+        }
+
+        assert_eq!(winners.len(), 1);
+        let item = service.get(id).await.unwrap();
+        assert_eq!(item.status, QueueStatus::Processing);
+        assert_eq!(item.worker_id, winners[0]);
+    }
+
+    #[tokio::test]
+    async fn test_queue_query() {
+        let service = QueueService::new();
+
+        let mut ids = Vec::new();
+        for subject in ["Invoice #1", "Invoice #2", "Newsletter", "Invoice #3"] {
+            let email = EmailBuilder::new()
+                .from("test@example.com")
+                .to("recipient@example.com")
+                .subject(subject)
+                .text("Body")
+                .build()
+                .unwrap();
+            ids.push(service.enqueue(email).await.unwrap().id);
+        }
+        service.cancel(ids[1]).await.unwrap();
+        service.set_priority(ids[3], 10).await.unwrap();
+
+        let filter = QueueFilter {
+            subject: Some("invoice".to_string()),
+            ..QueueFilter::with_status(QueueStatus::Pending)
+        };
+        let found: Vec<_> = service.query(filter.clone()).await.into_iter().map(|i| i.id).collect();
+        assert_eq!(found.len(), 2);
+        assert!(found.contains(&ids[0]));
+        assert!(found.contains(&ids[3]));
+
+        let high_priority = QueueFilter { min_priority: Some(5), ..filter.clone() };
+        let found: Vec<_> = service.query(high_priority).await.into_iter().map(|i| i.id).collect();
+        assert_eq!(found, vec![ids[3]]);
+
+        let paged = QueueFilter { limit: 1, offset: 1, ..filter };
+        assert_eq!(service.query(paged).await.len(), 1);
+    }
+
+    #[test]
+    fn test_deferred_ready_order() {
+        let now = chrono::Utc::now();
+        let email = EmailBuilder::new()
+            .from("test@example.com")
+            .to("recipient@example.com")
+            .subject("Test")
+            .text("Body")
+            .build()
+            .unwrap();
+
+        let deferred = |scheduled_mins_ago: i64, retry_mins_ago: i64| {
+            let mut item = QueueItem::scheduled(email.clone(), now - chrono::Duration::minutes(scheduled_mins_ago));
+            item.status = QueueStatus::Deferred;
+            item.next_retry_at = Some(now - chrono::Duration::minutes(retry_mins_ago));
+            item
+        };
+
+        // Scheduled earlier but only just came due again
+        let fresh = deferred(60, 1);
+        // Scheduled later but has been waiting for its retry much longer
+        let overdue = deferred(30, 20);
+
+        let mut ready = [fresh.clone(), overdue.clone()];
+        ready.sort_by(|a, b| a.cmp_ready(b));
+        assert_eq!(ready[0].id, overdue.id);
+        assert_eq!(ready[1].id, fresh.id);
+    }
+
+    #[test]
+    fn test_priority_aging() {
+        let now = chrono::Utc::now();
+        let aging = Some(chrono::Duration::hours(1));
+        let email = EmailBuilder::new()
+            .from("test@example.com")
+            .to("recipient@example.com")
+            .subject("Test")
+            .text("Body")
+            .build()
+            .unwrap();
+
+        let mut old_low = QueueItem::new(email.clone());
+        old_low.priority = -5;
+        old_low.created_at = now - chrono::Duration::hours(8);
+
+        let urgent = |mins_ago: i64| {
+            let mut item = QueueItem::new(email.clone());
+            item.priority = 10;
+            item.created_at = now - chrono::Duration::minutes(mins_ago);
+            item
+        };
+        let fresh = [urgent(0), urgent(5), urgent(10)];
+
+        // Without aging the old item always waits
+        let mut ready = [old_low.clone(), fresh[0].clone(), fresh[1].clone(), fresh[2].clone()];
+        ready.sort_by(|a, b| a.cmp_ready_at(b, now, None));
+        assert_eq!(ready.last().unwrap().id, old_low.id);
+
+        // After 8 hours it has aged to 3, still behind fresh urgent mail
+        ready.sort_by(|a, b| a.cmp_ready_at(b, now, aging));
+        assert_eq!(ready.last().unwrap().id, old_low.id);
+
+        // Eight hours later it outranks newly arrived urgent mail
+        let later = now + chrono::Duration::hours(8);
+        let mut ready = [old_low.clone(), urgent(-480), urgent(-475)];
+        ready.sort_by(|a, b| a.cmp_ready_at(b, later, aging));
+        assert_eq!(ready[0].id, old_low.id);
+    }
+
+    #[tokio::test]
+    async fn test_reclaim_expired_lease() {
+        let service = QueueService::new().with_lease_duration(chrono::Duration::minutes(5));
+
+        let email = EmailBuilder::new()
+            .from("test@example.com")
+            .to("recipient@example.com")
+            .subject("Test")
+            .text("Body")
+            .build()
+            .unwrap();
+        let id = service.enqueue(email).await.unwrap().id;
+
+        let claimed = service.claim(id, "crashed-worker").await.unwrap();
+        let started_at = claimed.started_at.unwrap();
+
+        // Still within the lease
+        assert_eq!(service.reclaim_expired().await, 0);
+        assert_eq!(service.reclaim_expired_at(started_at + chrono::Duration::minutes(4)).await, 0);
+        assert_eq!(service.get(id).await.unwrap().status, QueueStatus::Processing);
+
+        // Worker never reported back
+        assert_eq!(service.reclaim_expired_at(started_at + chrono::Duration::minutes(6)).await, 1);
+        let item = service.get(id).await.unwrap();
+        assert_eq!(item.status, QueueStatus::Pending);
+        assert!(item.worker_id.is_none());
+        assert_eq!(service.get_pending(10).await.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_non_retryable_failure() {
+        let service = QueueService::new();
+
+        let email = EmailBuilder::new()
+            .from("test@example.com")
+            .to("recipient@example.com")
+            .subject("Test")
+            .text("Body")
+            .build()
+            .unwrap();
+        let id = service.enqueue(email).await.unwrap().id;
+
+        service.claim(id, "worker").await.unwrap();
+        service.mark_failed(id, "Invalid recipient").await.unwrap();
+
+        let item = service.get(id).await.unwrap();
+        assert_eq!(item.status, QueueStatus::Failed);
+        assert_eq!(item.attempts, 1);
+        assert!(item.next_retry_at.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_retry_jitter() {
+        let policy = RetryPolicy { jitter_fraction: 0.2, ..Default::default() };
+        let service = QueueService::new().with_retry_policy(policy);
+
+        let mut ids = Vec::new();
+        for i in 0..100 {
+            let email = EmailBuilder::new()
+                .from("test@example.com")
+                .to(format!("user{}@example.com", i))
+                .subject("Test")
+                .text("Body")
+                .build()
+                .unwrap();
+            let id = service.enqueue(email).await.unwrap().id;
+            service.claim(id, "worker").await.unwrap();
+            ids.push(id);
+        }
+
+        let failed_at = chrono::Utc::now();
+        for id in &ids {
+            service.mark_failed(*id, "connection refused").await.unwrap();
+        }
+
+        // First retry is 60s out; ±20% gives 48s..=72s
+        let mut offsets = Vec::new();
+        for id in &ids {
+            let item = service.get(*id).await.unwrap();
+            let offset = (item.next_retry_at.unwrap() - failed_at).num_milliseconds();
+            assert!((47_000..=73_000).contains(&offset), "offset {}ms", offset);
+            offsets.push(offset);
+        }
+
+        let spread = offsets.iter().max().unwrap() - offsets.iter().min().unwrap();
+        assert!(spread > 5_000, "retries bunched within {}ms", spread);
+
+        assert_eq!(
+            RetryPolicy::default().apply_jitter(chrono::Duration::seconds(120)),
+            chrono::Duration::seconds(120)
+        );
+    }
+
+    #[test]
+    fn test_queue_stats_send_latency() {
+        let now = chrono::Utc::now();
+        let email = EmailBuilder::new()
+            .from("test@example.com")
+            .to("recipient@example.com")
+            .subject("Test")
+            .text("Body")
+            .build()
+            .unwrap();
+
+        let sent = |started_mins_ago: i64, duration_ms: i64| {
+            let mut item = QueueItem::new(email.clone());
+            let started = now - chrono::Duration::minutes(started_mins_ago);
+            item.started_at = Some(started);
+            item.mark_sent();
+            item.completed_at = Some(started + chrono::Duration::milliseconds(duration_ms));
+            item.send_duration_ms = Some(duration_ms as u64);
+            item
+        };
+
+        let items = vec![
+            sent(10, 100),
+            sent(20, 200),
+            sent(30, 600),
+            // Outside the 24h window
+            sent(60 * 30, 10_000),
+        ];
+
+        let stats = QueueStats::compute(&items, now);
+        assert_eq!(stats.sent, 3);
+        assert!((stats.avg_send_time_ms - 300.0).abs() < f64::EPSILON);
+        assert!((stats.throughput - 3.0 / 24.0).abs() < f64::EPSILON);
+
+        // Durations are recorded from the processing start
+        let mut item = QueueItem::new(email);
+        item.start_processing("worker");
+        item.mark_sent();
+        assert!(item.send_duration_ms.is_some());
+    }
+
+    #[tokio::test]
+    async fn test_queue_events() {
+        let service = QueueService::new();
+        let mut events = service.subscribe().await;
+
+        let email = EmailBuilder::new()
+            .from("test@example.com")
+            .to("recipient@example.com")
+            .subject("Test")
+            .text("Body")
+            .build()
+            .unwrap();
+
+        let item = service.enqueue(email).await.unwrap();
+        service.mark_sent(item.id).await.unwrap();
+
+        let first = events.recv().await.unwrap();
+        assert_eq!(first.item_id, item.id);
+        assert_eq!(first.status, QueueStatus::Pending);
+
+        let second = events.recv().await.unwrap();
+        assert_eq!(second.item_id, item.id);
+        assert_eq!(second.status, QueueStatus::Sent);
+
+        assert!(events.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_log_service() {
+        let service = LogService::new();
+
+        let email_id = uuid::Uuid::now_v7();
+        service.log_sent(email_id, "test@example.com", "Test Subject", "smtp", None).await;
+
+        let logs = service.recent(10).await;
+        assert!(!logs.is_empty());
+
+        let stats = service.stats(None, None).await;
+        assert!(stats.total_sent > 0);
+    }
+
+    #[tokio::test]
+    async fn test_bounce_diagnostic() {
+        let logs = LogService::new();
+
+        let entry = EmailLog::new(uuid::Uuid::now_v7(), EmailEvent::HardBounce, "Gone@Example.com", "Hello")
+            .with_error("Mailbox does not exist")
+            .with_provider_response("smtp; 550 5.1.1 <gone@example.com>: Recipient address rejected: User unknown");
+        logs.log(entry).await;
+
+        let record = logs.get_bounce("gone@example.com").await.unwrap();
+        assert_eq!(record.reason.as_deref(), Some("Mailbox does not exist"));
+        assert_eq!(
+            record.diagnostic.as_deref(),
+            Some("smtp; 550 5.1.1 <gone@example.com>: Recipient address rejected: User unknown")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_suppression_audit() {
+        use crate::services::log::SuppressionReason;
+
+        let logs = LogService::new();
+        let notified = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+        let sink = std::sync::Arc::clone(&notified);
+        logs.on_suppression(move |audit| sink.lock().unwrap().push(audit.email.clone())).await;
+
+        let email_id = uuid::Uuid::now_v7();
+        let entry = EmailLog::new(email_id, EmailEvent::HardBounce, "Gone@Example.com", "Hello")
+            .with_error("Mailbox does not exist");
+        logs.log(entry).await;
+
+        let audit = logs.suppression_audit("gone@example.com").await;
+        assert_eq!(audit.len(), 1);
+        assert_eq!(audit[0].email_id, Some(email_id));
+        assert!(matches!(audit[0].reason, SuppressionReason::HardBounce));
+        assert_eq!(*notified.lock().unwrap(), ["gone@example.com"]);
+
+        // Soft bounces don't suppress
+        let entry = EmailLog::new(uuid::Uuid::now_v7(), EmailEvent::SoftBounce, "full@example.com", "Hello");
+        logs.log(entry).await;
+        assert!(logs.suppression_audit("full@example.com").await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_template_usage() {
+        let service = LogService::new();
+        let welcome = uuid::Uuid::now_v7();
+        let reset = uuid::Uuid::now_v7();
+        let base = chrono::Utc::now() - chrono::Duration::hours(3);
+
+        let sent = |template_id, name: &str, hours: i64| {
+            let mut entry = EmailLog::new(uuid::Uuid::now_v7(), EmailEvent::Sent, "user@example.com", "Subject")
+                .with_template(template_id, name);
+            entry.timestamp = base + chrono::Duration::hours(hours);
+            entry
+        };
+
+        service.log(sent(welcome, "welcome", 0)).await;
+        service.log(sent(welcome, "welcome", 2)).await;
+        service.log(sent(welcome, "welcome", 1)).await;
+        service.log(sent(reset, "reset", 1)).await;
+        // Non-send events don't count
+        let opened = EmailLog::new(uuid::Uuid::now_v7(), EmailEvent::Opened, "user@example.com", "Subject")
+            .with_template(reset, "reset");
+        service.log(opened).await;
+
+        let usage = service.template_usage().await;
+        assert_eq!(usage.len(), 2);
+
+        assert_eq!(usage[0].template_id, welcome);
+        assert_eq!(usage[0].sent_count, 3);
+        assert_eq!(usage[0].last_used, base + chrono::Duration::hours(2));
+
+        assert_eq!(usage[1].template_id, reset);
+        assert_eq!(usage[1].sent_count, 1);
+        assert_eq!(usage[1].last_used, base + chrono::Duration::hours(1));
+    }
+
+    #[tokio::test]
+    async fn test_suppression() {
+        let service = LogService::new();
+
+        assert!(!service.is_suppressed("test@example.com").await);
+
+        service.add_to_suppression("test@example.com", crate::services::log::SuppressionReason::Manual, None).await;
+        assert!(service.is_suppressed("test@example.com").await);
+
+        service.remove_from_suppression("test@example.com").await;
+        assert!(!service.is_suppressed("test@example.com").await);
+    }
+
+    #[tokio::test]
+    async fn test_filter_suppressed() {
+        let service = LogService::new();
+        service.add_to_suppression("bounced@example.com", crate::services::log::SuppressionReason::HardBounce, None).await;
+        service.add_to_suppression("spam@example.com", crate::services::log::SuppressionReason::SpamComplaint, None).await;
+
+        let addresses: Vec<String> = ["ok@example.com", "Bounced@Example.com", "fine@example.com", "spam@example.com"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+
+        let suppressed = service.filter_suppressed(&addresses).await;
+        assert_eq!(suppressed, vec!["Bounced@Example.com".to_string(), "spam@example.com".to_string()]);
+    }
+
+    #[tokio::test]
+    async fn test_topic_suppression() {
+        let mailer = MailerService::new();
+        mailer.configure(crate::services::mailer::MailerConfig {
+            default_from: Some(EmailAddress::new("noreply@example.com")),
+            ..Default::default()
+        }).await;
+
+        for (slug, subject) in [("digest", "Weekly digest"), ("invoice", "Your invoice")] {
+            let template = TemplateBuilder::new()
+                .name(slug)
+                .subject(subject)
+                .text("Hello {{name}}")
+                .build()
+                .unwrap();
+            mailer.templates().register(template).await.unwrap();
+        }
+
+        let user = "user@example.com";
+        mailer.logs().add_to_suppression_topic(user, "digest").await;
+        assert!(mailer.logs().is_suppressed_for(user, "digest").await);
+        assert!(!mailer.logs().is_suppressed_for(user, "billing").await);
+        assert!(!mailer.logs().is_suppressed(user).await);
+
+        let data = serde_json::json!({ "name": "User" });
+        let err = mailer.send_template("digest", EmailAddress::new(user), data.clone(), Some("digest"))
+            .await
+            .unwrap_err();
+        assert!(matches!(err, services::mailer::MailerError::Suppressed(_)));
+
+        mailer.send_template("invoice", EmailAddress::new(user), data.clone(), Some("billing"))
+            .await
+            .unwrap();
+        assert_eq!(mailer.queue().size().await, 1);
+
+        // A global suppression blocks every topic
+        mailer.logs().add_to_suppression(user, crate::services::log::SuppressionReason::Manual, None).await;
+        assert!(mailer.logs().is_suppressed_for(user, "billing").await);
+        assert!(mailer.send_template("invoice", EmailAddress::new(user), data, Some("billing"))
+            .await
+            .is_err());
+    }
+
+    #[test]
+    fn test_retry_policy() {
+        let policy = RetryPolicy::default();
+
+        assert_eq!(policy.max_attempts, 3);
+        assert!(policy.is_retryable("Connection timeout"));
+        assert!(!policy.is_retryable("Invalid recipient"));
+
+        let delay = policy.get_delay(0);
+        assert_eq!(delay.num_seconds(), 60);
+
+        let delay = policy.get_delay(1);
+        assert_eq!(delay.num_seconds(), 120);
+    }
+
+    #[test]
+    fn test_backoff_strategies() {
+        let delays = |backoff: BackoffStrategy| {
+            let policy = RetryPolicy { backoff, max_delay_secs: 600, ..Default::default() };
+            (0..4).map(|attempt| policy.get_delay(attempt).num_seconds()).collect::<Vec<_>>()
+        };
+
+        assert_eq!(delays(BackoffStrategy::default()), [60, 120, 240, 480]);
+        assert_eq!(delays(BackoffStrategy::Exponential { multiplier: 3.0 }), [60, 180, 540, 600]);
+        assert_eq!(delays(BackoffStrategy::Linear { step: 30 }), [60, 90, 120, 150]);
+        assert_eq!(delays(BackoffStrategy::Fixed), [60, 60, 60, 60]);
+        assert_eq!(delays(BackoffStrategy::Custom(vec![10, 30])), [10, 30, 30, 30]);
+        assert_eq!(delays(BackoffStrategy::Custom(vec![])), [60, 60, 60, 60]);
+
+        // Configs from before `backoff` set a top-level multiplier
+        let legacy: RetryPolicy = serde_json::from_value(serde_json::json!({ "multiplier": 3.0 })).unwrap();
+        assert_eq!(legacy.backoff, BackoffStrategy::Exponential { multiplier: 3.0 });
+        assert_eq!(legacy.max_attempts, RetryPolicy::default().max_attempts);
+        let current: RetryPolicy = serde_json::from_value(serde_json::json!({ "backoff": "fixed" })).unwrap();
+        assert_eq!(current.backoff, BackoffStrategy::Fixed);
+    }
+
+    #[tokio::test]
+    async fn test_queue_uses_backoff_strategy() {
+        let policy = RetryPolicy { backoff: BackoffStrategy::Custom(vec![5, 10]), ..Default::default() };
+        let service = QueueService::new().with_retry_policy(policy);
+
+        let email = EmailBuilder::new()
+            .from("test@example.com")
+            .to("recipient@example.com")
+            .subject("Test")
+            .text("Body")
+            .build()
+            .unwrap();
+        let id = service.enqueue(email).await.unwrap().id;
+
+        service.claim(id, "worker").await.unwrap();
+        let failed_at = chrono::Utc::now();
+        service.mark_failed(id, "connection refused").await.unwrap();
+
+        let item = service.get(id).await.unwrap();
+        let delay = item.next_retry_at.unwrap() - failed_at;
+        assert!((4..=5).contains(&delay.num_seconds()), "{:?}", delay);
+    }
+
+    #[test]
+    fn test_classify_bounce() {
+        use models::classify_bounce;
+
+        assert_eq!(classify_bounce("5.1.1", "User unknown"), BounceType::Hard);
+        assert_eq!(classify_bounce("550", "5.1.1 <bob@example.com>: Recipient address rejected"), BounceType::Hard);
+        assert_eq!(classify_bounce("5.2.2", "Mailbox full"), BounceType::Soft);
+        assert_eq!(classify_bounce("552", "Requested mail action aborted: exceeded storage allocation"), BounceType::Soft);
+        assert_eq!(classify_bounce("4.2.1", "Mailbox temporarily unavailable"), BounceType::Soft);
+        assert_eq!(classify_bounce("421", "Service not available"), BounceType::Soft);
+        assert_eq!(classify_bounce("5.7.1", "Message rejected as spam"), BounceType::General);
+        assert_eq!(classify_bounce("550", "Mailbox unavailable"), BounceType::Hard);
+        assert_eq!(classify_bounce("", "Connection refused"), BounceType::General);
+    }
+
+    /// Transport whose sends never complete, like a server stalled mid-DATA
+    struct StalledTransport;
+
+    #[async_trait::async_trait]
+    impl MailTransport for StalledTransport {
+        async fn send(&self, _email: &Email) -> Result<services::smtp::SendResult, services::smtp::SmtpError> {
+            std::future::pending().await
+        }
+
+        async fn test_connection(&self) -> Result<bool, services::smtp::SmtpError> {
+            Ok(true)
+        }
+
+        fn timeout(&self) -> std::time::Duration {
+            std::time::Duration::from_millis(100)
+        }
+    }
+
+    /// Transport that accepts every message
+    struct AcceptingTransport;
+
+    #[async_trait::async_trait]
+    impl MailTransport for AcceptingTransport {
+        async fn send(&self, email: &Email) -> Result<services::smtp::SendResult, services::smtp::SmtpError> {
+            Ok(services::smtp::SendResult {
+                message_id: Some(email.id.to_string()),
+                code: "250".to_string(),
+                message: Some("OK".to_string()),
+                size: None,
+                rejected: Vec::new(),
+            })
+        }
+
+        async fn test_connection(&self) -> Result<bool, services::smtp::SmtpError> {
+            Ok(true)
+        }
+    }
+
+    /// Defers every message with a 4xx reply, as a greylisting server does
+    struct GreylistingTransport;
+
+    #[async_trait::async_trait]
+    impl MailTransport for GreylistingTransport {
+        async fn send(&self, _: &Email) -> Result<services::smtp::SendResult, services::smtp::SmtpError> {
+            Err(services::smtp::SmtpError::Send("451 4.7.1 Try again later".to_string()))
+        }
+
+        async fn test_connection(&self) -> Result<bool, services::smtp::SmtpError> {
+            Ok(true)
+        }
+    }
+
+    /// Accepts every message and keeps a copy of each
+    #[derive(Default)]
+    struct RecordingTransport(std::sync::Arc<std::sync::Mutex<Vec<Email>>>);
+
+    #[async_trait::async_trait]
+    impl MailTransport for RecordingTransport {
+        async fn send(&self, email: &Email) -> Result<services::smtp::SendResult, services::smtp::SmtpError> {
+            self.0.lock().unwrap().push(email.clone());
+            AcceptingTransport.send(email).await
+        }
+
+        async fn test_connection(&self) -> Result<bool, services::smtp::SmtpError> {
+            Ok(true)
+        }
+    }
+
+    /// Accepts every recipient except those at `bounce.example.com`
+    struct PartialBounceTransport;
+
+    #[async_trait::async_trait]
+    impl MailTransport for PartialBounceTransport {
+        async fn send(&self, email: &Email) -> Result<services::smtp::SendResult, services::smtp::SmtpError> {
+            Ok(services::smtp::SendResult {
+                message_id: Some("partial-1".to_string()),
+                code: "250".to_string(),
+                message: Some("OK".to_string()),
+                size: None,
+                rejected: email.to.iter()
+                    .filter(|a| a.domain() == Some("bounce.example.com"))
+                    .map(|a| (a.email.clone(), "550 5.1.1 User unknown".to_string()))
+                    .collect(),
+            })
+        }
+
+        async fn test_connection(&self) -> Result<bool, services::smtp::SmtpError> {
+            Ok(true)
+        }
+    }
+
+    #[tokio::test]
+    async fn test_recipient_statuses() {
+        let mailer = MailerService::new();
+        mailer.set_transport(PartialBounceTransport).await;
+
+        let email = EmailBuilder::new()
+            .from("sender@example.com")
+            .to("ada@example.com")
+            .to("gone@bounce.example.com")
+            .to("grace@example.com")
+            .subject("Hello")
+            .text("Body")
+            .build()
+            .unwrap();
+        let id = mailer.queue_email(email).await.unwrap().id;
+
+        let result = mailer.process_queue(10).await;
+        assert_eq!(result.sent, 1);
+
+        let item = mailer.queue().get(id).await.unwrap();
+        assert_eq!(item.status, QueueStatus::Sent);
+        let delivered = |email: &str| RecipientStatus {
+            email: email.to_string(),
+            state: RecipientState::Delivered,
+            error: None,
+        };
+        assert_eq!(item.recipient_statuses, vec![
+            delivered("ada@example.com"),
+            RecipientStatus {
+                email: "gone@bounce.example.com".to_string(),
+                state: RecipientState::Bounced,
+                error: Some("550 5.1.1 User unknown".to_string()),
+            },
+            delivered("grace@example.com"),
+        ]);
+
+        assert!(mailer.logs().is_suppressed("gone@bounce.example.com").await);
+        assert!(!mailer.logs().is_suppressed("ada@example.com").await);
+    }
+
+    #[tokio::test]
+    async fn test_warmup_schedule() {
+        use crate::services::mailer::WarmupSchedule;
+
+        let mailer = MailerService::new();
+        mailer.set_transport(AcceptingTransport).await;
+
+        let started_at = chrono::Utc::now();
+        mailer.set_warmup_schedule(Some(WarmupSchedule::new(vec![50, 100], started_at))).await;
+
+        // A send that fails doesn't use up the day's budget
+        let bounced = EmailBuilder::new()
+            .from("test@example.com")
+            .to("bounced@example.com")
+            .subject("Hello")
+            .text("Body")
+            .build()
+            .unwrap();
+        mailer.queue_email(bounced).await.unwrap();
+        mailer.logs().add_to_suppression("bounced@example.com", crate::services::log::SuppressionReason::HardBounce, None).await;
+
+        for i in 0..51 {
+            let email = EmailBuilder::new()
+                .from("test@example.com")
+                .to(format!("user{}@example.com", i))
+                .subject("Hello")
+                .text("Body")
+                .build()
+                .unwrap();
+            mailer.queue_email(email).await.unwrap();
+        }
+
+        let result = mailer.process_queue(100).await;
+        assert_eq!(result.sent, 50);
+        assert_eq!(result.failed, 1);
+        assert_eq!(result.deferred, 1);
+
+        let waiting = mailer.queue().list_by_status(QueueStatus::Pending, 10, 0).await;
+        assert_eq!(waiting.len(), 1);
+        assert_eq!(waiting[0].scheduled_at, started_at + chrono::Duration::days(1));
+
+        // Nothing more goes out today
+        assert_eq!(mailer.process_queue(100).await.sent, 0);
+    }
+
+    #[tokio::test]
+    async fn test_delivery_outcome() {
+        use crate::services::mailer::{DeliveryDisposition, MailerConfig, PRIMARY_PROVIDER};
+
+        let mailer = MailerService::new();
+        mailer.set_transport(AcceptingTransport).await;
+        mailer.configure(MailerConfig {
+            default_from: Some(EmailAddress::new("noreply@example.com")),
+            ..Default::default()
+        }).await;
+
+        let queued = mailer.quick_send("user@example.com", "Hi", "Hello").await.unwrap();
+        let item = mailer.queue().get(queued.queue_id().unwrap()).await.unwrap();
+        assert_eq!(item.email.id, queued.email_id);
+
+        mailer.configure(MailerConfig {
+            default_from: Some(EmailAddress::new("noreply@example.com")),
+            queue_by_default: false,
+            ..Default::default()
+        }).await;
+
+        let sent = mailer.quick_send("user@example.com", "Hi", "Hello").await.unwrap();
+        assert_eq!(sent.queue_id(), None);
+        assert_eq!(
+            sent.disposition,
+            DeliveryDisposition::Sent { message_id: Some(sent.email_id.to_string()) }
+        );
+        let logs = mailer.logs().get_for_email(sent.email_id).await;
+        assert!(logs.iter().any(|log| log.event == EmailEvent::Sent && log.provider == PRIMARY_PROVIDER));
+    }
+
+    #[tokio::test]
+    async fn test_recipient_frequency_cap() {
+        use crate::services::mailer::{DeliveryDisposition, MailerConfig};
+
+        let mailer = MailerService::new();
+        mailer.set_transport(AcceptingTransport).await;
+        mailer.configure(MailerConfig {
+            default_from: Some(EmailAddress::new("noreply@example.com")),
+            queue_by_default: false,
+            max_per_recipient_per_day: Some(2),
+            ..Default::default()
+        }).await;
+
+        let started_at = chrono::Utc::now();
+        let mut outcomes = Vec::new();
+        for subject in ["One", "Two", "Three"] {
+            outcomes.push(mailer.quick_send("user@example.com", subject, "Hello").await.unwrap());
+        }
+
+        assert!(matches!(outcomes[0].disposition, DeliveryDisposition::Sent { .. }));
+        assert!(matches!(outcomes[1].disposition, DeliveryDisposition::Sent { .. }));
+
+        // The third waits until the first send leaves the 24 hour window
+        let item = mailer.queue().get(outcomes[2].queue_id().unwrap()).await.unwrap();
+        assert!(item.scheduled_at >= started_at + chrono::Duration::days(1));
+        assert!(item.scheduled_at <= chrono::Utc::now() + chrono::Duration::days(1));
+
+        // Other recipients are unaffected
+        let other = mailer.quick_send("other@example.com", "One", "Hello").await.unwrap();
+        assert!(matches!(other.disposition, DeliveryDisposition::Sent { .. }));
+    }
+
+    #[tokio::test]
+    async fn test_recipient_frequency_cap_when_queued() {
+        use crate::services::mailer::MailerConfig;
+
+        let mailer = MailerService::new();
+        mailer.set_transport(AcceptingTransport).await;
+        mailer.configure(MailerConfig {
+            default_from: Some(EmailAddress::new("noreply@example.com")),
+            max_per_recipient_per_day: Some(2),
+            ..Default::default()
+        }).await;
+
+        let started_at = chrono::Utc::now();
+        let mut outcomes = Vec::new();
+        for subject in ["One", "Two", "Three"] {
+            outcomes.push(mailer.quick_send("user@example.com", subject, "Hello").await.unwrap());
+        }
+
+        let result = mailer.process_queue(10).await;
+        assert_eq!((result.sent, result.deferred), (2, 1));
+
+        let mut held = Vec::new();
+        for outcome in &outcomes {
+            let item = mailer.queue().get(outcome.queue_id().unwrap()).await.unwrap();
+            if item.status == QueueStatus::Pending {
+                held.push(item);
+            }
+        }
+        assert_eq!(held.len(), 1);
+        assert!(held[0].scheduled_at >= started_at + chrono::Duration::days(1));
+
+        // The held item is not due, so another pass sends nothing
+        assert_eq!(mailer.process_queue(10).await.sent, 0);
+    }
+
+    #[test]
+    fn test_blocking_mailer() {
+        use crate::blocking::BlockingMailer;
+        use crate::services::mailer::{DeliveryDisposition, MailerConfig};
+
+        let mailer = BlockingMailer::new().unwrap();
+        mailer.set_transport(AcceptingTransport);
+        mailer.configure(MailerConfig {
+            default_from: Some(EmailAddress::new("noreply@example.com")),
+            ..Default::default()
+        });
+        mailer.initialize();
+
+        let email = EmailBuilder::new()
+            .from("noreply@example.com")
+            .to("user@example.com")
+            .subject("Hi")
+            .text("Hello")
+            .build()
+            .unwrap();
+        let outcome = mailer.send(email).unwrap();
+        assert!(matches!(outcome.disposition, DeliveryDisposition::Sent { .. }));
+
+        // Templates queue by default; processing sends them
+        let queued = mailer.send_template(
+            "welcome",
+            EmailAddress::new("jane@example.com"),
+            serde_json::json!({ "user_name": "Jane", "site_name": "RustPress" }),
+            None,
+        ).unwrap();
+        assert!(queued.queue_id().is_some());
+        assert_eq!(mailer.process_queue(10).sent, 1);
+    }
+
+    #[tokio::test]
+    async fn test_from_name_defaults_to_site_name() {
+        let mailer = MailerService::new();
+        let transport = SmtpTransport::new(SmtpConfig::default());
+        let from_header = |item: QueueItem| {
+            let formatted = transport.build_message(&item.email).unwrap().formatted();
+            String::from_utf8(formatted).unwrap()
+                .lines()
+                .find(|line| line.starts_with("From: "))
+                .unwrap()
+                .to_string()
+        };
+
+        let email = EmailBuilder::new()
+            .from("noreply@example.com")
+            .to("user@example.com")
+            .subject("Hi")
+            .text("Hello")
+            .build()
+            .unwrap();
+        let queued = mailer.deliver(email).await.unwrap();
+        let item = mailer.queue().get(queued.queue_id().unwrap()).await.unwrap();
+        assert_eq!(from_header(item), "From: RustPress <noreply@example.com>");
+
+        // An explicit name is kept
+        let email = EmailBuilder::new()
+            .from_name("noreply@example.com", "Support")
+            .to("user@example.com")
+            .subject("Hi")
+            .text("Hello")
+            .build()
+            .unwrap();
+        let queued = mailer.deliver(email).await.unwrap();
+        let item = mailer.queue().get(queued.queue_id().unwrap()).await.unwrap();
+        assert_eq!(from_header(item), "From: Support <noreply@example.com>");
+    }
+
+    #[tokio::test]
+    async fn test_send_template_with_cc() {
+        use crate::services::mailer::{MailerConfig, MailerError, TemplateRecipients};
+        use crate::services::log::SuppressionReason;
+
+        let mailer = MailerService::new();
+        mailer.configure(MailerConfig {
+            default_from: Some(EmailAddress::new("noreply@example.com")),
+            ..Default::default()
+        }).await;
+        mailer.initialize().await;
+
+        let data = serde_json::json!({ "user_name": "Jane", "site_name": "RustPress" });
+        let recipients = TemplateRecipients::new(EmailAddress::new("jane@example.com"))
+            .cc(EmailAddress::with_name("manager@example.com", "Manager"))
+            .reply_to("support@example.com");
+
+        let queued = mailer.send_template_with("welcome", recipients, data.clone(), None).await.unwrap();
+        let item = mailer.queue().get(queued.queue_id().unwrap()).await.unwrap();
+
+        let transport = SmtpTransport::new(SmtpConfig::default());
+        let formatted = String::from_utf8(transport.build_message(&item.email).unwrap().formatted()).unwrap();
+        assert!(formatted.contains("Cc: Manager <manager@example.com>"));
+        assert!(formatted.contains("Reply-To: support@example.com"));
+
+        // A suppressed CC blocks the send like a suppressed To would
+        mailer.logs().add_to_suppression("archive@example.com", SuppressionReason::Manual, None).await;
+        let recipients = TemplateRecipients::new(EmailAddress::new("jane@example.com"))
+            .bcc("archive@example.com");
+        let err = mailer.send_template_with("welcome", recipients, data, None).await.unwrap_err();
+        assert!(matches!(err, MailerError::Suppressed(_)));
+    }
+
+    #[tokio::test]
+    async fn test_template_rate_limit() {
+        use crate::services::mailer::{MailerConfig, MailerError};
+
+        let mailer = MailerService::new();
+        mailer.set_transport(AcceptingTransport).await;
+        mailer.configure(MailerConfig {
+            default_from: Some(EmailAddress::new("shop@example.com")),
+            queue_by_default: false,
+            template_rate_limits: std::collections::HashMap::from([("cart-reminder".to_string(), 3)]),
+            ..Default::default()
+        }).await;
+        for slug in ["cart-reminder", "receipt"] {
+            let template = TemplateBuilder::new()
+                .name(slug)
+                .subject("Hi")
+                .text("Hello")
+                .build()
+                .unwrap();
+            mailer.templates().register(template).await.unwrap();
+        }
+
+        // Failed sends give their slot back
+        mailer.logs().add_to_suppression("gone@example.com", crate::services::log::SuppressionReason::Manual, None).await;
+        for _ in 0..3 {
+            let result = mailer.send_template("cart-reminder", EmailAddress::new("gone@example.com"), serde_json::json!({}), None).await;
+            assert!(matches!(result, Err(MailerError::Suppressed(_))));
+        }
+
+        let send = |slug: &'static str| mailer.send_template(slug, EmailAddress::new("user@example.com"), serde_json::json!({}), None);
+        for _ in 0..3 {
+            send("cart-reminder").await.unwrap();
+        }
+        assert!(matches!(send("cart-reminder").await, Err(MailerError::RateLimited(_))));
+
+        // Other templates are unaffected
+        assert!(send("receipt").await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_bcc_broadcast() {
+        use crate::services::mailer::MailerConfig;
+
+        let mailer = MailerService::new();
+        mailer.set_transport(AcceptingTransport).await;
+        mailer.configure(MailerConfig {
+            default_from: Some(EmailAddress::new("news@example.com")),
+            ..Default::default()
+        }).await;
+
+        let email = EmailBuilder::new()
+            .from("news@example.com")
+            .bcc_many(vec!["a@example.com", "b@example.com", "c@example.com"])
+            .subject("Announcement")
+            .text("We moved.")
+            .build()
+            .unwrap();
+        assert!(email.is_broadcast());
+
+        let mime = mailer.render_mime(&email).await.unwrap();
+        assert!(mime.contains("To: undisclosed-recipients:;"));
+        assert!(!mime.contains("b@example.com"));
+
+        mailer.send(email.clone()).await.unwrap();
+        let logs = mailer.logs().query(LogFilter::for_email(email.id)).await;
+        let sent: Vec<_> = logs.iter()
+            .filter(|log| log.event == EmailEvent::Sent)
+            .map(|log| log.recipient.as_str())
+            .collect();
+        assert_eq!(sent.len(), 3);
+        assert!(sent.contains(&"b@example.com"));
+    }
+
+    #[tokio::test]
+    async fn test_escaping_only_in_html() {
+        let service = TemplateService::new();
+        let template = TemplateBuilder::new()
+            .name("show")
+            .subject("Tonight: {{title}}")
+            .text("Watch {{title}}")
+            .html("<p>Watch {{title}}</p>")
+            .build()
+            .unwrap();
+        service.register(template).await.unwrap();
+
+        let rendered = service.render_by_slug("show", &serde_json::json!({"title": "Tom & Jerry"})).await.unwrap();
+        assert_eq!(rendered.subject, "Tonight: Tom & Jerry");
+        assert_eq!(rendered.text_body.as_deref(), Some("Watch Tom & Jerry"));
+        assert!(rendered.html_body.unwrap().contains("Watch Tom &amp; Jerry"));
+    }
+
+    #[tokio::test]
+    async fn test_queued_attachment_reaches_custom_transport() {
+        let transport = RecordingTransport::default();
+        let sent = std::sync::Arc::clone(&transport.0);
+        let mailer = MailerService::new();
+        mailer.set_transport(transport).await;
+
+        let mut email = EmailBuilder::new()
+            .from("noreply@example.com")
+            .to("user@example.com")
+            .subject("Your invoice")
+            .text("Attached.")
+            .build()
+            .unwrap();
+        email.attachments.push(Attachment::new("invoice.pdf", "application/pdf", b"%PDF-1.4 invoice".to_vec()));
+
+        // The queue moves the content into the blob store
+        let item = mailer.queue_email(email).await.unwrap();
+        assert!(item.email.attachments[0].is_stored());
+        assert_eq!(mailer.process_queue(10).await.sent, 1);
+
+        let sent = sent.lock().unwrap();
+        assert_eq!(sent[0].attachments[0].bytes(), Some(&b"%PDF-1.4 invoice"[..]));
+    }
+
+    #[tokio::test]
+    async fn test_attachment_checksum_logged() {
+        use sha2::{Digest, Sha256};
+        use crate::models::ATTACHMENT_SHA256_META;
+        use crate::services::mailer::MailerConfig;
+
+        let mailer = MailerService::new();
+        mailer.set_transport(AcceptingTransport).await;
+        mailer.configure(MailerConfig {
+            default_from: Some(EmailAddress::new("noreply@example.com")),
+            ..Default::default()
+        }).await;
+
+        let content = b"%PDF-1.4 signed contract".to_vec();
+        let expected = format!("{:x}", Sha256::digest(&content));
+        let attachment = Attachment::new("contract.pdf", "application/pdf", content);
+        assert_eq!(attachment.checksum().as_deref(), Some(expected.as_str()));
+
+        // File attachments are hashed without loading them into memory
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("contract.pdf");
+        std::fs::write(&path, b"%PDF-1.4 signed contract").unwrap();
+        let from_file = Attachment::from_file(path.to_str().unwrap()).unwrap();
+        assert_eq!(from_file.checksum().as_deref(), Some(expected.as_str()));
+
+        let mut email = EmailBuilder::new()
+            .from("noreply@example.com")
+            .to("user@example.com")
+            .subject("Your contract")
+            .text("Attached.")
+            .build()
+            .unwrap();
+        email.attachments.push(attachment);
+
+        // Queued attachments move to the blob store but keep the same digest
+        mailer.queue_email(email).await.unwrap();
+        assert_eq!(mailer.process_queue(10).await.sent, 1);
+
+        let logs = mailer.logs().get_for_recipient("user@example.com").await;
+        let sent = logs.iter().find(|log| log.event == EmailEvent::Sent).unwrap();
+        let logged = &sent.metadata[ATTACHMENT_SHA256_META][0];
+        assert_eq!(logged["filename"], "contract.pdf");
+        assert_eq!(logged["sha256"], expected.as_str());
+    }
+
+    #[tokio::test]
+    async fn test_list_queue_by_tag() {
+        let service = QueueService::new();
+        let tagged = |tag: &str| {
+            let mut email = EmailBuilder::new()
+                .from("noreply@example.com")
+                .to("user@example.com")
+                .subject("Hi")
+                .text("Hello")
+                .build()
+                .unwrap();
+            email.tags.push(tag.to_string());
+            email
+        };
+
+        let mut spring = Vec::new();
+        for _ in 0..3 {
+            spring.push(service.enqueue(tagged("campaign-spring")).await.unwrap().id);
+        }
+        service.enqueue(tagged("campaign-autumn")).await.unwrap();
+        service.enqueue(tagged("receipts")).await.unwrap();
+
+        let found: Vec<_> = service.list_by_tag("campaign-spring", 10, 0).await
+            .into_iter().map(|item| item.id).collect();
+        assert_eq!(found.len(), 3);
+        assert!(spring.iter().all(|id| found.contains(id)));
+
+        assert_eq!(service.list_by_tag("campaign-spring", 10, 2).await.len(), 1);
+        assert!(service.list_by_tag("campaign", 10, 0).await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_redacted_log_export() {
+        let logs = LogService::new();
+        logs.log(EmailLog::new(uuid::Uuid::now_v7(), EmailEvent::Failed, "jane@example.com", "Your invoice")
+            .with_error("550 <jane@example.com>: mailbox full")).await;
+
+        let export = logs.export(LogFilter::new(), Redaction { mask_recipients: true, mask_subjects: false }).await;
+        assert!(export.contains("j***@example.com"));
+        assert!(!export.contains("jane@"));
+        assert!(export.contains("Your invoice"));
+
+        let export = logs.export(LogFilter::new(), Redaction::all()).await;
+        assert!(!export.contains("Your invoice"));
+
+        let export = logs.export(LogFilter::new(), Redaction::default()).await;
+        assert!(export.contains("jane@example.com"));
+    }
+
+    #[tokio::test]
+    async fn test_greylisting_retried_without_fallback() {
+        use crate::services::mailer::MailerConfig;
+
+        let policy = RetryPolicy::default();
+        assert!(policy.is_retryable("451 4.7.1 Try again later"));
+        assert!(policy.is_retryable("SMTP error: Send error: 421 Service not available"));
+        assert!(!policy.is_retryable("550 5.1.1 User unknown"));
+        // Numbers that aren't a reply code don't make an error transient
+        assert!(!policy.is_retryable("SMTP error: Configuration error: failed to connect to host:465"));
+        assert!(!policy.is_retryable("Invalid email: message size 450 KB exceeds limit"));
+
+        let mailer = MailerService::new();
+        mailer.set_transport(GreylistingTransport).await;
+        mailer.configure(MailerConfig {
+            default_from: Some(EmailAddress::new("noreply@example.com")),
+            ..Default::default()
+        }).await;
+
+        let email = EmailBuilder::new()
+            .from("noreply@example.com")
+            .to("user@example.com")
+            .subject("Hi")
+            .text("Hello")
+            .build()
+            .unwrap();
+        let item = mailer.queue_email(email).await.unwrap();
+
+        assert_eq!(mailer.process_queue(10).await.failed, 1);
+        let retried = mailer.queue().get(item.id).await.unwrap();
+        assert_eq!(retried.status, QueueStatus::Deferred);
+        assert!(retried.next_retry_at.is_some());
+        assert_eq!(retried.attempts, 1);
+    }
+
+    #[tokio::test]
+    async fn test_reroute_to_fallback_provider() {
+        use crate::services::mailer::{MailerConfig, PRIMARY_PROVIDER};
+
+        let mailer = MailerService::new();
+        mailer.set_transport(GreylistingTransport).await;
+        mailer.add_fallback_transport("backup", AcceptingTransport).await;
+        mailer.configure(MailerConfig {
+            default_from: Some(EmailAddress::new("noreply@example.com")),
+            reroute_on_failure: true,
+            ..Default::default()
+        }).await;
+        assert_eq!(mailer.providers().await, vec![PRIMARY_PROVIDER, "backup"]);
+
+        let email = EmailBuilder::new()
+            .from("noreply@example.com")
+            .to("user@example.com")
+            .subject("Hi")
+            .text("Hello")
+            .build()
+            .unwrap();
+        let item = mailer.queue_email(email).await.unwrap();
+
+        // Provider A fails, so the item is due again right away for provider B
+        let result = mailer.process_queue(10).await;
+        assert_eq!((result.sent, result.deferred), (0, 1));
+        let rerouted = mailer.queue().get(item.id).await.unwrap();
+        assert!(rerouted.tried_providers.contains(PRIMARY_PROVIDER));
+        assert!(rerouted.next_retry_at.is_none());
+
+        assert_eq!(mailer.process_queue(10).await.sent, 1);
+        let sent = mailer.queue().get(item.id).await.unwrap();
+        assert_eq!(sent.status, QueueStatus::Sent);
+        let logs = mailer.logs().get_for_recipient("user@example.com").await;
+        assert!(logs.iter().any(|log| log.event == EmailEvent::Sent && log.provider == "backup"));
+    }
+
+    #[tokio::test]
+    async fn test_render_string() {
+        let service = TemplateService::new();
+        let data = serde_json::json!({"name": "ada"});
+
+        let rendered = service.render_string("Hi {{uppercase name}}", &data).await.unwrap();
+        assert_eq!(rendered, "Hi ADA");
+
+        assert!(service.render_string("{{#if}}", &data).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_mailer_halt() {
+        use crate::services::mailer::{MailerConfig, MailerError};
+
+        let mailer = MailerService::new();
+        mailer.set_transport(AcceptingTransport).await;
+        mailer.configure(MailerConfig {
+            default_from: Some(EmailAddress::new("noreply@example.com")),
+            ..Default::default()
+        }).await;
+        let email = || EmailBuilder::new()
+            .from("noreply@example.com")
+            .to("user@example.com")
+            .subject("Hi")
+            .text("Hello")
+            .build()
+            .unwrap();
+
+        let queued = mailer.queue_email(email()).await.unwrap();
+        mailer.halt("template bug").await;
+        assert!(mailer.is_halted().await);
+
+        let err = mailer.send(email()).await.unwrap_err();
+        assert!(matches!(err, MailerError::Halted(ref reason) if reason == "template bug"));
+        assert!(matches!(mailer.deliver(email()).await, Err(MailerError::Halted(_))));
+        assert!(matches!(mailer.queue_email(email()).await, Err(MailerError::Halted(_))));
+
+        // Queued mail waits out the halt
+        assert_eq!(mailer.process_queue(10).await.sent, 0);
+        assert_eq!(mailer.queue().get(queued.id).await.unwrap().status, QueueStatus::Pending);
+
+        mailer.resume().await;
+        assert!(mailer.send(email()).await.is_ok());
+        assert_eq!(mailer.process_queue(10).await.sent, 1);
+    }
+
+    #[tokio::test]
+    async fn test_empty_body_rejected() {
+        let mailer = MailerService::new();
+
+        let mut email = EmailBuilder::new()
+            .from("sender@example.com")
+            .to("recipient@example.com")
+            .subject("Hello")
+            .text("placeholder")
+            .build()
+            .unwrap();
+        email.text_body = Some("   ".to_string());
+
+        let err = mailer.deliver(email.clone()).await.unwrap_err();
+        assert!(matches!(&err, services::mailer::MailerError::Invalid(msg) if msg == "empty body"));
+
+        email.html_body = Some("<p>Hi</p>".to_string());
+        mailer.deliver(email).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_send_template_test() {
+        use crate::services::mailer::MailerConfig;
+
+        let transport = RecordingTransport::default();
+        let sent = std::sync::Arc::clone(&transport.0);
+        let mailer = MailerService::new();
+        mailer.set_transport(transport).await;
+        mailer.configure(MailerConfig {
+            default_from: Some(EmailAddress::new("noreply@example.com")),
+            ..Default::default()
+        }).await;
+        mailer.templates().register_system_templates().await;
+
+        // The admin has unsubscribed, but test sends still reach them
+        mailer.logs().add_to_suppression("admin@example.com", crate::services::log::SuppressionReason::Manual, None).await;
+
+        let outcome = mailer.send_template_test("welcome", EmailAddress::new("admin@example.com")).await.unwrap();
+        assert!(matches!(outcome.disposition, services::mailer::DeliveryDisposition::Sent { .. }));
+        let email = sent.lock().unwrap().remove(0);
+        assert_eq!(email.subject, "[TEST] Welcome to Example Site!");
+        assert!(email.text_body.unwrap().contains("Welcome Jane Doe!"));
+        assert!(email.html_body.unwrap().contains("/login"));
+
+        // Regular sends remain suppressed
+        let regular = mailer.send_template(
+            "welcome",
+            EmailAddress::new("admin@example.com"),
+            serde_json::json!({"user_name": "Admin", "site_name": "Example Site"}),
+            None,
+        ).await;
+        assert!(matches!(regular, Err(services::mailer::MailerError::Suppressed(_))));
+
+        // Email metadata can't switch the check off
+        let email = EmailBuilder::new()
+            .from("noreply@example.com")
+            .to("admin@example.com")
+            .subject("Hi")
+            .text("Hello")
+            .meta("test_send", "true")
+            .build()
+            .unwrap();
+        assert!(matches!(mailer.deliver(email).await, Err(services::mailer::MailerError::Suppressed(_))));
+        assert!(sent.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_log_entries_capture_email_metadata() {
+        use crate::services::mailer::MailerConfig;
+
+        let mailer = MailerService::new();
+        let email = || EmailBuilder::new()
+            .from("shop@example.com")
+            .to("buyer@example.com")
+            .subject("Order confirmed")
+            .text("Thanks!")
+            .meta("order_id", "123")
+            .tag("orders")
+            .build()
+            .unwrap();
+
+        let sent = email();
+        mailer.deliver(sent.clone()).await.unwrap();
+        let logs = mailer.logs().get_for_email(sent.id).await;
+        assert_eq!(logs.len(), 1);
+        assert_eq!(logs[0].metadata["order_id"], "123");
+        assert_eq!(logs[0].metadata[models::TAGS_META], serde_json::json!(["orders"]));
+
+        mailer.configure(MailerConfig {
+            log_email_metadata: false,
+            ..Default::default()
+        }).await;
+        let unlogged = email();
+        mailer.deliver(unlogged.clone()).await.unwrap();
+        let logs = mailer.logs().get_for_email(unlogged.id).await;
+        assert!(logs[0].metadata.is_null());
+    }
+
+    #[tokio::test]
+    async fn test_template_reply_to() {
+        use crate::services::mailer::{MailerConfig, TemplateRecipients};
+
+        let mailer = MailerService::new();
+        mailer.configure(MailerConfig {
+            default_from: Some(EmailAddress::new("noreply@example.com")),
+            default_reply_to: Some(EmailAddress::new("help@example.com")),
+            ..Default::default()
+        }).await;
+        let support = TemplateBuilder::new()
+            .name("ticket")
+            .subject("Ticket update")
+            .text("Reply to this email.")
+            .reply_to("support@example.com")
+            .build()
+            .unwrap();
+        let plain = TemplateBuilder::new()
+            .name("notice")
+            .subject("Notice")
+            .text("Hi")
+            .build()
+            .unwrap();
+        mailer.templates().register(support).await.unwrap();
+        mailer.templates().register(plain).await.unwrap();
+
+        async fn queued_reply_to(mailer: &MailerService, outcome: services::mailer::DeliveryOutcome) -> (Option<String>, Option<String>) {
+            let item = mailer.queue().get(outcome.queue_id().unwrap()).await.unwrap();
+            let mime = mailer.render_mime(&item.email).await.unwrap();
+            let header = mime.lines().find(|l| l.starts_with("Reply-To:")).map(|l| l.to_string());
+            (item.email.reply_to.map(|a| a.email), header)
+        }
+        let to = || EmailAddress::new("user@example.com");
+        let data = || serde_json::json!({});
+
+        // Template beats config
+        let outcome = mailer.send_template("ticket", to(), data(), None).await.unwrap();
+        let (address, header) = queued_reply_to(&mailer, outcome).await;
+        assert_eq!(address.as_deref(), Some("support@example.com"));
+        assert_eq!(header.as_deref(), Some("Reply-To: support@example.com"));
+
+        // Explicit beats template
+        let recipients = TemplateRecipients::new(to()).reply_to("agent@example.com");
+        let outcome = mailer.send_template_with("ticket", recipients, data(), None).await.unwrap();
+        assert_eq!(queued_reply_to(&mailer, outcome).await.0.as_deref(), Some("agent@example.com"));
+
+        // Config when the template has none
+        let outcome = mailer.send_template("notice", to(), data(), None).await.unwrap();
+        assert_eq!(queued_reply_to(&mailer, outcome).await.0.as_deref(), Some("help@example.com"));
+    }
+
+    #[tokio::test]
+    async fn test_backlog_pressure() {
+        let service = std::sync::Arc::new(QueueService::new());
+        let email = || EmailBuilder::new()
+            .from("noreply@example.com")
+            .to("user@example.com")
+            .subject("Hi")
+            .text("Hello")
+            .build()
+            .unwrap();
+
+        assert_eq!(service.backlog_pressure().await, 0.0);
+
+        let mut ids = Vec::new();
+        for _ in 0..40 {
+            ids.push(service.enqueue(email()).await.unwrap().id);
+        }
+
+        // 10 sends in the 15 minute window, 30 still pending
+        for id in &ids[..10] {
+            service.claim(*id, "worker").await.unwrap();
+            service.mark_sent(*id).await.unwrap();
+        }
+        let now = chrono::Utc::now();
+        let minutes = service.backlog_pressure_at(now).await;
+        assert!((minutes - 45.0).abs() < 1e-9, "got {}", minutes);
+
+        // Once the sends age out of the window, a stalled queue is assumed
+        // to manage one send per window
+        let later = now + chrono::Duration::minutes(services::queue::BACKLOG_WINDOW_MINUTES + 1);
+        assert!((service.backlog_pressure_at(later).await - 450.0).abs() < 1e-9);
+
+        let stats = QueueHandler::new(service).stats().await;
+        assert!(stats.backlog_minutes > 0.0);
+    }
+
+    #[test]
+    fn test_empty_parts_not_built() {
+        let transport = SmtpTransport::new(SmtpConfig::default());
+        let email = || Email::new(
+            EmailAddress::new("sender@example.com"),
+            EmailAddress::new("recipient@example.com"),
+            "Hello",
+        );
+
+        // Empty text only
+        let mut text_only = email();
+        text_only.text_body = Some(String::new());
+        assert!(matches!(transport.build_message(&text_only), Err(services::smtp::SmtpError::InvalidEmail(_))));
+
+        // Empty HTML only
+        let mut html_only = email();
+        html_only.html_body = Some(" \n".to_string());
+        assert!(matches!(transport.build_message(&html_only), Err(services::smtp::SmtpError::InvalidEmail(_))));
+
+        // An empty text part is dropped from the alternative
+        let mut mixed = email();
+        mixed.text_body = Some(String::new());
+        mixed.html_body = Some("<p>Hi</p>".to_string());
+        let formatted = String::from_utf8(transport.build_message(&mixed).unwrap().formatted()).unwrap();
+        assert!(!formatted.contains("multipart/alternative"));
+        assert!(!formatted.contains("text/plain"));
+        assert!(formatted.contains("text/html"));
+    }
+
+    #[test]
+    fn test_calendar_invite_part() {
+        let transport = SmtpTransport::new(SmtpConfig::default());
+        let ics = "BEGIN:VCALENDAR\r\nVERSION:2.0\r\nMETHOD:REQUEST\r\nBEGIN:VEVENT\r\nSUMMARY:Launch\r\nEND:VEVENT\r\nEND:VCALENDAR\r\n";
+        let email = Email::new(
+            EmailAddress::new("events@example.com"),
+            EmailAddress::new("guest@example.com"),
+            "Launch invite",
+        )
+        .text("You're invited")
+        .html("<p>You're invited</p>")
+        .with_calendar(ics.to_string(), "request");
+
+        let formatted = String::from_utf8(transport.build_message(&email).unwrap().formatted()).unwrap();
+        assert!(formatted.contains("multipart/alternative"));
+        assert!(formatted.contains("text/plain"));
+        assert!(formatted.contains("text/html"));
+        assert!(formatted.contains("Content-Type: text/calendar; charset=utf-8; method=REQUEST"));
+        assert!(formatted.contains("SUMMARY:Launch"));
+
+        let bad = email.with_calendar(ics.to_string(), "REQUEST; x=y");
+        assert!(matches!(transport.build_message(&bad), Err(services::smtp::SmtpError::InvalidEmail(_))));
+    }
+
+    #[tokio::test]
+    async fn test_embed_data_uri_images() {
+        use crate::services::mailer::MailerConfig;
+
+        let mailer = MailerService::new();
+        mailer.configure(MailerConfig {
+            embed_data_uri_images: true,
+            ..Default::default()
+        }).await;
+
+        let png = [0x89u8, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A];
+        let encoded = base64::Engine::encode(&base64::engine::general_purpose::STANDARD, png);
+        let email = EmailBuilder::new()
+            .from("sender@example.com")
+            .to("recipient@example.com")
+            .subject("Newsletter")
+            .html(&format!(r#"<p>Hi</p><img alt="logo" src="data:image/png;base64,{}">"#, encoded))
+            .build()
+            .unwrap();
+
+        let outcome = mailer.deliver(email).await.unwrap();
+        let item = mailer.queue().get(outcome.queue_id().unwrap()).await.unwrap();
+
+        assert_eq!(item.email.attachments.len(), 1);
+        let attachment = &item.email.attachments[0];
+        assert!(attachment.is_inline());
+        assert_eq!(attachment.content_type, "image/png");
+        assert_eq!(attachment.size(), png.len());
+
+        let cid = attachment.content_id.as_deref().unwrap();
+        let html = item.email.html_body.as_deref().unwrap();
+        assert!(html.contains(&format!(r#"src="cid:{}""#, cid)), "{}", html);
+        assert!(!html.contains("data:"));
+    }
+
+    #[tokio::test]
+    async fn test_email_handler_reports_disposition() {
+        use crate::handlers::email::SendEmailRequest;
+        use crate::services::mailer::MailerConfig;
+
+        let mailer = std::sync::Arc::new(MailerService::new());
+        mailer.set_transport(AcceptingTransport).await;
+        let handler = EmailHandler::new(std::sync::Arc::clone(&mailer));
+
+        let request = || -> SendEmailRequest {
+            serde_json::from_value(serde_json::json!({
+                "to": ["user@example.com"],
+                "subject": "Hello",
+                "text_body": "Hi there",
+            })).unwrap()
+        };
+
+        for queue_by_default in [true, false] {
+            mailer.configure(MailerConfig {
+                default_from: Some(EmailAddress::new("noreply@example.com")),
+                queue_by_default,
+                ..Default::default()
+            }).await;
+
+            let response = handler.send(request()).await.unwrap();
+            assert!(response.success);
+            assert!(response.email_id.is_some());
+
+            if queue_by_default {
+                assert_eq!(response.message, "Email queued for delivery");
+                let queue_id = response.queue_id.unwrap().parse().unwrap();
+                assert!(mailer.queue().get(queue_id).await.is_some());
+            } else {
+                assert_eq!(response.message, "Email sent");
+                assert!(response.queue_id.is_none());
+            }
+        }
+
+        assert_eq!(mailer.queue().stats().await.pending, 1);
+    }
+
+    #[tokio::test]
+    async fn test_global_headers() {
+        use crate::services::mailer::MailerConfig;
+
+        let mailer = MailerService::new();
+        mailer.configure(MailerConfig {
+            global_headers: std::collections::HashMap::from([
+                ("X-Mailer".to_string(), "RustMail".to_string()),
+                ("X-Environment".to_string(), "production".to_string()),
+            ]),
+            ..Default::default()
+        }).await;
+
+        let email = EmailBuilder::new()
+            .from("sender@example.com")
+            .to("recipient@example.com")
+            .subject("Hello")
+            .text("Body")
+            .header("x-environment", "staging")
+            .build()
+            .unwrap();
+
+        let outcome = mailer.deliver(email).await.unwrap();
+        let queued = mailer.queue().get(outcome.queue_id().unwrap()).await.unwrap().email;
+
+        let mime = mailer.render_mime(&queued).await.unwrap();
+        assert!(mime.contains("X-Mailer: RustMail\r\n"), "{}", mime);
+        assert!(mime.contains("staging"), "{}", mime);
+        assert!(!mime.contains("production"), "{}", mime);
+    }
+
+    #[tokio::test]
+    async fn test_custom_headers_in_mime() {
+        let mailer = MailerService::new();
+
+        let email = EmailBuilder::new()
+            .from("sender@example.com")
+            .to("recipient@example.com")
+            .subject("Hello")
+            .text("Body")
+            .header("X-Campaign", "spring-sale")
+            .priority(EmailPriority::Urgent)
+            .build()
+            .unwrap();
+
+        let mime = mailer.render_mime(&email).await.unwrap();
+        assert!(mime.contains("X-Campaign: spring-sale\r\n"), "{}", mime);
+        assert!(mime.contains("X-Priority: 1\r\n"), "{}", mime);
+
+        let mut invalid = email.clone();
+        invalid.headers.insert("X Bad Name".to_string(), "value".to_string());
+        assert!(mailer.render_mime(&invalid).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_render_mime() {
+        let mailer = MailerService::new();
+
+        let email = EmailBuilder::new()
+            .from("sender@example.com")
+            .to("recipient@example.com")
+            .subject("Invoice")
+            .text("See attached")
+            .html("<p>See attached</p>")
+            .attach(Attachment::new("invoice.pdf", "application/pdf", b"%PDF-1.4".to_vec()))
+            .build()
+            .unwrap();
+
+        let mime = mailer.render_mime(&email).await.unwrap();
+        assert!(mime.contains("Subject: Invoice"));
+        assert!(mime.contains("Content-Type: multipart/mixed;"));
+        assert!(mime.contains("boundary="));
+        assert!(mime.contains("filename=\"invoice.pdf\""));
+    }
+
+    #[tokio::test]
+    async fn test_threading_headers() {
+        let mailer = MailerService::new();
+
+        let email = EmailBuilder::new()
+            .from("sender@example.com")
+            .to("recipient@example.com")
+            .subject("Re: Your order")
+            .text("Thanks")
+            .in_reply_to("<reply-2@example.com>")
+            .reference("root-1@example.com")
+            .reference("<reply-2@example.com>")
+            .build()
+            .unwrap();
+
+        let mime = mailer.render_mime(&email).await.unwrap();
+        assert!(mime.contains("In-Reply-To: <reply-2@example.com>\r\n"), "{}", mime);
+        assert!(mime.contains("References: <root-1@example.com> <reply-2@example.com>\r\n"), "{}", mime);
+    }
+
+    /// Decode the RFC 2047 encoded words of a (possibly folded) header
+    fn decode_header(mime: &str, name: &str) -> String {
+        use base64::Engine;
+
+        let mime = format!("\r\n{}", mime);
+        let start = mime.find(&format!("\r\n{}: ", name)).unwrap() + name.len() + 4;
+        let mut value = String::new();
+        for (i, line) in mime[start..].split("\r\n").enumerate() {
+            if i > 0 && !line.starts_with([' ', '\t']) {
+                break;
+            }
+            value.push_str(line.trim());
+            value.push(' ');
+        }
+
+        let re = regex::Regex::new(r"=\?(?i:utf-8)\?[bB]\?([^?]*)\?=").unwrap();
+        let mut decoded = Vec::new();
+        let mut last = 0;
+        for caps in re.captures_iter(&value) {
+            let m = caps.get(0).unwrap();
+            let between = &value[last..m.start()];
+            if !between.trim().is_empty() {
+                decoded.extend_from_slice(between.as_bytes());
+            }
+            decoded.extend(base64::engine::general_purpose::STANDARD.decode(&caps[1]).unwrap());
+            last = m.end();
+        }
+        decoded.extend_from_slice(value[last..].trim_end().as_bytes());
+        String::from_utf8(decoded).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_non_ascii_headers_encoded() {
+        let mailer = MailerService::new();
+
+        let email = EmailBuilder::new()
+            .from_name("sender@example.com", "José Müller")
+            .to("recipient@example.com")
+            .subject("Résumé 📄")
+            .text("Attached")
+            .build()
+            .unwrap();
+
+        let mime = mailer.render_mime(&email).await.unwrap();
+        let headers = &mime[..mime.find("\r\n\r\n").unwrap()];
+        assert!(headers.is_ascii(), "{}", headers);
+        assert!(headers.to_lowercase().contains("=?utf-8?b?"), "{}", headers);
+
+        assert_eq!(decode_header(&mime, "Subject"), "Résumé 📄");
+        assert_eq!(decode_header(&mime, "From"), "José Müller <sender@example.com>");
+    }
+
+    #[tokio::test]
+    async fn test_send_timeout() {
+        let mailer = MailerService::new();
+        mailer.set_transport(StalledTransport).await;
+
+        let email = EmailBuilder::new()
+            .from("test@example.com")
+            .to("recipient@example.com")
+            .subject("Test")
+            .text("Body")
+            .build()
+            .unwrap();
+
+        let started = std::time::Instant::now();
+        let err = mailer.send(email).await.unwrap_err();
+
+        assert!(started.elapsed() < std::time::Duration::from_secs(2));
+        assert!(matches!(
+            &err,
+            services::mailer::MailerError::Smtp(services::smtp::SmtpError::Send(msg)) if msg == "timed out"
+        ));
+        assert!(RetryPolicy::default().is_retryable(&err.to_string()));
+    }
+
+    #[test]
+    fn test_parse_queued_id() {
+        use crate::services::smtp::parse_queued_id;
+
+        // Postfix
+        assert_eq!(parse_queued_id("250 2.0.0 Ok: queued as 4Z1xYz0abcD").as_deref(), Some("4Z1xYz0abcD"));
+        assert_eq!(parse_queued_id("2.0.0 Ok: queued as 4Z1xYz0abcD").as_deref(), Some("4Z1xYz0abcD"));
+        // SendGrid
+        assert_eq!(parse_queued_id("250 Ok: queued as Xk3dP9qLQ2mZ5w8HqvTrAg").as_deref(), Some("Xk3dP9qLQ2mZ5w8HqvTrAg"));
+        // Amazon SES
+        assert_eq!(
+            parse_queued_id("250 Ok 0100018c2f4e6b7a-1c2d3e4f-5a6b-7c8d-9e0f-a1b2c3d4e5f6-000000").as_deref(),
+            Some("0100018c2f4e6b7a-1c2d3e4f-5a6b-7c8d-9e0f-a1b2c3d4e5f6-000000")
+        );
+        // Angle-bracketed id
+        assert_eq!(parse_queued_id("250 2.0.0 OK <abc123@mail.example.com>").as_deref(), Some("abc123@mail.example.com"));
+        // Nothing usable
+        assert_eq!(parse_queued_id("250 2.0.0 Message accepted for delivery"), None);
+    }
+
+    /// Minimal plaintext SMTP server accepting every command; returns its port
+    async fn serve_smtp() -> u16 {
+        serve_smtp_dropping(0).await
+    }
+
+    /// Like [`serve_smtp`], but hangs up on the first `drops` MAIL commands
+    async fn serve_smtp_dropping(drops: usize) -> u16 {
+        spawn_smtp(drops, &[], Default::default()).await
+    }
+
+    /// Like [`serve_smtp`], advertising `extensions` and recording every
+    /// command the client sends
+    async fn serve_smtp_recording(
+        extensions: &'static [&'static str],
+    ) -> (u16, std::sync::Arc<std::sync::Mutex<Vec<String>>>) {
+        let commands = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+        (spawn_smtp(0, extensions, std::sync::Arc::clone(&commands)).await, commands)
+    }
+
+    async fn spawn_smtp(
+        drops: usize,
+        extensions: &'static [&'static str],
+        commands: std::sync::Arc<std::sync::Mutex<Vec<String>>>,
+    ) -> u16 {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let drops = std::sync::Arc::new(AtomicUsize::new(drops));
+        let ehlo: String = std::iter::once("localhost")
+            .chain(extensions.iter().copied())
+            .chain(std::iter::once("8BITMIME"))
+            .enumerate()
+            .map(|(i, line)| format!("250{}{}\r\n", if i == extensions.len() + 1 { ' ' } else { '-' }, line))
+            .collect();
+
+        tokio::spawn(async move {
+            while let Ok((socket, _)) = listener.accept().await {
+                let drops = std::sync::Arc::clone(&drops);
+                let commands = std::sync::Arc::clone(&commands);
+                let ehlo = ehlo.clone();
+                tokio::spawn(async move {
+                    let (read, mut write) = socket.into_split();
+                    let mut lines = BufReader::new(read).lines();
+                    write.write_all(b"220 localhost ESMTP\r\n").await.unwrap();
+                    let mut in_data = false;
+                    while let Ok(Some(line)) = lines.next_line().await {
+                        if in_data {
+                            if line == "." {
+                                in_data = false;
+                                write.write_all(b"250 2.0.0 Ok: queued as 4Z1xYz0abcD\r\n").await.unwrap();
+                            }
+                            continue;
+                        }
+                        commands.lock().unwrap().push(line.clone());
+                        let command = line.to_ascii_uppercase();
+                        if command.starts_with("MAIL")
+                            && drops.fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| n.checked_sub(1)).is_ok()
+                        {
+                            break;
+                        }
+                        let reply: &[u8] = match command.get(..4) {
+                            Some("EHLO") => ehlo.as_bytes(),
+                            Some("RCPT") if command.contains("@BOUNCE.EXAMPLE.COM>") => b"550 5.1.1 User unknown\r\n",
+                            Some("DATA") => {
+                                in_data = true;
+                                b"354 End data with <CR><LF>.<CR><LF>\r\n"
+                            }
+                            Some("QUIT") => b"221 Bye\r\n",
+                            _ => b"250 OK\r\n",
+                        };
+                        if write.write_all(reply).await.is_err() {
+                            break;
+                        }
+                    }
+                });
+            }
+        });
+
+        port
+    }
+
+    #[tokio::test]
+    async fn test_smtp_partial_rcpt_rejection() {
+        use crate::services::mailer::MailerConfig;
+
+        let (port, commands) = serve_smtp_recording(&[]).await;
+        let config = SmtpConfig::new("127.0.0.1", port).with_tls(TlsMode::None);
+
+        let email = EmailBuilder::new()
+            .from("sender@example.com")
+            .to("ada@example.com")
+            .to("gone@bounce.example.com")
+            .to("grace@example.com")
+            .subject("Hello")
+            .text("Body")
+            .build()
+            .unwrap();
+
+        // The refused recipient doesn't stop delivery to the others
+        let transport = SmtpTransport::new(config.clone());
+        let result = transport.send(&email).await.unwrap();
+        assert_eq!(result.code, "250");
+        assert_eq!(result.rejected, vec![
+            ("gone@bounce.example.com".to_string(), "550 5.1.1 User unknown".to_string()),
+        ]);
+        assert!(commands.lock().unwrap().iter().any(|c| c == "DATA"));
+
+        let mailer = MailerService::new();
+        mailer.configure(MailerConfig { queue_by_default: false, ..Default::default() }).await;
+        mailer.configure_smtp(config).await.unwrap();
+        let outcome = mailer.send(email).await.unwrap();
+        let states: Vec<_> = outcome.recipients.iter().map(|r| (r.email.as_str(), r.state)).collect();
+        assert_eq!(states, vec![
+            ("ada@example.com", RecipientState::Delivered),
+            ("gone@bounce.example.com", RecipientState::Bounced),
+            ("grace@example.com", RecipientState::Delivered),
+        ]);
+
+        // With every recipient refused the message isn't sent at all
+        let email = EmailBuilder::new()
+            .from("sender@example.com")
+            .to("gone@bounce.example.com")
+            .cc("lost@bounce.example.com")
+            .subject("Hello")
+            .text("Body")
+            .build()
+            .unwrap();
+        assert!(matches!(transport.send(&email).await, Err(services::smtp::SmtpError::Send(_))));
+    }
+
+    #[tokio::test]
+    async fn test_dsn_request_sets_envelope_parameters() {
+        let (port, commands) = serve_smtp_recording(&["DSN"]).await;
+        let transport = SmtpTransport::new(SmtpConfig::new("127.0.0.1", port).with_tls(TlsMode::None));
+        transport.connect().await.unwrap();
+
+        let email = EmailBuilder::new()
+            .from("sender@example.com")
+            .to("ops@example.com")
+            .cc("audit@example.com")
+            .subject("Invoice")
+            .text("Attached.")
+            .request_dsn(DsnRequest { success: true, failure: true, delay: false })
+            .build()
+            .unwrap();
+
+        let result = transport.send(&email).await.unwrap();
+        assert_eq!(result.message_id.as_deref(), Some("4Z1xYz0abcD"));
+        assert_eq!(result.code, "250");
+        assert_eq!(result.message.as_deref(), Some("2.0.0 Ok: queued as 4Z1xYz0abcD"));
+
+        let commands = commands.lock().unwrap().clone();
+        let mail = commands.iter().find(|c| c.starts_with("MAIL FROM")).unwrap();
+        assert!(mail.contains("RET=HDRS"));
+        let rcpts: Vec<&String> = commands.iter().filter(|c| c.starts_with("RCPT TO")).collect();
+        assert_eq!(rcpts.len(), 2);
+        assert!(rcpts.iter().all(|c| c.ends_with("NOTIFY=SUCCESS,FAILURE")));
+
+        // Without server support the envelope goes out without DSN parameters
+        let (port, commands) = serve_smtp_recording(&[]).await;
+        let transport = SmtpTransport::new(SmtpConfig::new("127.0.0.1", port).with_tls(TlsMode::None));
+        transport.connect().await.unwrap();
+        transport.send(&email).await.unwrap();
+        assert!(commands.lock().unwrap().iter().all(|c| !c.contains("NOTIFY")));
+    }
+
+    #[tokio::test]
+    async fn test_plugin_from_config() {
+        let port = serve_smtp().await;
+        let fixture = format!(r#"
+            default_from = "noreply@example.com"
+            default_from_name = "Example"
+            site_name = "Example Site"
+            track_opens = true
+            max_queue_size = 50
+
+            [smtp]
+            host = "127.0.0.1"
+            port = {}
+            tls = "none"
+            timeout_secs = 5
+
+            [retry_policy]
+            max_attempts = 7
+        "#, port);
+
+        let config: RustMailConfig = toml::from_str(&fixture).unwrap();
+        assert_eq!(config.retry_policy.max_attempts, 7);
+        assert_eq!(config.retry_policy.initial_delay_secs, RetryPolicy::default().initial_delay_secs);
+
+        let plugin = RustMailPlugin::from_config(config).await.unwrap();
+
+        let mailer_config = plugin.mailer().config().await;
+        assert_eq!(
+            mailer_config.default_from.map(|a| a.formatted()).as_deref(),
+            Some("Example <noreply@example.com>")
+        );
+        assert_eq!(mailer_config.site_name, "Example Site");
+        assert!(mailer_config.track_opens);
+
+        let smtp = plugin.mailer().smtp_config().await.unwrap();
+        assert_eq!(smtp.host, "127.0.0.1");
+        assert_eq!(smtp.tls, TlsMode::None);
+        assert!(plugin.test_connection().await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_subject_prefix() {
+        use crate::services::mailer::MailerConfig;
+
+        let mailer = MailerService::new();
+        mailer.configure(MailerConfig {
+            default_from: Some(EmailAddress::new("noreply@example.com")),
+            subject_prefix: Some("[STAGING]".to_string()),
+            ..Default::default()
+        }).await;
+
+        let email = EmailBuilder::new()
+            .from("noreply@example.com")
+            .to("user@example.com")
+            .subject("Your order")
+            .text("Shipped.")
+            .build()
+            .unwrap();
+
+        let first = mailer.deliver(email).await.unwrap();
+        let queued = mailer.queue().get(first.queue_id().unwrap()).await.unwrap().email;
+        assert_eq!(queued.subject, "[STAGING] Your order");
+
+        // Delivering the already prefixed email again leaves the subject alone
+        let second = mailer.deliver(queued).await.unwrap();
+        let requeued = mailer.queue().get(second.queue_id().unwrap()).await.unwrap().email;
+        assert_eq!(requeued.subject, "[STAGING] Your order");
+    }
+
+    #[tokio::test]
+    async fn test_template_syntax_checked_on_register() {
+        let broken = TemplateBuilder::new()
+            .name("broken")
+            .subject("Hello")
+            .html("{{#if premium}}<p>Thanks for upgrading</p>");
+        assert!(broken.build().unwrap_err().contains("html"));
+
+        let service = TemplateService::new();
+        let mut template = TemplateBuilder::new()
+            .name("broken")
+            .subject("Hello")
+            .html("<p>Hi</p>")
+            .build()
+            .unwrap();
+        template.html_body = Some("{{#if premium}}<p>Thanks for upgrading</p>".to_string());
+
+        let result = service.register(template).await;
+        assert!(matches!(result, Err(services::template::TemplateError::Invalid(_))));
+        assert!(service.get_by_slug("broken").await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_template_attachments() {
+        let service = TemplateService::new();
+        let template = TemplateBuilder::new()
+            .name("invoice")
+            .subject("Invoice {{number}}")
+            .html("<img src=\"cid:logo\"><p>Invoice {{number}}</p>")
+            .attachment(TemplateAttachment::bytes("logo.png", "image/png", vec![0x89, b'P', b'N', b'G']).inline("logo"))
+            .attachment(TemplateAttachment::bytes("terms.pdf", "application/pdf", b"%PDF-1.4 terms".to_vec()))
+            .build()
+            .unwrap();
+        service.register(template).await.unwrap();
+
+        let rendered = service.render_by_slug("invoice", &serde_json::json!({"number": 42})).await.unwrap();
+        let email = service.build_email(
+            rendered,
+            EmailAddress::new("billing@example.com"),
+            EmailAddress::new("user@example.com"),
+        );
+
+        assert_eq!(email.attachments.len(), 2);
+        let logo = &email.attachments[0];
+        assert_eq!(logo.content_id.as_deref(), Some("logo"));
+        assert!(logo.is_inline());
+        let terms = &email.attachments[1];
+        assert_eq!(terms.filename, "terms.pdf");
+        assert!(!terms.is_inline());
+        assert!(matches!(&terms.source, AttachmentSource::Bytes(b) if b == b"%PDF-1.4 terms"));
+    }
+
+    #[tokio::test]
+    async fn test_hidden_preheader() {
+        let service = TemplateService::new();
+        let template = TemplateBuilder::new()
+            .name("digest")
+            .subject("Weekly digest")
+            .preheader("{{count}} new posts this week")
+            .html("<html><body><p>Hello {{name}}</p></body></html>")
+            .build()
+            .unwrap();
+        service.register(template).await.unwrap();
+
+        let rendered = service.render_by_slug("digest", &serde_json::json!({"count": 3, "name": "Ada"})).await.unwrap();
+        assert_eq!(rendered.preheader.as_deref(), Some("3 new posts this week"));
+
+        let email = service.build_email(
+            rendered,
+            EmailAddress::new("news@example.com"),
+            EmailAddress::new("user@example.com"),
+        );
+        let html = email.html_body.unwrap();
+
+        // Hidden block sits inside the body, ahead of the visible content
+        assert!(html.starts_with("<html><body><div style=\"display:none;"));
+        assert!(html.contains("mso-hide:all;\">3 new posts this week</div>"));
+        assert!(html.contains("&#847;&zwnj;&nbsp;&#847;&zwnj;&nbsp;"));
+        let body = html.find("<p>Hello Ada</p>").unwrap();
+        assert!(html.find("&zwnj;").unwrap() < body);
+        assert!(html.ends_with("<p>Hello Ada</p></body></html>"));
+    }
+
+    #[test]
+    fn test_email_event_round_trip() {
+        let mut seen = std::collections::HashSet::new();
+        for event in EmailEvent::ALL {
+            assert_eq!(event.as_str().parse::<EmailEvent>().unwrap(), event);
+            assert_eq!(event.as_str().to_uppercase().parse::<EmailEvent>().unwrap(), event);
+            assert!(seen.insert(event));
+        }
+        assert_eq!("spam".parse::<EmailEvent>().unwrap(), EmailEvent::SpamComplaint);
+        assert!("Soft Bounce".parse::<EmailEvent>().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_worker_id_auto() {
+        let first = WorkerId::auto();
+        let second = WorkerId::auto();
+
+        // Same host and process, different suffix
+        let prefix = |id: &WorkerId| id.as_str().rsplit_once('-').unwrap().0.to_string();
+        assert_eq!(prefix(&first), prefix(&second));
+        assert!(prefix(&first).ends_with(&format!("-{}", std::process::id())));
+        assert_ne!(first, second);
+        assert_eq!(first.as_str().rsplit_once('-').unwrap().1.len(), 6);
+
+        let queue = QueueService::new();
+        let email = EmailBuilder::new()
+            .from("noreply@example.com")
+            .to("user@example.com")
+            .subject("Hi")
+            .text("Hello")
+            .build()
+            .unwrap();
+        let item = queue.enqueue(email).await.unwrap();
+        let claimed = queue.claim(item.id, first.as_str()).await.unwrap();
+        assert_eq!(claimed.worker_id.as_deref(), Some(first.as_str()));
+    }
+
+    #[tokio::test]
+    async fn test_log_stream() {
+        let logs = LogService::new();
+        let email_id = uuid::Uuid::now_v7();
+        for i in 0..2500 {
+            let event = if i % 3 == 0 { EmailEvent::Failed } else { EmailEvent::Sent };
+            logs.log(EmailLog::new(email_id, event, &format!("user{}@example.com", i), "Hi")).await;
+        }
+
+        let filter = LogFilter {
+            event: Some(EmailEvent::Sent),
+            limit: u32::MAX,
+            ..Default::default()
+        };
+        let mut stream = logs.stream(filter.clone());
+        let mut streamed = Vec::new();
+        while let Some(log) = stream.recv().await {
+            streamed.push(log.id);
+        }
+        let queried: Vec<_> = logs.query(filter.clone()).await.into_iter().map(|log| log.id).collect();
+        assert_eq!(streamed.len(), 1666);
+        assert_eq!(streamed, queried);
+
+        // Pagination matches query too
+        let page = LogFilter { offset: 990, limit: 20, ..filter };
+        let mut stream = logs.stream(page.clone());
+        let mut streamed = Vec::new();
+        while let Some(log) = stream.recv().await {
+            streamed.push(log.id);
+        }
+        let queried: Vec<_> = logs.query(page).await.into_iter().map(|log| log.id).collect();
+        assert_eq!(streamed, queried);
+    }
+
+    #[test]
+    fn test_tls_settings() {
+        let config = SmtpConfig::new("mail.dev.local", 587);
+        assert_eq!(config.tls_settings, TlsSettings::default());
+        assert!(!config.tls_settings.accept_invalid_certs);
+        assert_eq!(config.tls_settings.min_version, MinTlsVersion::Tls12);
+        assert_eq!(config.tls_parameters().unwrap().domain(), "mail.dev.local");
+
+        let config = config.with_tls_settings(TlsSettings {
+            min_version: MinTlsVersion::Tls12,
+            accept_invalid_certs: true,
+            sni_name: Some("smtp.internal".to_string()),
+        });
+        assert!(config.tls_settings.accept_invalid_certs);
+        assert_eq!(config.tls_parameters().unwrap().domain(), "smtp.internal");
+
+        // native-tls cannot require TLS 1.3
+        let config = config.with_tls_settings(TlsSettings {
+            min_version: MinTlsVersion::Tls13,
+            ..Default::default()
+        });
+        assert!(matches!(config.tls_parameters(), Err(services::smtp::SmtpError::Configuration(_))));
+    }
+
+    #[tokio::test]
+    async fn test_smtp_reconnect() {
+        let transport = SmtpTransport::new(SmtpConfig {
+            host: "127.0.0.1".to_string(),
+            port: serve_smtp_dropping(1).await,
+            tls: TlsMode::None,
+            timeout_secs: 5,
+            ..Default::default()
+        });
+        assert!(!transport.healthy().await);
+
+        transport.connect().await.unwrap();
+        assert!(transport.healthy().await);
+
+        let email = EmailBuilder::new()
+            .from("sender@example.com")
+            .to("recipient@example.com")
+            .subject("Hello")
+            .text("Body")
+            .build()
+            .unwrap();
+
+        // The server hangs up mid-send; the transport reconnects and retries
+        let result = transport.send(&email).await.unwrap();
+        assert!(result.is_success());
+        assert_eq!(result.message_id.as_deref(), Some("4Z1xYz0abcD"));
+    }
+
+    #[tokio::test]
+    async fn test_bytes_sent_stats() {
+        use crate::services::mailer::MailerConfig;
+
+        let mailer = MailerService::new();
+        mailer.configure(MailerConfig { queue_by_default: false, ..Default::default() }).await;
+        mailer.configure_smtp(SmtpConfig {
+            host: "127.0.0.1".to_string(),
+            port: serve_smtp().await,
+            tls: TlsMode::None,
+            timeout_secs: 5,
+            ..Default::default()
+        }).await.unwrap();
+
+        let mut expected = 0;
+        for body in ["Short", &"A much longer body. ".repeat(200)] {
+            let email = EmailBuilder::new()
+                .from("sender@example.com")
+                .to("first@example.com")
+                .to("second@example.com")
+                .subject("Volume")
+                .text(body)
+                .build()
+                .unwrap();
+            expected += mailer.render_mime(&email).await.unwrap().len() as u64;
+            mailer.send(email).await.unwrap();
+        }
+
+        let stats = mailer.logs().stats(None, None).await;
+        assert_eq!(stats.total_sent, 4);
+        assert_eq!(stats.bytes_sent, expected);
+    }
+
+    #[test]
+    fn test_named_recipients() {
+        let team = vec![
+            ("ada@example.com".to_string(), "Ada Lovelace".to_string()),
+            ("grace@example.com".to_string(), "Grace Hopper".to_string()),
+            ("alan@example.com".to_string(), "Alan Turing".to_string()),
+        ];
+
+        let email = EmailBuilder::new()
+            .from("sender@example.com")
+            .to_many_named(team)
+            .cc_many(vec![("lead@example.com", "Team Lead")])
+            .bcc_many(vec!["audit@example.com"])
+            .subject("Kickoff")
+            .text("Hello team")
+            .build()
+            .unwrap();
+
+        let to: Vec<String> = email.to.iter().map(|a| a.formatted()).collect();
+        assert_eq!(to, [
+            "Ada Lovelace <ada@example.com>",
+            "Grace Hopper <grace@example.com>",
+            "Alan Turing <alan@example.com>",
+        ]);
+        assert_eq!(email.cc[0].formatted(), "Team Lead <lead@example.com>");
+        assert_eq!(email.bcc[0].formatted(), "audit@example.com");
     }
 
     #[test]
@@ -4,6 +4,7 @@ use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 use std::collections::HashMap;
+use std::sync::LazyLock;
 
 /// Email address with optional name
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -29,6 +30,13 @@ impl EmailAddress {
         }
     }
 
+    /// Domain part of the address, if any
+    pub fn domain(&self) -> Option<&str> {
+        self.email.rsplit_once('@')
+            .map(|(_, domain)| domain)
+            .filter(|domain| !domain.is_empty())
+    }
+
     pub fn formatted(&self) -> String {
         match &self.name {
             Some(name) => format!("{} <{}>", name, self.email),
@@ -49,6 +57,13 @@ impl From<String> for EmailAddress {
     }
 }
 
+/// `(email, name)` pair
+impl From<(&str, &str)> for EmailAddress {
+    fn from((email, name): (&str, &str)) -> Self {
+        Self::with_name(email, name)
+    }
+}
+
 /// Email attachment
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Attachment {
@@ -56,12 +71,69 @@ pub struct Attachment {
     pub filename: String,
     /// MIME type
     pub content_type: String,
-    /// Content (base64 encoded for serialization)
-    pub content: Vec<u8>,
-    /// Whether to embed inline
-    pub inline: bool,
+    /// Content, in memory or read from disk at send time
+    pub source: AttachmentSource,
+    /// Whether the attachment is downloaded or shown inline
+    #[serde(default)]
+    pub disposition: Disposition,
     /// Content ID for inline attachments
     pub content_id: Option<String>,
+    /// Reference to deduplicated content in the blob store
+    #[serde(default)]
+    pub blob: Option<BlobRef>,
+}
+
+/// How a mail client should present an attachment (`Content-Disposition`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+pub enum Disposition {
+    /// Offered as a download under its filename
+    #[default]
+    Attachment,
+    /// Displayed in the message; with a Content-ID it can be referenced
+    /// from the HTML body
+    Inline,
+}
+
+/// Attachment content source
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum AttachmentSource {
+    /// Content held in memory
+    Bytes(Vec<u8>),
+    /// File read lazily when the message is built
+    Path(std::path::PathBuf),
+}
+
+/// `To` header of a broadcast, which names no visible recipient
+pub const UNDISCLOSED_RECIPIENTS: &str = "undisclosed-recipients:;";
+
+/// Default maximum size of a remote attachment download (10 MiB)
+pub const MAX_REMOTE_ATTACHMENT_BYTES: usize = 10 * 1024 * 1024;
+
+/// Quoted `src` holding a base64 `data:` URI image
+static DATA_URI_IMAGE_RE: LazyLock<regex::Regex> = LazyLock::new(|| {
+    regex::Regex::new(r#"src\s*=\s*["']data:(image/[\w.+-]+);base64,([A-Za-z0-9+/=\s]+)["']"#).unwrap()
+});
+
+/// Error fetching a remote attachment
+#[derive(Debug, thiserror::Error)]
+pub enum AttachmentError {
+    #[error("Invalid URL: {0}")]
+    InvalidUrl(String),
+    #[error("Fetch failed: {0}")]
+    Fetch(String),
+    #[error("Unexpected HTTP status: {0}")]
+    Status(u16),
+    #[error("Attachment exceeds {limit} bytes")]
+    TooLarge { limit: usize },
+}
+
+/// Reference to attachment content held in a content-addressed blob store
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BlobRef {
+    /// SHA-256 hash of the content (hex encoded)
+    pub hash: String,
+    /// Content size in bytes
+    pub size: usize,
 }
 
 impl Attachment {
@@ -69,24 +141,130 @@ impl Attachment {
         Self {
             filename: filename.to_string(),
             content_type: content_type.to_string(),
-            content,
-            inline: false,
+            source: AttachmentSource::Bytes(content),
+            disposition: Disposition::Attachment,
             content_id: None,
+            blob: None,
         }
     }
 
+    /// Create an attachment, correcting a generic content type from the
+    /// content's magic bytes
+    pub fn new_sniffed(filename: &str, content_type: &str, content: Vec<u8>) -> Self {
+        Self::new(filename, content_type, content).sniff_content_type()
+    }
+
+    /// Replace an `application/octet-stream` content type with the type
+    /// detected from the content, if it is recognised
+    pub fn sniff_content_type(mut self) -> Self {
+        if self.content_type != "application/octet-stream" {
+            return self;
+        }
+
+        let detected = match &self.source {
+            AttachmentSource::Bytes(bytes) => infer::get(bytes),
+            AttachmentSource::Path(path) => infer::get_from_path(path).ok().flatten(),
+        };
+
+        if let Some(kind) = detected {
+            self.content_type = kind.mime_type().to_string();
+        }
+        self
+    }
+
+    /// Show the attachment inline or offer it as a download
+    pub fn with_disposition(mut self, disposition: Disposition) -> Self {
+        self.disposition = disposition;
+        self
+    }
+
+    /// Whether the attachment is shown inline
+    pub fn is_inline(&self) -> bool {
+        self.disposition == Disposition::Inline
+    }
+
     pub fn inline(filename: &str, content_type: &str, content: Vec<u8>, cid: &str) -> Self {
         Self {
             filename: filename.to_string(),
             content_type: content_type.to_string(),
-            content,
-            inline: true,
+            source: AttachmentSource::Bytes(content),
+            disposition: Disposition::Inline,
             content_id: Some(cid.to_string()),
+            blob: None,
+        }
+    }
+
+    /// Download an attachment, up to [`MAX_REMOTE_ATTACHMENT_BYTES`]
+    pub async fn from_url(url: &str) -> Result<Self, AttachmentError> {
+        Self::from_url_with_limit(url, MAX_REMOTE_ATTACHMENT_BYTES).await
+    }
+
+    /// Download an attachment of at most `max_bytes`.
+    ///
+    /// The filename comes from `Content-Disposition` or the last URL path
+    /// segment, and the content type from `Content-Type` or the filename.
+    pub async fn from_url_with_limit(url: &str, max_bytes: usize) -> Result<Self, AttachmentError> {
+        let parsed = url::Url::parse(url)
+            .map_err(|e| AttachmentError::InvalidUrl(e.to_string()))?;
+        if !matches!(parsed.scheme(), "http" | "https") {
+            return Err(AttachmentError::InvalidUrl(format!("Unsupported scheme: {}", parsed.scheme())));
         }
+
+        let mut response = reqwest::get(parsed.clone()).await
+            .map_err(|e| AttachmentError::Fetch(e.to_string()))?;
+
+        if !response.status().is_success() {
+            return Err(AttachmentError::Status(response.status().as_u16()));
+        }
+
+        if response.content_length().is_some_and(|len| len as usize > max_bytes) {
+            return Err(AttachmentError::TooLarge { limit: max_bytes });
+        }
+
+        let header = |name: reqwest::header::HeaderName| {
+            response.headers().get(name)
+                .and_then(|v| v.to_str().ok())
+                .map(|v| v.to_string())
+        };
+
+        let filename = header(reqwest::header::CONTENT_DISPOSITION)
+            .and_then(|v| disposition_filename(&v))
+            .or_else(|| {
+                parsed.path_segments()
+                    .and_then(|mut segments| segments.next_back())
+                    .filter(|name| !name.is_empty())
+                    .map(|name| name.to_string())
+            })
+            .unwrap_or_else(|| "attachment".to_string());
+
+        let content_type = header(reqwest::header::CONTENT_TYPE)
+            .and_then(|v| v.split(';').next().map(|t| t.trim().to_string()))
+            .filter(|t| !t.is_empty())
+            .unwrap_or_else(|| mime_guess::from_path(&filename).first_or_octet_stream().to_string());
+
+        let mut content = Vec::new();
+        while let Some(chunk) = response.chunk().await
+            .map_err(|e| AttachmentError::Fetch(e.to_string()))?
+        {
+            if content.len() + chunk.len() > max_bytes {
+                return Err(AttachmentError::TooLarge { limit: max_bytes });
+            }
+            content.extend_from_slice(&chunk);
+        }
+
+        Ok(Self::new(&filename, &content_type, content))
     }
 
+    /// Attach a file from disk; its content is only read when sending
     pub fn from_file(path: &str) -> Result<Self, std::io::Error> {
-        let content = std::fs::read(path)?;
+        let metadata = std::fs::metadata(path)?;
+        if !metadata.is_file() {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!("Not a file: {}", path),
+            ));
+        }
+
         let filename = std::path::Path::new(path)
             .file_name()
             .and_then(|n| n.to_str())
@@ -100,14 +278,65 @@ impl Attachment {
         Ok(Self {
             filename,
             content_type,
-            content,
-            inline: false,
+            source: AttachmentSource::Path(path.into()),
+            disposition: Disposition::Attachment,
             content_id: None,
+            blob: None,
         })
     }
 
+    /// Hex encoded SHA-256 digest of the content. Stored attachments reuse
+    /// their blob hash; `None` if a file-backed attachment can't be read.
+    pub fn checksum(&self) -> Option<String> {
+        use sha2::{Digest, Sha256};
+
+        if let Some(blob) = &self.blob {
+            return Some(blob.hash.clone());
+        }
+        match &self.source {
+            AttachmentSource::Bytes(bytes) => Some(format!("{:x}", Sha256::digest(bytes))),
+            AttachmentSource::Path(path) => {
+                // Stream the file rather than loading it whole
+                let mut file = std::fs::File::open(path).ok()?;
+                let mut hasher = Sha256::new();
+                std::io::copy(&mut file, &mut hasher).ok()?;
+                Some(format!("{:x}", hasher.finalize()))
+            }
+        }
+    }
+
     pub fn size(&self) -> usize {
-        self.content.len()
+        if let Some(blob) = &self.blob {
+            return blob.size;
+        }
+
+        match &self.source {
+            AttachmentSource::Bytes(bytes) => bytes.len(),
+            AttachmentSource::Path(path) => std::fs::metadata(path)
+                .map(|m| m.len() as usize)
+                .unwrap_or(0),
+        }
+    }
+
+    /// In-memory content, if any
+    pub fn bytes(&self) -> Option<&[u8]> {
+        match &self.source {
+            AttachmentSource::Bytes(bytes) => Some(bytes),
+            AttachmentSource::Path(_) => None,
+        }
+    }
+
+    /// Read the attachment content, loading file-backed sources from disk
+    pub fn read_content(&self) -> Result<Vec<u8>, std::io::Error> {
+        match &self.source {
+            AttachmentSource::Bytes(bytes) => Ok(bytes.clone()),
+            AttachmentSource::Path(path) => std::fs::read(path),
+        }
+    }
+
+    /// Check if content has been moved to the blob store
+    pub fn is_stored(&self) -> bool {
+        self.blob.is_some() && self.bytes().is_some_and(|b| b.is_empty())
     }
 }
 
@@ -130,8 +359,66 @@ impl EmailPriority {
             Self::Urgent => "1",
         }
     }
+
+    /// Default queue priority for this level; normal mail sits at zero
+    pub fn queue_priority(self) -> i32 {
+        match self {
+            Self::Low => -1,
+            Self::Normal => 0,
+            Self::High => 1,
+            Self::Urgent => 2,
+        }
+    }
+}
+
+/// Delivery status notifications to request from the server (RFC 3461)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+pub struct DsnRequest {
+    /// Notify when the message is delivered
+    pub success: bool,
+    /// Notify when delivery fails
+    pub failure: bool,
+    /// Notify when delivery is delayed
+    pub delay: bool,
+}
+
+impl DsnRequest {
+    /// Request notifications for every outcome
+    pub fn all() -> Self {
+        Self { success: true, failure: true, delay: true }
+    }
+
+    /// Value of the `NOTIFY` RCPT parameter
+    pub fn notify_value(&self) -> String {
+        let conditions: Vec<&str> = [
+            (self.success, "SUCCESS"),
+            (self.failure, "FAILURE"),
+            (self.delay, "DELAY"),
+        ]
+        .into_iter()
+        .filter_map(|(on, name)| on.then_some(name))
+        .collect();
+
+        if conditions.is_empty() {
+            "NEVER".to_string()
+        } else {
+            conditions.join(",")
+        }
+    }
 }
 
+/// iCalendar invite sent as a `text/calendar` alternative
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CalendarInvite {
+    /// iCalendar (`.ics`) content
+    pub ics: String,
+    /// iTIP method, e.g. `REQUEST` or `CANCEL`
+    pub method: String,
+}
+
+/// Error message for CR/LF found in a header field
+pub const HEADER_INJECTION: &str = "header injection detected";
+
 /// Email message
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Email {
@@ -141,6 +428,12 @@ pub struct Email {
     pub from: EmailAddress,
     /// Reply-to address
     pub reply_to: Option<EmailAddress>,
+    /// Message-ID this email replies to (`In-Reply-To`)
+    #[serde(default)]
+    pub in_reply_to: Option<String>,
+    /// Message-IDs of the thread (`References`)
+    #[serde(default)]
+    pub references: Vec<String>,
     /// To recipients
     pub to: Vec<EmailAddress>,
     /// CC recipients
@@ -153,12 +446,18 @@ pub struct Email {
     pub text_body: Option<String>,
     /// HTML body
     pub html_body: Option<String>,
+    /// Calendar invite sent alongside the text and HTML bodies
+    #[serde(default)]
+    pub calendar: Option<CalendarInvite>,
     /// Attachments
     pub attachments: Vec<Attachment>,
     /// Custom headers
     pub headers: HashMap<String, String>,
     /// Priority
     pub priority: EmailPriority,
+    /// Delivery status notifications to request
+    #[serde(default)]
+    pub request_dsn: Option<DsnRequest>,
     /// Template ID (if rendered from template)
     pub template_id: Option<Uuid>,
     /// Template variables used
@@ -177,15 +476,19 @@ impl Email {
             id: Uuid::now_v7(),
             from,
             reply_to: None,
+            in_reply_to: None,
+            references: vec![],
             to: vec![to],
             cc: vec![],
             bcc: vec![],
             subject: subject.to_string(),
             text_body: None,
             html_body: None,
+            calendar: None,
             attachments: vec![],
             headers: HashMap::new(),
             priority: EmailPriority::Normal,
+            request_dsn: None,
             template_id: None,
             template_data: None,
             tags: vec![],
@@ -199,6 +502,12 @@ impl Email {
         self
     }
 
+    /// Thread this email as a reply to `message_id`
+    pub fn in_reply_to(mut self, message_id: &str) -> Self {
+        self.in_reply_to = Some(message_id.to_string());
+        self
+    }
+
     pub fn add_to(mut self, address: EmailAddress) -> Self {
         self.to.push(address);
         self
@@ -229,6 +538,12 @@ impl Email {
         self
     }
 
+    /// Add an iCalendar invite so clients offer "Add to calendar"
+    pub fn with_calendar(mut self, ics: String, method: &str) -> Self {
+        self.calendar = Some(CalendarInvite { ics, method: method.to_ascii_uppercase() });
+        self
+    }
+
     pub fn header(mut self, name: &str, value: &str) -> Self {
         self.headers.insert(name.to_string(), value.to_string());
         self
@@ -239,6 +554,12 @@ impl Email {
         self
     }
 
+    /// Ask the server for delivery status notifications
+    pub fn request_dsn(mut self, dsn: DsnRequest) -> Self {
+        self.request_dsn = Some(dsn);
+        self
+    }
+
     pub fn tag(mut self, tag: &str) -> Self {
         self.tags.push(tag.to_string());
         self
@@ -254,15 +575,161 @@ impl Email {
         self.to.len() + self.cc.len() + self.bcc.len()
     }
 
-    /// Check if email has content
+    /// Whether every recipient is in BCC. Broadcasts go out with an
+    /// [`UNDISCLOSED_RECIPIENTS`] To header, so recipients can't see each
+    /// other, and are logged per BCC recipient.
+    pub fn is_broadcast(&self) -> bool {
+        self.to.is_empty() && self.cc.is_empty() && !self.bcc.is_empty()
+    }
+
+    /// Recipients that get their own log entries: the To recipients, or
+    /// every BCC recipient of a broadcast
+    pub fn logged_recipients(&self) -> &[EmailAddress] {
+        if self.is_broadcast() {
+            &self.bcc
+        } else {
+            &self.to
+        }
+    }
+
+    /// Check the email has recipients with plausible addresses and a body
+    pub fn validate(&self) -> Result<(), String> {
+        if self.recipient_count() == 0 {
+            return Err("At least one recipient is required".to_string());
+        }
+
+        for addr in self.to.iter().chain(self.cc.iter()).chain(self.bcc.iter()) {
+            let valid = addr.email.split_once('@')
+                .is_some_and(|(local, domain)| !local.is_empty() && !domain.is_empty());
+            if !valid {
+                return Err(format!("Invalid recipient address: {}", addr.email));
+            }
+        }
+
+        if !self.has_body() {
+            return Err("Email must have a body (text or HTML)".to_string());
+        }
+
+        self.check_header_injection()
+    }
+
+    /// Reject CR/LF in anything that ends up in a header, which could
+    /// otherwise smuggle extra headers (e.g. a `Bcc:`) into the message
+    pub fn check_header_injection(&self) -> Result<(), String> {
+        let addresses = std::iter::once(&self.from)
+            .chain(&self.reply_to)
+            .chain(&self.to)
+            .chain(&self.cc)
+            .chain(&self.bcc)
+            .flat_map(|a| std::iter::once(a.email.as_str()).chain(a.name.as_deref()));
+
+        let mut fields = std::iter::once(self.subject.as_str())
+            .chain(addresses)
+            .chain(self.in_reply_to.as_deref())
+            .chain(self.references.iter().map(String::as_str))
+            .chain(self.headers.iter().flat_map(|(k, v)| [k.as_str(), v.as_str()]));
+
+        if fields.any(|f| f.contains(['\r', '\n'])) {
+            return Err(HEADER_INJECTION.to_string());
+        }
+
+        Ok(())
+    }
+
+    /// Remove repeated addresses across recipient lists, keeping each in
+    /// its most visible field (to, then cc, then bcc)
+    pub fn dedupe_recipients(&mut self) {
+        let mut seen = std::collections::HashSet::new();
+
+        for list in [&mut self.to, &mut self.cc, &mut self.bcc] {
+            list.retain(|addr| seen.insert(addr.email.to_lowercase()));
+        }
+    }
+
+    /// SHA-256 over sender, recipients, subject, bodies and attachment
+    /// names and content; byte-identical emails share a hash whatever
+    /// their id
+    pub fn content_hash(&self) -> String {
+        use sha2::{Digest, Sha256};
+
+        let mut hasher = Sha256::new();
+        let addresses = std::iter::once(&self.from)
+            .chain(&self.to)
+            .chain(&self.cc)
+            .chain(&self.bcc);
+        for address in addresses {
+            hasher.update(address.email.as_bytes());
+            hasher.update([0]);
+        }
+        for field in [Some(&self.subject), self.text_body.as_ref(), self.html_body.as_ref()] {
+            hasher.update(field.map_or("", |f| f.as_str()).as_bytes());
+            hasher.update([0]);
+        }
+        // Content is identified by its digest, so this works the same before
+        // and after the bytes move into the blob store
+        for attachment in &self.attachments {
+            hasher.update(attachment.filename.as_bytes());
+            hasher.update([0]);
+            hasher.update(attachment.checksum().unwrap_or_default().as_bytes());
+            hasher.update([0]);
+        }
+        format!("{:x}", hasher.finalize())
+    }
+
+    /// Check if email has content; whitespace-only bodies don't count
     pub fn has_body(&self) -> bool {
-        self.text_body.is_some() || self.html_body.is_some()
+        [&self.text_body, &self.html_body]
+            .into_iter()
+            .any(|body| body.as_deref().is_some_and(|b| !b.trim().is_empty()))
     }
 
     /// Get total attachment size
     pub fn total_attachment_size(&self) -> usize {
         self.attachments.iter().map(|a| a.size()).sum()
     }
+
+    /// Move base64 `data:` URI images in the HTML body into inline
+    /// attachments, pointing each `src` at the new `cid:`. Returns the
+    /// number of images embedded.
+    pub fn embed_data_uri_images(&mut self) -> usize {
+        let Some(html) = &self.html_body else {
+            return 0;
+        };
+
+        let mut embedded = Vec::new();
+        let rewritten = DATA_URI_IMAGE_RE.replace_all(html, |caps: &regex::Captures| {
+            let encoded: String = caps[2].split_whitespace().collect();
+            let Ok(content) = base64::Engine::decode(&base64::engine::general_purpose::STANDARD, encoded) else {
+                return caps[0].to_string();
+            };
+
+            let content_type = &caps[1];
+            let extension = mime_guess::get_mime_extensions_str(content_type)
+                .and_then(|exts| exts.first())
+                .unwrap_or(&"bin");
+            let cid = format!("{}@rustmail", Uuid::now_v7().simple());
+            let filename = format!("image-{}.{}", embedded.len() + 1, extension);
+
+            embedded.push(Attachment::inline(&filename, content_type, content, &cid));
+            format!("src=\"cid:{}\"", cid)
+        }).into_owned();
+
+        let count = embedded.len();
+        if count > 0 {
+            self.html_body = Some(rewritten);
+            self.attachments.extend(embedded);
+        }
+        count
+    }
+}
+
+/// Extract the filename from a `Content-Disposition` header value
+fn disposition_filename(value: &str) -> Option<String> {
+    value.split(';')
+        .map(|part| part.trim())
+        .find_map(|part| part.strip_prefix("filename="))
+        .map(|name| name.trim_matches('"').to_string())
+        .filter(|name| !name.is_empty())
 }
 
 /// Email builder for fluent API
@@ -270,6 +737,8 @@ impl Email {
 pub struct EmailBuilder {
     from: Option<EmailAddress>,
     reply_to: Option<EmailAddress>,
+    in_reply_to: Option<String>,
+    references: Vec<String>,
     to: Vec<EmailAddress>,
     cc: Vec<EmailAddress>,
     bcc: Vec<EmailAddress>,
@@ -279,6 +748,7 @@ pub struct EmailBuilder {
     attachments: Vec<Attachment>,
     headers: HashMap<String, String>,
     priority: EmailPriority,
+    request_dsn: Option<DsnRequest>,
     tags: Vec<String>,
     metadata: HashMap<String, String>,
 }
@@ -303,6 +773,18 @@ impl EmailBuilder {
         self
     }
 
+    /// Set the Message-ID this email replies to
+    pub fn in_reply_to(mut self, message_id: &str) -> Self {
+        self.in_reply_to = Some(message_id.to_string());
+        self
+    }
+
+    /// Add a Message-ID to the `References` thread chain
+    pub fn reference(mut self, message_id: &str) -> Self {
+        self.references.push(message_id.to_string());
+        self
+    }
+
     pub fn to(mut self, address: impl Into<EmailAddress>) -> Self {
         self.to.push(address.into());
         self
@@ -313,16 +795,32 @@ impl EmailBuilder {
         self
     }
 
+    /// Add `(email, name)` pairs as To recipients
+    pub fn to_many_named(mut self, addresses: Vec<(impl AsRef<str>, impl AsRef<str>)>) -> Self {
+        self.to.extend(addresses.iter().map(|(email, name)| EmailAddress::with_name(email.as_ref(), name.as_ref())));
+        self
+    }
+
     pub fn cc(mut self, address: impl Into<EmailAddress>) -> Self {
         self.cc.push(address.into());
         self
     }
 
+    pub fn cc_many(mut self, addresses: Vec<impl Into<EmailAddress>>) -> Self {
+        self.cc.extend(addresses.into_iter().map(|a| a.into()));
+        self
+    }
+
     pub fn bcc(mut self, address: impl Into<EmailAddress>) -> Self {
         self.bcc.push(address.into());
         self
     }
 
+    pub fn bcc_many(mut self, addresses: Vec<impl Into<EmailAddress>>) -> Self {
+        self.bcc.extend(addresses.into_iter().map(|a| a.into()));
+        self
+    }
+
     pub fn subject(mut self, subject: &str) -> Self {
         self.subject = Some(subject.to_string());
         self
@@ -358,6 +856,12 @@ impl EmailBuilder {
         self
     }
 
+    /// Ask the server for delivery status notifications
+    pub fn request_dsn(mut self, dsn: DsnRequest) -> Self {
+        self.request_dsn = Some(dsn);
+        self
+    }
+
     pub fn tag(mut self, tag: &str) -> Self {
         self.tags.push(tag.to_string());
         self
@@ -380,24 +884,32 @@ impl EmailBuilder {
             return Err("Email must have a body (text or HTML)".to_string());
         }
 
-        Ok(Email {
+        let email = Email {
             id: Uuid::now_v7(),
             from,
             reply_to: self.reply_to,
+            in_reply_to: self.in_reply_to,
+            references: self.references,
             to: self.to,
             cc: self.cc,
             bcc: self.bcc,
             subject,
             text_body: self.text_body,
             html_body: self.html_body,
+            calendar: None,
             attachments: self.attachments,
             headers: self.headers,
             priority: self.priority,
+            request_dsn: self.request_dsn,
             template_id: None,
             template_data: None,
             tags: self.tags,
             metadata: self.metadata,
             created_at: Utc::now(),
-        })
+        };
+
+        email.check_header_injection()?;
+
+        Ok(email)
     }
 }
@@ -1,11 +1,14 @@
 //! Email Log Models
 
+use std::sync::LazyLock;
+
 use chrono::{DateTime, Utc};
+use regex::Regex;
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
 /// Email event type
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum EmailEvent {
     /// Email queued for sending
     Queued,
@@ -55,6 +58,73 @@ impl std::fmt::Display for EmailEvent {
     }
 }
 
+impl EmailEvent {
+    /// Every event type
+    pub const ALL: [EmailEvent; 13] = [
+        Self::Queued,
+        Self::Sent,
+        Self::Delivered,
+        Self::Bounced,
+        Self::SoftBounce,
+        Self::HardBounce,
+        Self::Opened,
+        Self::Clicked,
+        Self::SpamComplaint,
+        Self::Unsubscribed,
+        Self::Failed,
+        Self::Deferred,
+        Self::Cancelled,
+    ];
+
+    /// Canonical snake_case token, accepted back by `FromStr`
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Queued => "queued",
+            Self::Sent => "sent",
+            Self::Delivered => "delivered",
+            Self::Bounced => "bounced",
+            Self::SoftBounce => "soft_bounce",
+            Self::HardBounce => "hard_bounce",
+            Self::Opened => "opened",
+            Self::Clicked => "clicked",
+            Self::SpamComplaint => "spam_complaint",
+            Self::Unsubscribed => "unsubscribed",
+            Self::Failed => "failed",
+            Self::Deferred => "deferred",
+            Self::Cancelled => "cancelled",
+        }
+    }
+}
+
+/// Error parsing an unknown event token
+#[derive(Debug, thiserror::Error)]
+#[error("Unknown email event: {0}")]
+pub struct UnknownEventError(pub String);
+
+impl std::str::FromStr for EmailEvent {
+    type Err = UnknownEventError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let token = s.to_lowercase();
+        if token == "spam" {
+            return Ok(Self::SpamComplaint);
+        }
+        Self::ALL.into_iter()
+            .find(|event| event.as_str() == token)
+            .ok_or_else(|| UnknownEventError(s.to_string()))
+    }
+}
+
+/// Metadata key on `Sent` entries holding the transmitted message size
+pub const SIZE_BYTES_META: &str = "size_bytes";
+
+/// Metadata key on `Sent` entries listing each attachment's filename and
+/// SHA-256 digest
+pub const ATTACHMENT_SHA256_META: &str = "attachment_sha256";
+
+/// Metadata key listing the tags of the logged email
+pub const TAGS_META: &str = "tags";
+
 /// Email log entry
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct EmailLog {
@@ -128,6 +198,12 @@ impl EmailLog {
         self
     }
 
+    /// Raw provider response, e.g. the DSN diagnostic of a bounce
+    pub fn with_provider_response(mut self, response: &str) -> Self {
+        self.provider_response = Some(response.to_string());
+        self
+    }
+
     pub fn with_queue(mut self, queue_id: Uuid) -> Self {
         self.queue_id = Some(queue_id);
         self
@@ -149,6 +225,21 @@ impl EmailLog {
         self.click_url = Some(url.to_string());
         self
     }
+
+    pub fn with_meta(self, key: &str, value: &str) -> Self {
+        self.with_meta_value(key, serde_json::Value::String(value.to_string()))
+    }
+
+    /// Set a structured metadata value
+    pub fn with_meta_value(mut self, key: &str, value: serde_json::Value) -> Self {
+        if !self.metadata.is_object() {
+            self.metadata = serde_json::Value::Object(serde_json::Map::new());
+        }
+        if let serde_json::Value::Object(map) = &mut self.metadata {
+            map.insert(key.to_string(), value);
+        }
+        self
+    }
 }
 
 /// Log filter for queries
@@ -214,6 +305,118 @@ impl LogFilter {
             ..Default::default()
         }
     }
+
+    /// Check if a log entry matches the filter (ignoring pagination)
+    pub fn matches(&self, log: &EmailLog) -> bool {
+        // Filter by email ID
+        if let Some(email_id) = self.email_id {
+            if log.email_id != email_id {
+                return false;
+            }
+        }
+
+        // Filter by recipient
+        if let Some(ref recipient) = self.recipient {
+            if !log.recipient.to_lowercase().contains(&recipient.to_lowercase()) {
+                return false;
+            }
+        }
+
+        // Filter by event
+        if let Some(event) = self.event {
+            if log.event != event {
+                return false;
+            }
+        }
+
+        // Filter by template
+        if let Some(template_id) = self.template_id {
+            if log.template_id != Some(template_id) {
+                return false;
+            }
+        }
+
+        // Filter by provider
+        if let Some(ref provider) = self.provider {
+            if &log.provider != provider {
+                return false;
+            }
+        }
+
+        // Filter by date range
+        if let Some(from_date) = self.from_date {
+            if log.timestamp < from_date {
+                return false;
+            }
+        }
+
+        if let Some(to_date) = self.to_date {
+            if log.timestamp > to_date {
+                return false;
+            }
+        }
+
+        // Filter errors only
+        if self.errors_only && log.error.is_none() {
+            return false;
+        }
+
+        true
+    }
+}
+
+/// Personal data to mask when logs leave the system
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Redaction {
+    /// Mask the local part of recipient addresses, keeping the domain
+    pub mask_recipients: bool,
+    /// Replace subject lines
+    pub mask_subjects: bool,
+}
+
+impl Redaction {
+    /// Mask both recipients and subjects
+    pub fn all() -> Self {
+        Self { mask_recipients: true, mask_subjects: true }
+    }
+
+    /// Mask `log` in place. A masked recipient is also masked wherever it
+    /// appears in the error or provider response.
+    pub fn apply(&self, log: &mut EmailLog) {
+        if self.mask_recipients {
+            let masked = mask_email(&log.recipient);
+            for text in [&mut log.error, &mut log.provider_response].into_iter().flatten() {
+                *text = text.replace(&log.recipient, &masked);
+            }
+            log.recipient = masked;
+        }
+        if self.mask_subjects {
+            log.subject = "[redacted]".to_string();
+        }
+    }
+}
+
+/// Keep the first character of the local part and the domain,
+/// e.g. `j***@example.com`
+pub fn mask_email(email: &str) -> String {
+    match email.rsplit_once('@') {
+        Some((local, domain)) => match local.chars().next() {
+            Some(first) => format!("{}***@{}", first, domain),
+            None => format!("***@{}", domain),
+        },
+        None => "***".to_string(),
+    }
+}
+
+/// Send activity for a template
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TemplateUsage {
+    /// Template ID
+    pub template_id: Uuid,
+    /// Number of sends logged for the template
+    pub sent_count: u64,
+    /// Most recent send
+    pub last_used: DateTime<Utc>,
 }
 
 /// Log statistics
@@ -235,6 +438,8 @@ pub struct LogStats {
     pub total_unsubscribes: u64,
     /// Total failed
     pub total_failed: u64,
+    /// Bytes transmitted for sent messages (headers, bodies and attachments)
+    pub bytes_sent: u64,
     /// Delivery rate (percentage)
     pub delivery_rate: f64,
     /// Open rate (percentage)
@@ -298,6 +503,97 @@ pub enum BounceType {
     General,
 }
 
+impl BounceType {
+    /// Corresponding log event
+    pub fn event(&self) -> EmailEvent {
+        match self {
+            Self::Hard => EmailEvent::HardBounce,
+            Self::Soft => EmailEvent::SoftBounce,
+            Self::General => EmailEvent::Bounced,
+        }
+    }
+}
+
+/// Enhanced status code (`5.1.1`) with its class, subject and detail captured
+static BOUNCE_ENHANCED_RE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"\b([245])\.(\d{1,3})\.(\d{1,3})\b").unwrap()
+});
+/// Basic reply code (`550`)
+static BOUNCE_BASIC_RE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"\b([245])\d\d\b").unwrap()
+});
+/// Enhanced status code of a failure reply
+static FAILURE_ENHANCED_RE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"\b[45]\.\d{1,3}\.\d{1,3}\b").unwrap()
+});
+/// Basic reply code of a failure reply
+static FAILURE_BASIC_RE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"\b[45]\d\d\b").unwrap()
+});
+
+/// Classify a delivery failure from its SMTP reply code and message.
+///
+/// `code` may be a basic reply code (`550`) or an enhanced status code
+/// (`5.1.1`); an enhanced code found in `message` takes precedence over a
+/// basic one. Permanent failures are hard bounces and transient ones soft,
+/// except for codes where the mailbox itself is fine (full mailbox, message
+/// too large, policy rejections).
+pub fn classify_bounce(code: &str, message: &str) -> BounceType {
+    let message_lower = message.to_lowercase();
+    if message_lower.contains("mailbox full")
+        || message_lower.contains("over quota")
+        || message_lower.contains("quota exceeded")
+    {
+        return BounceType::Soft;
+    }
+
+    let found = BOUNCE_ENHANCED_RE.captures(code).or_else(|| BOUNCE_ENHANCED_RE.captures(message));
+    if let Some(caps) = found {
+        return match (&caps[1], &caps[2], &caps[3]) {
+            // Mailbox full
+            ("5", "2", "2") => BounceType::Soft,
+            // Message too large, policy/content rejections
+            ("5", "3", "4") | ("5", "7", _) => BounceType::General,
+            ("5", _, _) => BounceType::Hard,
+            ("4", _, _) => BounceType::Soft,
+            _ => BounceType::General,
+        };
+    }
+
+    match BOUNCE_BASIC_RE.find(code).or_else(|| BOUNCE_BASIC_RE.find(message)).map(|m| m.as_str()) {
+        // Exceeded storage allocation
+        Some("552") => BounceType::Soft,
+        Some(c) if c.starts_with('5') => BounceType::Hard,
+        Some(c) if c.starts_with('4') => BounceType::Soft,
+        _ => BounceType::General,
+    }
+}
+
+/// Basic SMTP reply code that opens a server reply.
+///
+/// Takes a bare reply (`451 4.7.1 Try again later`) or a send error
+/// quoting one (`SMTP error: Send error: 451 ...`). Only the start of the
+/// reply counts, so numbers elsewhere in an error, such as a port or a
+/// size, are never mistaken for a reply code.
+pub fn reply_code(error: &str) -> Option<&str> {
+    // Prefixes added by `MailerError::Smtp` and `SmtpError::Send`
+    let error = error.strip_prefix("SMTP error: ").unwrap_or(error);
+    let reply = error.strip_prefix("Send error: ").unwrap_or(error);
+
+    let code = reply.get(..3)?;
+    let is_code = matches!(code.as_bytes(), [b'2'..=b'5', b'0'..=b'9', b'0'..=b'9'])
+        && reply[3..].chars().next().is_none_or(|c| c == ' ' || c == '-');
+    is_code.then_some(code)
+}
+
+/// Extract an SMTP status code (enhanced if available, otherwise basic)
+/// from a provider response
+pub fn extract_status_code(message: &str) -> Option<String> {
+    FAILURE_ENHANCED_RE.find(message)
+        .or_else(|| FAILURE_BASIC_RE.find(message))
+        .map(|m| m.as_str().to_string())
+}
+
 impl BounceRecord {
     pub fn new(email: &str, bounce_type: BounceType) -> Self {
         let now = Utc::now();
@@ -4,8 +4,10 @@ pub mod email;
 pub mod template;
 pub mod queue;
 pub mod log;
+pub mod pagination;
 
 pub use email::*;
 pub use template::*;
 pub use queue::*;
 pub use log::*;
+pub use pagination::*;
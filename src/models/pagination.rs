@@ -0,0 +1,42 @@
+//! Pagination Models
+
+use serde::{Deserialize, Serialize};
+
+/// One page of results with the total number of matches
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Paginated<T> {
+    /// Items on this page
+    pub items: Vec<T>,
+    /// Number of matches before pagination
+    pub total: usize,
+    /// Page size requested
+    pub limit: usize,
+    /// Matches skipped before this page
+    pub offset: usize,
+}
+
+impl<T> Paginated<T> {
+    /// Take the page at `offset`/`limit` from all matches
+    pub fn from_matches(matches: impl IntoIterator<Item = T>, limit: usize, offset: usize) -> Self {
+        let mut total = 0;
+        let mut items = Vec::new();
+        for (index, item) in matches.into_iter().enumerate() {
+            if index >= offset && items.len() < limit {
+                items.push(item);
+            }
+            total += 1;
+        }
+
+        Self { items, total, limit, offset }
+    }
+
+    /// Convert each item, keeping the page metadata
+    pub fn map<U>(self, f: impl FnMut(T) -> U) -> Paginated<U> {
+        Paginated {
+            items: self.items.into_iter().map(f).collect(),
+            total: self.total,
+            limit: self.limit,
+            offset: self.offset,
+        }
+    }
+}
@@ -1,13 +1,16 @@
 //! Email Queue Models
 
+use std::collections::HashSet;
+
 use chrono::{DateTime, Utc};
+use rand::Rng;
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
-use super::Email;
+use super::{reply_code, Email};
 
 /// Queue item status
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, Default)]
 pub enum QueueStatus {
     /// Waiting to be sent
     #[default]
@@ -60,6 +63,15 @@ pub struct QueueItem {
     pub started_at: Option<DateTime<Utc>>,
     /// Completed at (sent or failed)
     pub completed_at: Option<DateTime<Utc>>,
+    /// Time taken by the successful send attempt
+    #[serde(default)]
+    pub send_duration_ms: Option<u64>,
+    /// Outcome for each recipient of the last send attempt
+    #[serde(default)]
+    pub recipient_statuses: Vec<RecipientStatus>,
+    /// Providers that have already failed to deliver this item
+    #[serde(default)]
+    pub tried_providers: HashSet<String>,
     /// Created timestamp
     pub created_at: DateTime<Utc>,
     /// Priority (higher = more important)
@@ -81,6 +93,9 @@ impl QueueItem {
             next_retry_at: None,
             started_at: None,
             completed_at: None,
+            send_duration_ms: None,
+            recipient_statuses: Vec::new(),
+            tried_providers: HashSet::new(),
             created_at: Utc::now(),
             priority: 0,
             worker_id: None,
@@ -107,7 +122,40 @@ impl QueueItem {
     pub fn is_ready(&self) -> bool {
         matches!(self.status, QueueStatus::Pending | QueueStatus::Deferred)
             && self.scheduled_at <= Utc::now()
-            && self.next_retry_at.map_or(true, |t| t <= Utc::now())
+            && self.next_retry_at.is_none_or(|t| t <= Utc::now())
+    }
+
+    /// When the item became (or becomes) due for processing
+    pub fn due_at(&self) -> DateTime<Utc> {
+        match self.next_retry_at {
+            Some(retry_at) => retry_at.max(self.scheduled_at),
+            None => self.scheduled_at,
+        }
+    }
+
+    /// Processing order for ready items: higher priority first, then the
+    /// longest overdue, then earliest scheduled
+    pub fn cmp_ready(&self, other: &Self) -> std::cmp::Ordering {
+        self.cmp_ready_at(other, Utc::now(), None)
+    }
+
+    /// Priority raised by one for every `aging` interval the item has
+    /// waited since it was created
+    pub fn effective_priority(&self, now: DateTime<Utc>, aging: Option<chrono::Duration>) -> i64 {
+        let boost = match aging {
+            Some(interval) if interval > chrono::Duration::zero() => {
+                ((now - self.created_at).num_milliseconds() / interval.num_milliseconds()).max(0)
+            }
+            _ => 0,
+        };
+        self.priority as i64 + boost
+    }
+
+    /// [`cmp_ready`](Self::cmp_ready) using aged priorities as of `now`
+    pub fn cmp_ready_at(&self, other: &Self, now: DateTime<Utc>, aging: Option<chrono::Duration>) -> std::cmp::Ordering {
+        other.effective_priority(now, aging).cmp(&self.effective_priority(now, aging))
+            .then(self.due_at().cmp(&other.due_at()))
+            .then(self.scheduled_at.cmp(&other.scheduled_at))
     }
 
     /// Check if can retry
@@ -125,20 +173,23 @@ impl QueueItem {
 
     /// Mark as sent
     pub fn mark_sent(&mut self) {
+        let now = Utc::now();
         self.status = QueueStatus::Sent;
-        self.completed_at = Some(Utc::now());
+        self.completed_at = Some(now);
+        self.send_duration_ms = self.started_at
+            .map(|started| (now - started).num_milliseconds().max(0) as u64);
         self.worker_id = None;
     }
 
-    /// Mark as failed
-    pub fn mark_failed(&mut self, error: &str) {
+    /// Mark as failed, retrying after `delay` if attempts remain.
+    ///
+    /// The delay comes from the queue's [`RetryPolicy`].
+    pub fn mark_failed_with_delay(&mut self, error: &str, delay: chrono::Duration) {
         self.last_error = Some(error.to_string());
         self.worker_id = None;
 
         if self.can_retry() {
             self.status = QueueStatus::Deferred;
-            // Exponential backoff: 1min, 5min, 15min, etc.
-            let delay = chrono::Duration::seconds(60 * (1 << self.attempts.min(5)));
             self.next_retry_at = Some(Utc::now() + delay);
         } else {
             self.status = QueueStatus::Failed;
@@ -146,11 +197,118 @@ impl QueueItem {
         }
     }
 
+    /// Record a failure on `provider` and make the item due again at once,
+    /// so the next untried provider can pick it up
+    pub fn mark_rerouted(&mut self, provider: &str, error: &str) {
+        self.tried_providers.insert(provider.to_string());
+        self.last_error = Some(error.to_string());
+        self.worker_id = None;
+        self.status = QueueStatus::Deferred;
+        self.next_retry_at = None;
+    }
+
+    /// Mark as failed without further retries
+    pub fn mark_failed_permanently(&mut self, error: &str) {
+        self.last_error = Some(error.to_string());
+        self.worker_id = None;
+        self.status = QueueStatus::Failed;
+        self.completed_at = Some(Utc::now());
+    }
+
     /// Cancel the queue item
     pub fn cancel(&mut self) {
         self.status = QueueStatus::Cancelled;
         self.completed_at = Some(Utc::now());
     }
+
+    /// Put the item back in line as pending with a fresh attempt budget
+    pub fn requeue(&mut self) {
+        self.requeue_at(Utc::now());
+    }
+
+    /// Like [`requeue`](Self::requeue), but due at `at` rather than now
+    pub fn requeue_at(&mut self, at: DateTime<Utc>) {
+        self.status = QueueStatus::Pending;
+        self.attempts = 0;
+        self.last_error = None;
+        self.next_retry_at = None;
+        self.scheduled_at = at;
+    }
+}
+
+/// Queue change notification
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct QueueEvent {
+    /// Queue item ID
+    pub item_id: Uuid,
+    /// Status after the change
+    pub status: QueueStatus,
+    /// Number of send attempts so far
+    pub attempts: u32,
+    /// Last error message
+    pub last_error: Option<String>,
+    /// When the change happened
+    pub timestamp: DateTime<Utc>,
+}
+
+impl QueueEvent {
+    pub fn new(item: &QueueItem) -> Self {
+        Self {
+            item_id: item.id,
+            status: item.status,
+            attempts: item.attempts,
+            last_error: item.last_error.clone(),
+            timestamp: Utc::now(),
+        }
+    }
+}
+
+/// When to send a scheduled email, resolved to UTC at enqueue time
+#[derive(Debug, Clone, PartialEq)]
+pub enum ScheduleSpec {
+    /// Send after a relative delay
+    In(chrono::Duration),
+    /// Send at an absolute time in a given timezone
+    At(DateTime<chrono_tz::Tz>),
+    /// Send at the next occurrence of `hour`:00 in the given timezone
+    NextTimeOfDay { hour: u32, tz: chrono_tz::Tz },
+}
+
+impl ScheduleSpec {
+    /// Resolve the send time in UTC relative to `now`
+    pub fn resolve(&self, now: DateTime<Utc>) -> Result<DateTime<Utc>, String> {
+        match self {
+            Self::In(delay) => Ok(now + *delay),
+            Self::At(at) => Ok(at.with_timezone(&Utc)),
+            Self::NextTimeOfDay { hour, tz } => {
+                use chrono::TimeZone;
+
+                if *hour > 23 {
+                    return Err(format!("Invalid hour: {}", hour));
+                }
+
+                let local_today = now.with_timezone(tz).date_naive();
+
+                // Check today and the following days, skipping times that
+                // fall in a DST gap
+                for offset in 0..3 {
+                    let date = local_today + chrono::Duration::days(offset);
+                    let Some(naive) = date.and_hms_opt(*hour, 0, 0) else {
+                        continue;
+                    };
+
+                    if let Some(candidate) = tz.from_local_datetime(&naive).earliest() {
+                        let candidate = candidate.with_timezone(&Utc);
+                        if candidate > now {
+                            return Ok(candidate);
+                        }
+                    }
+                }
+
+                Err(format!("Could not resolve {}:00 in {}", hour, tz))
+            }
+        }
+    }
 }
 
 /// Queue statistics
@@ -174,6 +332,158 @@ pub struct QueueStats {
     pub throughput: f64,
 }
 
+impl QueueStats {
+    /// Compute statistics over queue items as of `now`.
+    ///
+    /// Sent and failed counts, send latency and throughput cover the 24 hours
+    /// before `now`.
+    pub fn compute<'a>(items: impl IntoIterator<Item = &'a QueueItem>, now: DateTime<Utc>) -> Self {
+        let day_ago = now - chrono::Duration::hours(24);
+        let mut stats = Self::default();
+        let mut total_send_ms = 0u64;
+        let mut timed_sends = 0u64;
+
+        for item in items {
+            let recent = item.completed_at.is_some_and(|t| t > day_ago);
+
+            match item.status {
+                QueueStatus::Pending => stats.pending += 1,
+                QueueStatus::Processing => stats.processing += 1,
+                QueueStatus::Sent => {
+                    if recent {
+                        stats.sent += 1;
+                        if let Some(ms) = item.send_duration_ms {
+                            total_send_ms += ms;
+                            timed_sends += 1;
+                        }
+                    }
+                }
+                QueueStatus::Failed => {
+                    if recent {
+                        stats.failed += 1;
+                    }
+                }
+                QueueStatus::Deferred => stats.deferred += 1,
+                QueueStatus::Cancelled => {}
+            }
+        }
+
+        // Calculate rates
+        let total = stats.sent + stats.failed;
+        if total > 0 {
+            stats.success_rate = (stats.sent as f64 / total as f64) * 100.0;
+        }
+        if timed_sends > 0 {
+            stats.avg_send_time_ms = total_send_ms as f64 / timed_sends as f64;
+        }
+        stats.throughput = stats.sent as f64 / 24.0;
+
+        stats
+    }
+}
+
+/// Queue filter for queries
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct QueueFilter {
+    /// Filter by status
+    pub status: Option<QueueStatus>,
+    /// Filter by recipient (substring)
+    pub recipient: Option<String>,
+    /// Filter by subject (substring)
+    pub subject: Option<String>,
+    /// Filter by email tag (exact)
+    pub tag: Option<String>,
+    /// Scheduled at or after
+    pub scheduled_after: Option<DateTime<Utc>>,
+    /// Scheduled at or before
+    pub scheduled_before: Option<DateTime<Utc>>,
+    /// Minimum priority
+    pub min_priority: Option<i32>,
+    /// Maximum priority
+    pub max_priority: Option<i32>,
+    /// Pagination offset
+    pub offset: u32,
+    /// Page size
+    pub limit: u32,
+}
+
+impl QueueFilter {
+    pub fn new() -> Self {
+        Self {
+            limit: 50,
+            ..Default::default()
+        }
+    }
+
+    pub fn with_status(status: QueueStatus) -> Self {
+        Self {
+            status: Some(status),
+            limit: 50,
+            ..Default::default()
+        }
+    }
+
+    /// Check if an item matches the filter (ignoring pagination)
+    pub fn matches(&self, item: &QueueItem) -> bool {
+        // Filter by status
+        if let Some(status) = self.status {
+            if item.status != status {
+                return false;
+            }
+        }
+
+        // Filter by recipient
+        if let Some(ref recipient) = self.recipient {
+            let recipient = recipient.to_lowercase();
+            if !item.email.to.iter().any(|a| a.email.to_lowercase().contains(&recipient)) {
+                return false;
+            }
+        }
+
+        // Filter by subject
+        if let Some(ref subject) = self.subject {
+            if !item.email.subject.to_lowercase().contains(&subject.to_lowercase()) {
+                return false;
+            }
+        }
+
+        // Filter by tag
+        if let Some(ref tag) = self.tag {
+            if !item.email.tags.contains(tag) {
+                return false;
+            }
+        }
+
+        // Filter by schedule range
+        if let Some(after) = self.scheduled_after {
+            if item.scheduled_at < after {
+                return false;
+            }
+        }
+
+        if let Some(before) = self.scheduled_before {
+            if item.scheduled_at > before {
+                return false;
+            }
+        }
+
+        // Filter by priority range
+        if let Some(min) = self.min_priority {
+            if item.priority < min {
+                return false;
+            }
+        }
+
+        if let Some(max) = self.max_priority {
+            if item.priority > max {
+                return false;
+            }
+        }
+
+        true
+    }
+}
+
 /// Batch send request
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct BatchSendRequest {
@@ -210,8 +520,51 @@ pub struct BatchError {
     pub message: String,
 }
 
+/// Delivery state of a single recipient
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum RecipientState {
+    /// Accepted by the server
+    Delivered,
+    /// Rejected by the server with a status code
+    Bounced,
+    /// Not delivered for another reason (e.g. connection failure)
+    Failed,
+}
+
+/// Delivery outcome for one recipient of an email
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RecipientStatus {
+    /// Recipient address
+    pub email: String,
+    /// Delivery state
+    pub state: RecipientState,
+    /// Server reply or error, if not delivered
+    pub error: Option<String>,
+}
+
+/// How retry delays grow with each attempt
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BackoffStrategy {
+    /// `initial_delay_secs * multiplier^attempt`
+    Exponential { multiplier: f64 },
+    /// `initial_delay_secs + step * attempt`
+    Linear { step: u64 },
+    /// Always `initial_delay_secs`
+    Fixed,
+    /// Delay in seconds per attempt; the last entry repeats
+    Custom(Vec<u64>),
+}
+
+impl Default for BackoffStrategy {
+    fn default() -> Self {
+        Self::Exponential { multiplier: 2.0 }
+    }
+}
+
 /// Retry policy
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(from = "RetryPolicyConfig")]
 pub struct RetryPolicy {
     /// Maximum attempts
     pub max_attempts: u32,
@@ -219,10 +572,13 @@ pub struct RetryPolicy {
     pub initial_delay_secs: u64,
     /// Maximum delay in seconds
     pub max_delay_secs: u64,
-    /// Multiplier for exponential backoff
-    pub multiplier: f64,
+    /// How the delay grows between attempts
+    pub backoff: BackoffStrategy,
     /// Errors to retry on
     pub retryable_errors: Vec<String>,
+    /// Random spread applied to retry delays, as a fraction of the delay
+    /// (0.2 means ±20%); 0 keeps retries deterministic
+    pub jitter_fraction: f64,
 }
 
 impl Default for RetryPolicy {
@@ -231,27 +587,100 @@ impl Default for RetryPolicy {
             max_attempts: 3,
             initial_delay_secs: 60,
             max_delay_secs: 3600,
-            multiplier: 2.0,
+            backoff: BackoffStrategy::default(),
             retryable_errors: vec![
                 "connection".to_string(),
                 "timeout".to_string(),
+                "timed out".to_string(),
                 "temporary".to_string(),
                 "rate limit".to_string(),
             ],
+            jitter_fraction: 0.0,
+        }
+    }
+}
+
+/// Serialized [`RetryPolicy`], which also accepts the top-level
+/// `multiplier` key of configs written before [`BackoffStrategy`]
+#[derive(Deserialize)]
+#[serde(default)]
+struct RetryPolicyConfig {
+    max_attempts: u32,
+    initial_delay_secs: u64,
+    max_delay_secs: u64,
+    backoff: Option<BackoffStrategy>,
+    multiplier: Option<f64>,
+    retryable_errors: Vec<String>,
+    jitter_fraction: f64,
+}
+
+impl Default for RetryPolicyConfig {
+    fn default() -> Self {
+        let policy = RetryPolicy::default();
+        Self {
+            max_attempts: policy.max_attempts,
+            initial_delay_secs: policy.initial_delay_secs,
+            max_delay_secs: policy.max_delay_secs,
+            backoff: None,
+            multiplier: None,
+            retryable_errors: policy.retryable_errors,
+            jitter_fraction: policy.jitter_fraction,
+        }
+    }
+}
+
+impl From<RetryPolicyConfig> for RetryPolicy {
+    fn from(config: RetryPolicyConfig) -> Self {
+        let legacy = config.multiplier.map(|multiplier| BackoffStrategy::Exponential { multiplier });
+        Self {
+            max_attempts: config.max_attempts,
+            initial_delay_secs: config.initial_delay_secs,
+            max_delay_secs: config.max_delay_secs,
+            backoff: config.backoff.or(legacy).unwrap_or_default(),
+            retryable_errors: config.retryable_errors,
+            jitter_fraction: config.jitter_fraction,
         }
     }
 }
 
 impl RetryPolicy {
-    /// Calculate delay for attempt number
+    /// Calculate delay before retrying after `attempt` (0-based) failed
     pub fn get_delay(&self, attempt: u32) -> chrono::Duration {
-        let delay = (self.initial_delay_secs as f64 * self.multiplier.powi(attempt as i32)) as u64;
+        let delay = match &self.backoff {
+            BackoffStrategy::Exponential { multiplier } => {
+                (self.initial_delay_secs as f64 * multiplier.powi(attempt as i32)) as u64
+            }
+            BackoffStrategy::Linear { step } => {
+                self.initial_delay_secs.saturating_add(step.saturating_mul(attempt as u64))
+            }
+            BackoffStrategy::Fixed => self.initial_delay_secs,
+            BackoffStrategy::Custom(delays) => delays.get(attempt as usize)
+                .or(delays.last())
+                .copied()
+                .unwrap_or(self.initial_delay_secs),
+        };
         let delay = delay.min(self.max_delay_secs);
         chrono::Duration::seconds(delay as i64)
     }
 
-    /// Check if error is retryable
+    /// Randomly stretch or shrink `delay` by up to `jitter_fraction`
+    pub fn apply_jitter(&self, delay: chrono::Duration) -> chrono::Duration {
+        let fraction = self.jitter_fraction.clamp(0.0, 1.0);
+        if fraction == 0.0 {
+            return delay;
+        }
+
+        let factor = 1.0 + rand::thread_rng().gen_range(-fraction..=fraction);
+        chrono::Duration::milliseconds((delay.num_milliseconds() as f64 * factor) as i64)
+    }
+
+    /// Check if error is retryable: an SMTP 4xx reply (transient by
+    /// definition, e.g. greylisting) or one of the listed error substrings
     pub fn is_retryable(&self, error: &str) -> bool {
+        if reply_code(error).is_some_and(|code| code.starts_with('4')) {
+            return true;
+        }
+
         let error_lower = error.to_lowercase();
         self.retryable_errors.iter().any(|e| error_lower.contains(&e.to_lowercase()))
     }
@@ -1,9 +1,11 @@
 //! Email Template Models
 
 use chrono::{DateTime, Utc};
+use handlebars::template::{BlockParam, HelperTemplate, Parameter, TemplateElement};
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
-use std::collections::HashMap;
+
+use super::{Attachment, AttachmentSource, Disposition};
 
 /// Template type
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
@@ -90,6 +92,12 @@ pub struct EmailTemplate {
     pub preheader: Option<String>,
     /// Parent layout template ID
     pub layout_id: Option<Uuid>,
+    /// Whether to wrap in a layout; `Some(false)` opts out of the default layout
+    #[serde(default)]
+    pub use_layout: Option<bool>,
+    /// Layout slug, used when no layout ID is set
+    #[serde(default)]
+    pub layout_slug: Option<String>,
     /// Variable definitions
     pub variables: Vec<TemplateVariable>,
     /// Default sender address
@@ -98,6 +106,9 @@ pub struct EmailTemplate {
     pub default_reply_to: Option<String>,
     /// Tags for categorization
     pub tags: Vec<String>,
+    /// Files attached to every email built from the template
+    #[serde(default)]
+    pub attachments: Vec<TemplateAttachment>,
     /// Whether template is active
     pub active: bool,
     /// Version number
@@ -125,10 +136,13 @@ impl EmailTemplate {
             html_body: None,
             preheader: None,
             layout_id: None,
+            use_layout: None,
+            layout_slug: None,
             variables: vec![],
             default_from: None,
             default_reply_to: None,
             tags: vec![],
+            attachments: vec![],
             active: true,
             version: 1,
             created_by: None,
@@ -153,28 +167,88 @@ impl EmailTemplate {
         self
     }
 
-    /// Extract variables from template content
+    pub fn with_attachment(mut self, attachment: TemplateAttachment) -> Self {
+        self.attachments.push(attachment);
+        self
+    }
+
+    /// Render data built from each variable's example, falling back to its
+    /// default and then to a `[name]` placeholder
+    pub fn sample_data(&self) -> serde_json::Value {
+        let data: serde_json::Map<String, serde_json::Value> = self.variables.iter()
+            .map(|var| {
+                let value = var.example.clone()
+                    .or_else(|| var.default.clone())
+                    .unwrap_or_else(|| format!("[{}]", var.name));
+                (var.name.clone(), serde_json::Value::String(value))
+            })
+            .collect();
+        serde_json::Value::Object(data)
+    }
+
+    /// Compile each field with Handlebars to catch syntax errors such as
+    /// unclosed blocks before the template is ever rendered
+    pub fn check_syntax(&self) -> Result<(), String> {
+        let fields = [
+            ("subject", Some(self.subject.as_str())),
+            ("preheader", self.preheader.as_deref()),
+            ("text", self.text_body.as_deref()),
+            ("html", self.html_body.as_deref()),
+        ];
+
+        for (field, source) in fields {
+            if let Some(source) = source {
+                handlebars::Template::compile(source)
+                    .map_err(|e| format!("{} has invalid syntax: {}", field, e))?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Extract variables from template content.
+    ///
+    /// Walks the parsed templates, so this covers plain and raw
+    /// (`{{{var}}}`) expressions, the root of dotted paths, and the
+    /// arguments of helpers, blocks and subexpressions such as
+    /// `{{date created_at "%Y"}}` or `{{currency (sum items)}}`. Block
+    /// parameters, `this`, parent (`../`) and `@` paths are not variables.
     pub fn extract_variables(&self) -> Vec<String> {
         let mut vars = Vec::new();
-        let re = regex::Regex::new(r"\{\{\s*([a-zA-Z_][a-zA-Z0-9_]*)\s*\}\}").unwrap();
-
-        let content = format!(
-            "{} {} {}",
-            self.subject,
-            self.text_body.as_deref().unwrap_or(""),
-            self.html_body.as_deref().unwrap_or("")
-        );
-
-        for cap in re.captures_iter(&content) {
-            let var_name = cap[1].to_string();
-            if !vars.contains(&var_name) {
-                vars.push(var_name);
+        let sources = [
+            Some(self.subject.as_str()),
+            self.preheader.as_deref(),
+            self.text_body.as_deref(),
+            self.html_body.as_deref(),
+        ];
+
+        for source in sources.into_iter().flatten() {
+            if let Ok(template) = handlebars::Template::compile(source) {
+                collect_template_variables(&template, &[], &mut vars);
             }
         }
 
         vars
     }
 
+    /// Compare declared variables with the ones the content actually uses
+    pub fn reconcile_variables(&self) -> VariableReport {
+        let used = self.extract_variables();
+        let declared: Vec<&str> = self.variables.iter().map(|v| v.name.as_str()).collect();
+
+        VariableReport {
+            undeclared: used.iter()
+                .filter(|name| !declared.contains(&name.as_str()))
+                .cloned()
+                .collect(),
+            unused: declared.iter()
+                .filter(|name| !used.iter().any(|u| u == *name))
+                .map(|name| name.to_string())
+                .collect(),
+            used,
+        }
+    }
+
     /// Check if all required variables are provided
     pub fn validate_data(&self, data: &serde_json::Value) -> Vec<String> {
         let mut missing = Vec::new();
@@ -195,6 +269,78 @@ impl EmailTemplate {
     }
 }
 
+/// Add the variables used by `template` to `vars`, skipping `locals`
+fn collect_template_variables(template: &handlebars::Template, locals: &[String], vars: &mut Vec<String>) {
+    for element in &template.elements {
+        match element {
+            TemplateElement::Expression(helper) | TemplateElement::HtmlExpression(helper) => {
+                // A bare `{{name}}` is a variable; with arguments it's a helper call
+                if helper.params.is_empty() && helper.hash.is_empty() {
+                    collect_parameter_variables(&helper.name, locals, vars);
+                }
+                collect_helper_variables(helper, locals, vars);
+            }
+            TemplateElement::HelperBlock(helper) => {
+                collect_helper_variables(helper, locals, vars);
+
+                let mut scope = locals.to_vec();
+                let block_params = match &helper.block_param {
+                    Some(BlockParam::Single(name)) => vec![name],
+                    Some(BlockParam::Pair((first, second))) => vec![first, second],
+                    None => Vec::new(),
+                };
+                scope.extend(block_params.into_iter().filter_map(Parameter::as_name).map(str::to_string));
+
+                for inner in [&helper.template, &helper.inverse].into_iter().flatten() {
+                    collect_template_variables(inner, &scope, vars);
+                }
+            }
+            TemplateElement::DecoratorExpression(decorator)
+            | TemplateElement::DecoratorBlock(decorator)
+            | TemplateElement::PartialExpression(decorator)
+            | TemplateElement::PartialBlock(decorator) => {
+                for param in decorator.params.iter().chain(decorator.hash.values()) {
+                    collect_parameter_variables(param, locals, vars);
+                }
+                if let Some(inner) = &decorator.template {
+                    collect_template_variables(inner, locals, vars);
+                }
+            }
+            TemplateElement::RawString(_) | TemplateElement::Comment(_) => {}
+        }
+    }
+}
+
+/// Add the variables passed to a helper, positionally or by name
+fn collect_helper_variables(helper: &HelperTemplate, locals: &[String], vars: &mut Vec<String>) {
+    for param in helper.params.iter().chain(helper.hash.values()) {
+        collect_parameter_variables(param, locals, vars);
+    }
+}
+
+/// Add the root of a path, or the variables of a subexpression
+fn collect_parameter_variables(param: &Parameter, locals: &[String], vars: &mut Vec<String>) {
+    let raw = match param {
+        Parameter::Name(name) => name.as_str(),
+        Parameter::Path(handlebars::Path::Relative((_, raw))) => raw.as_str(),
+        Parameter::Path(handlebars::Path::Local(_)) | Parameter::Literal(_) => return,
+        Parameter::Subexpression(subexpression) => {
+            if let TemplateElement::Expression(helper) = subexpression.element.as_ref() {
+                collect_helper_variables(helper, locals, vars);
+            }
+            return;
+        }
+    };
+
+    let root = raw.split(['.', '/', '[']).next().unwrap_or_default();
+    if root.is_empty() || root == "this" || root.starts_with('@') || locals.iter().any(|local| local == root) {
+        return;
+    }
+    if !vars.iter().any(|var| var == root) {
+        vars.push(root.to_string());
+    }
+}
+
 /// Email layout template
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct EmailLayout {
@@ -252,10 +398,138 @@ impl EmailLayout {
     }
 }
 
+/// Declared vs used variables of a template
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct VariableReport {
+    /// Variables referenced in the content
+    pub used: Vec<String>,
+    /// Used in the content but not declared
+    pub undeclared: Vec<String>,
+    /// Declared but never used in the content
+    pub unused: Vec<String>,
+}
+
+/// Layout builder
+#[derive(Debug, Default)]
+pub struct EmailLayoutBuilder {
+    name: Option<String>,
+    html: Option<String>,
+    text: Option<String>,
+    description: Option<String>,
+    is_default: bool,
+}
+
+impl EmailLayoutBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn name(mut self, name: &str) -> Self {
+        self.name = Some(name.to_string());
+        self
+    }
+
+    pub fn html(mut self, html: &str) -> Self {
+        self.html = Some(html.to_string());
+        self
+    }
+
+    pub fn text(mut self, text: &str) -> Self {
+        self.text = Some(text.to_string());
+        self
+    }
+
+    pub fn description(mut self, desc: &str) -> Self {
+        self.description = Some(desc.to_string());
+        self
+    }
+
+    /// Use this layout for templates that don't name one
+    pub fn as_default(mut self) -> Self {
+        self.is_default = true;
+        self
+    }
+
+    pub fn build(self) -> Result<EmailLayout, String> {
+        let name = self.name.ok_or("Layout name is required")?;
+        let html = self.html.ok_or("Layout HTML is required")?;
+
+        if !html.contains("{{content}}") && !html.contains("{{{content}}}") {
+            return Err("Layout HTML must contain a {{{content}}} placeholder".to_string());
+        }
+
+        let mut layout = EmailLayout::new(&name, &html);
+        layout.text = self.text;
+        layout.description = self.description;
+        layout.is_default = self.is_default;
+        Ok(layout)
+    }
+}
+
+/// Static attachment of a template, e.g. a logo or terms of service
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TemplateAttachment {
+    /// Filename
+    pub filename: String,
+    /// MIME type
+    pub content_type: String,
+    /// Content, in memory or read from disk at send time
+    pub source: AttachmentSource,
+    /// Content ID, making the attachment an inline image
+    #[serde(default)]
+    pub content_id: Option<String>,
+}
+
+impl TemplateAttachment {
+    pub fn bytes(filename: &str, content_type: &str, content: Vec<u8>) -> Self {
+        Self {
+            filename: filename.to_string(),
+            content_type: content_type.to_string(),
+            source: AttachmentSource::Bytes(content),
+            content_id: None,
+        }
+    }
+
+    /// Attach a file read at send time, typed by its extension
+    pub fn path(path: impl Into<std::path::PathBuf>) -> Self {
+        let path = path.into();
+        let filename = path.file_name()
+            .and_then(|n| n.to_str())
+            .unwrap_or("attachment")
+            .to_string();
+        let content_type = mime_guess::from_path(&path).first_or_octet_stream().to_string();
+
+        Self {
+            filename,
+            content_type,
+            source: AttachmentSource::Path(path),
+            content_id: None,
+        }
+    }
+
+    /// Show inline under `cid`, for `<img src="cid:...">` references
+    pub fn inline(mut self, cid: &str) -> Self {
+        self.content_id = Some(cid.to_string());
+        self
+    }
+
+    /// The attachment to add to an email
+    pub fn to_attachment(&self) -> Attachment {
+        let mut attachment = Attachment::new(&self.filename, &self.content_type, Vec::new());
+        attachment.source = self.source.clone();
+        if let Some(cid) = &self.content_id {
+            attachment.content_id = Some(cid.clone());
+            attachment.disposition = Disposition::Inline;
+        }
+        attachment
+    }
+}
+
 /// Template builder
 #[derive(Debug, Default)]
 pub struct TemplateBuilder {
     name: Option<String>,
+    slug: Option<String>,
     title: Option<String>,
     description: Option<String>,
     template_type: TemplateType,
@@ -264,10 +538,13 @@ pub struct TemplateBuilder {
     html_body: Option<String>,
     preheader: Option<String>,
     layout_id: Option<Uuid>,
+    use_layout: Option<bool>,
+    layout_slug: Option<String>,
     variables: Vec<TemplateVariable>,
     default_from: Option<String>,
     default_reply_to: Option<String>,
     tags: Vec<String>,
+    attachments: Vec<TemplateAttachment>,
 }
 
 impl TemplateBuilder {
@@ -280,6 +557,12 @@ impl TemplateBuilder {
         self
     }
 
+    /// Set the slug explicitly instead of deriving it from the name
+    pub fn slug(mut self, slug: &str) -> Self {
+        self.slug = Some(slug.to_string());
+        self
+    }
+
     pub fn title(mut self, title: &str) -> Self {
         self.title = Some(title.to_string());
         self
@@ -320,6 +603,18 @@ impl TemplateBuilder {
         self
     }
 
+    /// Wrap in the layout with this slug
+    pub fn layout_slug(mut self, slug: &str) -> Self {
+        self.layout_slug = Some(slug.to_string());
+        self
+    }
+
+    /// Render without any layout, even when a default layout is registered
+    pub fn no_layout(mut self) -> Self {
+        self.use_layout = Some(false);
+        self
+    }
+
     pub fn variable(mut self, var: TemplateVariable) -> Self {
         self.variables.push(var);
         self
@@ -337,6 +632,14 @@ impl TemplateBuilder {
         self
     }
 
+    /// Set the example value of an already declared variable
+    pub fn example(mut self, name: &str, example: &str) -> Self {
+        if let Some(var) = self.variables.iter_mut().find(|v| v.name == name) {
+            var.example = Some(example.to_string());
+        }
+        self
+    }
+
     pub fn optional_var(mut self, name: &str, default: &str) -> Self {
         self.variables.push(TemplateVariable {
             name: name.to_string(),
@@ -364,6 +667,11 @@ impl TemplateBuilder {
         self
     }
 
+    pub fn attachment(mut self, attachment: TemplateAttachment) -> Self {
+        self.attachments.push(attachment);
+        self
+    }
+
     pub fn build(self) -> Result<EmailTemplate, String> {
         let name = self.name.ok_or("Template name is required")?;
         let subject = self.subject.ok_or("Subject is required")?;
@@ -372,9 +680,9 @@ impl TemplateBuilder {
             return Err("Template must have a body (text or HTML)".to_string());
         }
 
-        Ok(EmailTemplate {
+        let template = EmailTemplate {
             id: Uuid::now_v7(),
-            slug: slugify(&name),
+            slug: self.slug.unwrap_or_else(|| slugify(&name)),
             name: name.clone(),
             title: self.title.unwrap_or(name),
             description: self.description,
@@ -384,19 +692,66 @@ impl TemplateBuilder {
             html_body: self.html_body,
             preheader: self.preheader,
             layout_id: self.layout_id,
+            use_layout: self.use_layout,
+            layout_slug: self.layout_slug,
             variables: self.variables,
             default_from: self.default_from,
             default_reply_to: self.default_reply_to,
             tags: self.tags,
+            attachments: self.attachments,
             active: true,
             version: 1,
             created_by: None,
             created_at: Utc::now(),
             updated_at: Utc::now(),
-        })
+        };
+
+        template.check_syntax()?;
+
+        Ok(template)
     }
 }
 
+/// Current template bundle format version
+pub const TEMPLATE_BUNDLE_VERSION: u32 = 1;
+
+/// Portable set of templates and layouts for moving between environments
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TemplateBundle {
+    /// Bundle format version
+    pub version: u32,
+    /// When the bundle was exported
+    pub exported_at: DateTime<Utc>,
+    /// Layouts
+    pub layouts: Vec<EmailLayout>,
+    /// Templates
+    pub templates: Vec<EmailTemplate>,
+}
+
+/// How to handle slugs that already exist when importing a bundle
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+pub enum ImportMode {
+    /// Keep existing entries and skip bundled ones with the same slug
+    #[default]
+    Merge,
+    /// Replace existing entries with bundled ones with the same slug
+    Overwrite,
+}
+
+/// Outcome of a bundle import
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ImportReport {
+    pub templates_imported: usize,
+    pub templates_skipped: usize,
+    pub layouts_imported: usize,
+    pub layouts_skipped: usize,
+}
+
+/// Check a string is already in slug form
+pub fn is_slug(s: &str) -> bool {
+    !s.is_empty() && slugify(s) == s
+}
+
 /// Convert string to slug
 pub fn slugify(s: &str) -> String {
     s.to_lowercase()
@@ -1,15 +1,128 @@
 //! RustMail Plugin Entry Point
 
+use std::collections::HashMap;
 use std::sync::Arc;
+use serde::{Deserialize, Serialize};
 
-use crate::models::EmailAddress;
+use crate::models::{EmailAddress, RetryPolicy};
 use crate::services::{
-    MailerService, TemplateService, QueueService, LogService,
-    SmtpConfig, SmtpTransport,
-    mailer::{MailerConfig, ProcessResult},
+    MailerService, TemplateService, QueueService, LogService, WorkerId,
+    SmtpConfig,
+    mailer::{DeliveryOutcome, MailerConfig, ProcessResult},
 };
 use crate::handlers::{EmailHandler, TemplateHandler, QueueHandler, LogHandler};
 
+/// Complete plugin settings, loadable from TOML, JSON or environment
+/// variables through any serde deserializer.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RustMailConfig {
+    /// SMTP server; mail stays queued until one is configured
+    pub smtp: Option<SmtpConfig>,
+    /// Default from address
+    pub default_from: Option<String>,
+    /// Display name for the default from address
+    pub default_from_name: Option<String>,
+    /// Default reply-to address
+    pub default_reply_to: Option<String>,
+    /// Site name for templates
+    pub site_name: String,
+    /// Site URL for templates
+    pub site_url: String,
+    /// Track opens
+    pub track_opens: bool,
+    /// Track clicks
+    pub track_clicks: bool,
+    /// Base URL for tracking links
+    pub tracking_base_url: Option<String>,
+    /// Queue emails by default
+    pub queue_by_default: bool,
+    /// Maximum number of attachments per email
+    pub max_attachments: usize,
+    /// Verified sending domains
+    pub allowed_from_domains: Option<Vec<String>>,
+    /// Turn `data:` URI images in HTML bodies into inline attachments
+    pub embed_data_uri_images: bool,
+    /// Headers added to every email
+    pub global_headers: HashMap<String, String>,
+    /// Most emails a single recipient may be sent per day
+    pub max_per_recipient_per_day: Option<u32>,
+    /// Retry failed queue items on the next untried fallback provider
+    pub reroute_on_failure: bool,
+    /// Most sends per minute for a template, keyed by slug
+    pub template_rate_limits: HashMap<String, u32>,
+    /// Worker identity recorded on claimed queue items; generated from the
+    /// hostname and process id when unset
+    pub worker_id: Option<WorkerId>,
+    /// Prepended to every subject, e.g. `[STAGING]`
+    pub subject_prefix: Option<String>,
+    /// Copy email metadata and tags into log entries
+    pub log_email_metadata: bool,
+    /// Retry policy for failed deliveries
+    pub retry_policy: RetryPolicy,
+    /// Maximum number of items in the queue
+    pub max_queue_size: usize,
+}
+
+impl Default for RustMailConfig {
+    fn default() -> Self {
+        let mailer = MailerConfig::default();
+        Self {
+            smtp: None,
+            default_from: None,
+            default_from_name: None,
+            default_reply_to: None,
+            site_name: mailer.site_name,
+            site_url: mailer.site_url,
+            track_opens: mailer.track_opens,
+            track_clicks: mailer.track_clicks,
+            tracking_base_url: mailer.tracking_base_url,
+            queue_by_default: mailer.queue_by_default,
+            max_attachments: mailer.max_attachments,
+            allowed_from_domains: mailer.allowed_from_domains,
+            embed_data_uri_images: mailer.embed_data_uri_images,
+            global_headers: mailer.global_headers,
+            max_per_recipient_per_day: mailer.max_per_recipient_per_day,
+            reroute_on_failure: mailer.reroute_on_failure,
+            template_rate_limits: mailer.template_rate_limits,
+            worker_id: mailer.worker_id,
+            subject_prefix: mailer.subject_prefix,
+            log_email_metadata: mailer.log_email_metadata,
+            retry_policy: RetryPolicy::default(),
+            max_queue_size: 100_000,
+        }
+    }
+}
+
+impl RustMailConfig {
+    /// Mailer settings described by this config
+    pub fn mailer_config(&self) -> MailerConfig {
+        MailerConfig {
+            default_from: self.default_from.as_deref().map(|email| match &self.default_from_name {
+                Some(name) => EmailAddress::with_name(email, name),
+                None => EmailAddress::new(email),
+            }),
+            default_reply_to: self.default_reply_to.as_deref().map(EmailAddress::new),
+            site_name: self.site_name.clone(),
+            site_url: self.site_url.clone(),
+            track_opens: self.track_opens,
+            track_clicks: self.track_clicks,
+            tracking_base_url: self.tracking_base_url.clone(),
+            queue_by_default: self.queue_by_default,
+            max_attachments: self.max_attachments,
+            allowed_from_domains: self.allowed_from_domains.clone(),
+            embed_data_uri_images: self.embed_data_uri_images,
+            global_headers: self.global_headers.clone(),
+            max_per_recipient_per_day: self.max_per_recipient_per_day,
+            reroute_on_failure: self.reroute_on_failure,
+            template_rate_limits: self.template_rate_limits.clone(),
+            worker_id: self.worker_id.clone(),
+            subject_prefix: self.subject_prefix.clone(),
+            log_email_metadata: self.log_email_metadata,
+        }
+    }
+}
+
 /// RustMail Plugin
 pub struct RustMailPlugin {
     /// Mailer service
@@ -33,7 +146,26 @@ pub struct RustMailPlugin {
 impl RustMailPlugin {
     /// Create a new RustMail plugin instance
     pub fn new() -> Self {
-        let mailer = Arc::new(MailerService::new());
+        Self::with_mailer(MailerService::new())
+    }
+
+    /// Create a plugin from `config`, connecting SMTP if one is configured
+    pub async fn from_config(config: RustMailConfig) -> Result<Self, String> {
+        let queue = QueueService::new()
+            .with_retry_policy(config.retry_policy.clone())
+            .with_max_size(config.max_queue_size);
+        let plugin = Self::with_mailer(MailerService::new().with_queue(queue));
+
+        plugin.mailer.configure(config.mailer_config()).await;
+        if let Some(smtp) = config.smtp {
+            plugin.configure_smtp(smtp).await?;
+        }
+
+        Ok(plugin)
+    }
+
+    fn with_mailer(mailer: MailerService) -> Self {
+        let mailer = Arc::new(mailer);
         let template_service = Arc::clone(mailer.templates());
         let queue_service = Arc::clone(mailer.queue());
         let log_service = Arc::clone(mailer.logs());
@@ -89,8 +221,10 @@ impl RustMailPlugin {
             None => EmailAddress::new(email),
         };
 
-        let mut config = MailerConfig::default();
-        config.default_from = Some(address);
+        let config = MailerConfig {
+            default_from: Some(address),
+            ..Default::default()
+        };
         self.mailer.configure(config).await;
     }
 
@@ -151,7 +285,7 @@ impl RustMailPlugin {
         to: &str,
         subject: &str,
         body: &str,
-    ) -> Result<(), String> {
+    ) -> Result<DeliveryOutcome, String> {
         self.mailer.quick_send(to, subject, body).await.map_err(|e| e.to_string())
     }
 
@@ -161,8 +295,8 @@ impl RustMailPlugin {
         template: &str,
         to: &str,
         data: serde_json::Value,
-    ) -> Result<(), String> {
-        self.mailer.send_template(template, EmailAddress::new(to), data)
+    ) -> Result<DeliveryOutcome, String> {
+        self.mailer.send_template(template, EmailAddress::new(to), data, None)
             .await
             .map_err(|e| e.to_string())
     }
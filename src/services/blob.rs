@@ -0,0 +1,150 @@
+//! Attachment Blob Store
+//!
+//! Content-addressed storage for attachment bytes, so that identical
+//! attachments shared by many queued emails are only held in memory once.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use tokio::sync::RwLock;
+use sha2::{Digest, Sha256};
+
+use crate::models::{Attachment, AttachmentSource, BlobRef, Email};
+
+/// Blob store error
+#[derive(Debug, thiserror::Error)]
+pub enum BlobError {
+    #[error("Blob not found: {0}")]
+    NotFound(String),
+}
+
+/// Stored blob with reference count
+struct Blob {
+    content: Arc<Vec<u8>>,
+    refs: usize,
+}
+
+/// Content-addressed attachment store keyed by SHA-256
+pub struct BlobStore {
+    blobs: Arc<RwLock<HashMap<String, Blob>>>,
+}
+
+impl BlobStore {
+    pub fn new() -> Self {
+        Self {
+            blobs: Arc::new(RwLock::new(HashMap::new())),
+        }
+    }
+
+    /// Compute the hex encoded SHA-256 hash of content
+    pub fn hash(content: &[u8]) -> String {
+        format!("{:x}", Sha256::digest(content))
+    }
+
+    /// Store content and return a reference to it
+    pub async fn put(&self, content: Vec<u8>) -> BlobRef {
+        let hash = Self::hash(&content);
+        let size = content.len();
+
+        let mut blobs = self.blobs.write().await;
+        blobs.entry(hash.clone())
+            .and_modify(|blob| blob.refs += 1)
+            .or_insert_with(|| Blob {
+                content: Arc::new(content),
+                refs: 1,
+            });
+
+        BlobRef { hash, size }
+    }
+
+    /// Get content by hash
+    pub async fn get(&self, hash: &str) -> Option<Arc<Vec<u8>>> {
+        let blobs = self.blobs.read().await;
+        blobs.get(hash).map(|blob| Arc::clone(&blob.content))
+    }
+
+    /// Drop one reference to a blob, removing it when unreferenced
+    pub async fn release(&self, hash: &str) {
+        let mut blobs = self.blobs.write().await;
+
+        if let Some(blob) = blobs.get_mut(hash) {
+            blob.refs = blob.refs.saturating_sub(1);
+            if blob.refs == 0 {
+                blobs.remove(hash);
+            }
+        }
+    }
+
+    /// Move attachment content of an email into the store
+    pub async fn store_attachments(&self, email: &mut Email) {
+        for att in email.attachments.iter_mut() {
+            if att.blob.is_some() {
+                continue;
+            }
+
+            // File-backed attachments are already kept out of memory
+            let AttachmentSource::Bytes(bytes) = &mut att.source else {
+                continue;
+            };
+            if bytes.is_empty() {
+                continue;
+            }
+
+            let content = std::mem::take(bytes);
+            att.blob = Some(self.put(content).await);
+        }
+    }
+
+    /// Load stored attachment content back into an email
+    pub async fn resolve_attachments(&self, email: &mut Email) -> Result<(), BlobError> {
+        for att in email.attachments.iter_mut() {
+            self.resolve(att).await?;
+        }
+        Ok(())
+    }
+
+    /// Load stored content back into a single attachment
+    pub async fn resolve(&self, attachment: &mut Attachment) -> Result<(), BlobError> {
+        if !attachment.is_stored() {
+            return Ok(());
+        }
+
+        let hash = attachment.blob.as_ref().map(|b| b.hash.clone()).unwrap_or_default();
+        let content = self.get(&hash).await
+            .ok_or(BlobError::NotFound(hash))?;
+
+        attachment.source = AttachmentSource::Bytes(content.as_ref().clone());
+        Ok(())
+    }
+
+    /// Release all attachment blobs referenced by an email
+    pub async fn release_attachments(&self, email: &Email) {
+        for att in &email.attachments {
+            if let Some(blob) = &att.blob {
+                self.release(&blob.hash).await;
+            }
+        }
+    }
+
+    /// Number of distinct blobs stored
+    pub async fn len(&self) -> usize {
+        let blobs = self.blobs.read().await;
+        blobs.len()
+    }
+
+    /// Check if the store is empty
+    pub async fn is_empty(&self) -> bool {
+        self.len().await == 0
+    }
+
+    /// Total bytes held by the store
+    pub async fn total_size(&self) -> usize {
+        let blobs = self.blobs.read().await;
+        blobs.values().map(|b| b.content.len()).sum()
+    }
+}
+
+impl Default for BlobStore {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -1,16 +1,22 @@
 //! Email Log Service
 
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::sync::Arc;
-use tokio::sync::RwLock;
+use tokio::sync::{mpsc, RwLock};
 use chrono::{DateTime, Utc};
 use uuid::Uuid;
 
 use crate::models::{
-    EmailLog, EmailEvent, LogFilter, LogStats,
+    EmailLog, EmailEvent, LogFilter, Paginated, LogStats, Redaction, TemplateUsage, SIZE_BYTES_META,
     BounceRecord, BounceType, ComplaintRecord, ComplaintType,
 };
 
+/// Entries read per lock acquisition while streaming
+const STREAM_CHUNK: usize = 1000;
+
+/// Entries buffered ahead of a stream consumer
+const STREAM_BUFFER: usize = 256;
+
 /// Log service error
 #[derive(Debug, thiserror::Error)]
 pub enum LogError {
@@ -30,6 +36,12 @@ pub struct LogService {
     complaints: Arc<RwLock<HashMap<String, ComplaintRecord>>>,
     /// Suppression list (emails that should not receive mail)
     suppression_list: Arc<RwLock<HashMap<String, SuppressionReason>>>,
+    /// Topics each email has unsubscribed from
+    topic_suppressions: Arc<RwLock<HashMap<String, HashSet<String>>>>,
+    /// History of suppressions and what triggered them
+    suppression_audit: Arc<RwLock<Vec<SuppressionAudit>>>,
+    /// Callbacks run whenever an address is suppressed
+    suppression_hooks: Arc<RwLock<Vec<SuppressionHook>>>,
     /// Max log entries to keep in memory
     max_entries: usize,
 }
@@ -42,6 +54,22 @@ pub enum SuppressionReason {
     Manual,
 }
 
+/// Record of an address being added to the suppression list
+#[derive(Debug, Clone)]
+pub struct SuppressionAudit {
+    /// Suppressed address
+    pub email: String,
+    /// Why it was suppressed
+    pub reason: SuppressionReason,
+    /// Email whose bounce, complaint or unsubscribe triggered it
+    pub email_id: Option<Uuid>,
+    /// When it was suppressed
+    pub at: DateTime<Utc>,
+}
+
+/// Callback notified of new suppressions
+pub type SuppressionHook = Box<dyn Fn(&SuppressionAudit) + Send + Sync>;
+
 impl LogService {
     pub fn new() -> Self {
         Self {
@@ -49,6 +77,9 @@ impl LogService {
             bounces: Arc::new(RwLock::new(HashMap::new())),
             complaints: Arc::new(RwLock::new(HashMap::new())),
             suppression_list: Arc::new(RwLock::new(HashMap::new())),
+            topic_suppressions: Arc::new(RwLock::new(HashMap::new())),
+            suppression_audit: Arc::new(RwLock::new(Vec::new())),
+            suppression_hooks: Arc::new(RwLock::new(Vec::new())),
             max_entries: 100_000,
         }
     }
@@ -65,13 +96,13 @@ impl LogService {
         // Handle special events
         match entry.event {
             EmailEvent::Bounced | EmailEvent::HardBounce | EmailEvent::SoftBounce => {
-                self.record_bounce(&entry).await;
+                self.record_bounce(&entry, entry.provider_response.as_deref()).await;
             }
             EmailEvent::SpamComplaint => {
                 self.record_complaint(&entry).await;
             }
             EmailEvent::Unsubscribed => {
-                self.add_to_suppression(&entry.recipient, SuppressionReason::Unsubscribed).await;
+                self.add_to_suppression(&entry.recipient, SuppressionReason::Unsubscribed, Some(entry.email_id)).await;
             }
             _ => {}
         }
@@ -122,69 +153,62 @@ impl LogService {
 
     /// Get logs with filter
     pub async fn query(&self, filter: LogFilter) -> Vec<EmailLog> {
-        let logs = self.logs.read().await;
-
-        logs.iter()
-            .filter(|log| {
-                // Filter by email ID
-                if let Some(email_id) = filter.email_id {
-                    if log.email_id != email_id {
-                        return false;
-                    }
-                }
-
-                // Filter by recipient
-                if let Some(ref recipient) = filter.recipient {
-                    if !log.recipient.to_lowercase().contains(&recipient.to_lowercase()) {
-                        return false;
-                    }
-                }
+        self.query_page(filter).await.items
+    }
 
-                // Filter by event
-                if let Some(event) = filter.event {
-                    if log.event != event {
-                        return false;
-                    }
-                }
+    /// Query logs, with the number of matches before pagination
+    pub async fn query_page(&self, filter: LogFilter) -> Paginated<EmailLog> {
+        let logs = self.logs.read().await;
 
-                // Filter by template
-                if let Some(template_id) = filter.template_id {
-                    if log.template_id != Some(template_id) {
-                        return false;
-                    }
-                }
+        let matching = logs.iter().filter(|log| filter.matches(log));
+        Paginated::from_matches(matching, filter.limit as usize, filter.offset as usize)
+            .map(EmailLog::clone)
+    }
 
-                // Filter by provider
-                if let Some(ref provider) = filter.provider {
-                    if &log.provider != provider {
-                        return false;
+    /// Stream matching entries, oldest first, without collecting them all.
+    ///
+    /// Entries are read in chunks, releasing the lock in between, and the
+    /// channel applies backpressure so a slow consumer (e.g. one writing
+    /// NDJSON to a file) holds only a bounded number in memory. Entries
+    /// removed by a concurrent [`cleanup`](Self::cleanup) may be skipped.
+    pub fn stream(&self, filter: LogFilter) -> mpsc::Receiver<EmailLog> {
+        let (tx, rx) = mpsc::channel(STREAM_BUFFER);
+        let logs = Arc::clone(&self.logs);
+
+        tokio::spawn(async move {
+            let mut position = 0;
+            let mut to_skip = filter.offset as usize;
+            let mut remaining = filter.limit as usize;
+
+            while remaining > 0 {
+                let chunk: Vec<EmailLog> = {
+                    let logs = logs.read().await;
+                    let end = (position + STREAM_CHUNK).min(logs.len());
+                    if position >= end {
+                        break;
                     }
-                }
-
-                // Filter by date range
-                if let Some(from_date) = filter.from_date {
-                    if log.timestamp < from_date {
-                        return false;
+                    let chunk = logs[position..end].iter()
+                        .filter(|log| filter.matches(log))
+                        .cloned()
+                        .collect();
+                    position = end;
+                    chunk
+                };
+
+                for log in chunk {
+                    if to_skip > 0 {
+                        to_skip -= 1;
+                        continue;
                     }
-                }
-
-                if let Some(to_date) = filter.to_date {
-                    if log.timestamp > to_date {
-                        return false;
+                    if remaining == 0 || tx.send(log).await.is_err() {
+                        return;
                     }
+                    remaining -= 1;
                 }
+            }
+        });
 
-                // Filter errors only
-                if filter.errors_only && log.error.is_none() {
-                    return false;
-                }
-
-                true
-            })
-            .skip(filter.offset as usize)
-            .take(filter.limit as usize)
-            .cloned()
-            .collect()
+        rx
     }
 
     /// Get logs for specific email
@@ -214,6 +238,8 @@ impl LogService {
 
         let from = from_date.unwrap_or_else(|| Utc::now() - chrono::Duration::days(30));
         let to = to_date.unwrap_or_else(Utc::now);
+        // A message to several recipients is transmitted once
+        let mut sized = HashSet::new();
 
         for log in logs.iter() {
             if log.timestamp < from || log.timestamp > to {
@@ -221,7 +247,17 @@ impl LogService {
             }
 
             match log.event {
-                EmailEvent::Sent => stats.total_sent += 1,
+                EmailEvent::Sent => {
+                    stats.total_sent += 1;
+                    let size = log.metadata.get(SIZE_BYTES_META)
+                        .and_then(|v| v.as_str())
+                        .and_then(|v| v.parse::<u64>().ok());
+                    if let Some(size) = size {
+                        if sized.insert(log.email_id) {
+                            stats.bytes_sent += size;
+                        }
+                    }
+                }
                 EmailEvent::Delivered => stats.total_delivered += 1,
                 EmailEvent::Bounced | EmailEvent::HardBounce | EmailEvent::SoftBounce => {
                     stats.total_bounced += 1;
@@ -239,8 +275,8 @@ impl LogService {
         stats
     }
 
-    /// Record a bounce
-    async fn record_bounce(&self, log: &EmailLog) {
+    /// Record a bounce, keeping the provider's raw diagnostic alongside the reason
+    async fn record_bounce(&self, log: &EmailLog, diagnostic: Option<&str>) {
         let email = log.recipient.to_lowercase();
         let bounce_type = match log.event {
             EmailEvent::HardBounce => BounceType::Hard,
@@ -256,15 +292,19 @@ impl LogService {
                 record.bounce_type = BounceType::Hard;
             }
             record.reason = log.error.clone();
+            if let Some(diagnostic) = diagnostic {
+                record.diagnostic = Some(diagnostic.to_string());
+            }
         } else {
             let mut record = BounceRecord::new(&email, bounce_type);
             record.reason = log.error.clone();
+            record.diagnostic = diagnostic.map(|d| d.to_string());
             bounces.insert(email.clone(), record);
         }
 
         // Add hard bounces to suppression list
         if bounce_type == BounceType::Hard {
-            self.add_to_suppression(&email, SuppressionReason::HardBounce).await;
+            self.add_to_suppression(&email, SuppressionReason::HardBounce, Some(log.email_id)).await;
         }
     }
 
@@ -281,13 +321,39 @@ impl LogService {
         complaints.insert(email.clone(), record);
 
         // Add to suppression list
-        self.add_to_suppression(&email, SuppressionReason::SpamComplaint).await;
+        self.add_to_suppression(&email, SuppressionReason::SpamComplaint, Some(log.email_id)).await;
     }
 
-    /// Add email to suppression list
-    pub async fn add_to_suppression(&self, email: &str, reason: SuppressionReason) {
-        let mut list = self.suppression_list.write().await;
-        list.insert(email.to_lowercase(), reason);
+    /// Add email to suppression list, recording the email that triggered it
+    pub async fn add_to_suppression(&self, email: &str, reason: SuppressionReason, email_id: Option<Uuid>) {
+        let email = email.to_lowercase();
+        self.suppression_list.write().await.insert(email.clone(), reason.clone());
+
+        let audit = SuppressionAudit {
+            email,
+            reason,
+            email_id,
+            at: Utc::now(),
+        };
+        for hook in self.suppression_hooks.read().await.iter() {
+            hook(&audit);
+        }
+        self.suppression_audit.write().await.push(audit);
+    }
+
+    /// Run `hook` whenever an address is suppressed, e.g. to sync a CRM
+    pub async fn on_suppression(&self, hook: impl Fn(&SuppressionAudit) + Send + Sync + 'static) {
+        self.suppression_hooks.write().await.push(Box::new(hook));
+    }
+
+    /// Suppression history for an address, oldest first
+    pub async fn suppression_audit(&self, email: &str) -> Vec<SuppressionAudit> {
+        let email = email.to_lowercase();
+        self.suppression_audit.read().await
+            .iter()
+            .filter(|a| a.email == email)
+            .cloned()
+            .collect()
     }
 
     /// Remove from suppression list
@@ -302,6 +368,70 @@ impl LogService {
         list.contains_key(&email.to_lowercase())
     }
 
+    /// Return the subset of addresses that are suppressed, taking the lock once
+    pub async fn filter_suppressed(&self, addresses: &[String]) -> Vec<String> {
+        let list = self.suppression_list.read().await;
+        addresses.iter()
+            .filter(|email| list.contains_key(&email.to_lowercase()))
+            .cloned()
+            .collect()
+    }
+
+    /// Return the subset of addresses suppressed globally or for the given topic
+    pub async fn filter_suppressed_for(&self, addresses: &[String], topic: &str) -> Vec<String> {
+        let list = self.suppression_list.read().await;
+        let topics = self.topic_suppressions.read().await;
+        addresses.iter()
+            .filter(|email| {
+                let key = email.to_lowercase();
+                list.contains_key(&key)
+                    || topics.get(&key).is_some_and(|set| set.contains(topic))
+            })
+            .cloned()
+            .collect()
+    }
+
+    /// Suppress an email for a single topic only
+    pub async fn add_to_suppression_topic(&self, email: &str, topic: &str) {
+        let mut topics = self.topic_suppressions.write().await;
+        topics.entry(email.to_lowercase())
+            .or_default()
+            .insert(topic.to_string());
+    }
+
+    /// Remove a topic suppression
+    pub async fn remove_from_suppression_topic(&self, email: &str, topic: &str) {
+        let mut topics = self.topic_suppressions.write().await;
+        let key = email.to_lowercase();
+        if let Some(set) = topics.get_mut(&key) {
+            set.remove(topic);
+            if set.is_empty() {
+                topics.remove(&key);
+            }
+        }
+    }
+
+    /// Check if email is suppressed globally or for the given topic
+    pub async fn is_suppressed_for(&self, email: &str, topic: &str) -> bool {
+        if self.is_suppressed(email).await {
+            return true;
+        }
+
+        let topics = self.topic_suppressions.read().await;
+        topics.get(&email.to_lowercase())
+            .is_some_and(|set| set.contains(topic))
+    }
+
+    /// Get topics an email is suppressed for
+    pub async fn get_suppressed_topics(&self, email: &str) -> Vec<String> {
+        let topics = self.topic_suppressions.read().await;
+        let mut list: Vec<String> = topics.get(&email.to_lowercase())
+            .map(|set| set.iter().cloned().collect())
+            .unwrap_or_default();
+        list.sort();
+        list
+    }
+
     /// Get suppression reason
     pub async fn get_suppression_reason(&self, email: &str) -> Option<SuppressionReason> {
         let list = self.suppression_list.read().await;
@@ -326,6 +456,30 @@ impl LogService {
         list.iter().map(|(k, v)| (k.clone(), v.clone())).collect()
     }
 
+    /// Send counts and last use per template, most recently used first
+    pub async fn template_usage(&self) -> Vec<TemplateUsage> {
+        let logs = self.logs.read().await;
+        let mut usage: HashMap<Uuid, TemplateUsage> = HashMap::new();
+
+        for log in logs.iter().filter(|log| log.event == EmailEvent::Sent) {
+            let Some(template_id) = log.template_id else {
+                continue;
+            };
+
+            let entry = usage.entry(template_id).or_insert(TemplateUsage {
+                template_id,
+                sent_count: 0,
+                last_used: log.timestamp,
+            });
+            entry.sent_count += 1;
+            entry.last_used = entry.last_used.max(log.timestamp);
+        }
+
+        let mut usage: Vec<_> = usage.into_values().collect();
+        usage.sort_by_key(|u| std::cmp::Reverse(u.last_used));
+        usage
+    }
+
     /// Count logs by event type
     pub async fn count_by_event(&self) -> HashMap<EmailEvent, u64> {
         let logs = self.logs.read().await;
@@ -349,9 +503,12 @@ impl LogService {
         original_len - logs.len()
     }
 
-    /// Export logs to JSON
-    pub async fn export(&self, filter: LogFilter) -> String {
-        let logs = self.query(filter).await;
+    /// Export logs to JSON, masking personal data per `redaction`
+    pub async fn export(&self, filter: LogFilter, redaction: Redaction) -> String {
+        let mut logs = self.query(filter).await;
+        for log in &mut logs {
+            redaction.apply(log);
+        }
         serde_json::to_string_pretty(&logs).unwrap_or_default()
     }
 }
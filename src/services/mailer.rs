@@ -1,14 +1,20 @@
 //! Mailer Service - Main email sending service
 
+use std::collections::{HashMap, VecDeque};
 use std::sync::Arc;
 use tokio::sync::RwLock;
+use chrono::{DateTime, Utc};
 use uuid::Uuid;
 
-use crate::models::{Email, EmailAddress, EmailBuilder, QueueItem, QueueStatus};
+use crate::models::{
+    Email, EmailAddress, EmailBuilder, EmailEvent, EmailLog, LogFilter, QueueItem, ScheduleSpec,
+    RecipientState, RecipientStatus,
+    classify_bounce, extract_status_code, reply_code, ATTACHMENT_SHA256_META, SIZE_BYTES_META, TAGS_META,
+};
 use crate::services::{
-    SmtpTransport, SmtpConfig, SmtpError,
-    TemplateService, QueueService, LogService,
-    template::RenderedEmail,
+    SmtpTransport, SmtpConfig, SmtpError, MailTransport,
+    TemplateService, QueueService, LogService, WorkerId,
+    tracking,
 };
 
 /// Mailer error
@@ -26,6 +32,10 @@ pub enum MailerError {
     Invalid(String),
     #[error("Configuration error: {0}")]
     Configuration(String),
+    #[error("Sending halted: {0}")]
+    Halted(String),
+    #[error("Rate limit exceeded: {0}")]
+    RateLimited(String),
 }
 
 /// Mailer configuration
@@ -43,8 +53,35 @@ pub struct MailerConfig {
     pub track_opens: bool,
     /// Track clicks
     pub track_clicks: bool,
+    /// Base URL for open pixels and click redirects; falls back to `site_url`
+    pub tracking_base_url: Option<String>,
     /// Queue emails by default
     pub queue_by_default: bool,
+    /// Maximum number of attachments per email
+    pub max_attachments: usize,
+    /// Verified sending domains; `None` or empty allows any From domain
+    pub allowed_from_domains: Option<Vec<String>>,
+    /// Turn `data:` URI images in HTML bodies into inline attachments
+    pub embed_data_uri_images: bool,
+    /// Headers added to every email; an email's own header of the same name wins
+    pub global_headers: HashMap<String, String>,
+    /// Most emails a single recipient may be sent in any 24 hours; further
+    /// emails are deferred until the window allows them
+    pub max_per_recipient_per_day: Option<u32>,
+    /// Hand queue items that fail on one provider to the next untried
+    /// fallback provider before backing off
+    pub reroute_on_failure: bool,
+    /// Most sends per rolling minute for a template, keyed by slug; a
+    /// safety cap against runaway integrations
+    pub template_rate_limits: HashMap<String, u32>,
+    /// Worker identity recorded on claimed queue items; defaults to the
+    /// service's [`WorkerId::auto`]
+    pub worker_id: Option<WorkerId>,
+    /// Prepended to every subject, e.g. `[STAGING]` outside production
+    pub subject_prefix: Option<String>,
+    /// Copy each email's metadata and tags into its log entries, so logs
+    /// can be traced back to business keys such as an order id
+    pub log_email_metadata: bool,
 }
 
 impl Default for MailerConfig {
@@ -56,8 +93,139 @@ impl Default for MailerConfig {
             site_url: "http://localhost".to_string(),
             track_opens: false,
             track_clicks: false,
+            tracking_base_url: None,
             queue_by_default: true,
+            max_attachments: 25,
+            allowed_from_domains: None,
+            embed_data_uri_images: false,
+            global_headers: HashMap::new(),
+            max_per_recipient_per_day: None,
+            reroute_on_failure: false,
+            template_rate_limits: HashMap::new(),
+            worker_id: None,
+            subject_prefix: None,
+            log_email_metadata: true,
+        }
+    }
+}
+
+/// Backup transport and the provider name it is logged under
+type NamedTransport = (String, Box<dyn MailTransport>);
+
+/// Provider name of the transport set with [`MailerService::set_transport`]
+pub const PRIMARY_PROVIDER: &str = "primary";
+
+/// Metadata key holding the subscription topic an email belongs to
+pub const TOPIC_META: &str = "topic";
+
+/// Metadata key recording which sending identity was chosen
+pub const SENDING_IDENTITY_META: &str = "sending_identity";
+
+/// Subject prefix of template test sends
+pub const TEST_SUBJECT_PREFIX: &str = "[TEST]";
+
+/// Weighted pool of verified From addresses.
+///
+/// Identities are picked with smooth weighted round-robin, so over any run of
+/// sends each identity is used in proportion to its weight.
+#[derive(Debug, Clone, Default)]
+pub struct SendingIdentityPool {
+    /// Identities with their relative weights
+    pub identities: Vec<(EmailAddress, u32)>,
+    /// Running round-robin state per identity
+    current: Vec<i64>,
+}
+
+impl SendingIdentityPool {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_identity(mut self, address: EmailAddress, weight: u32) -> Self {
+        self.identities.push((address, weight));
+        self.current.push(0);
+        self
+    }
+
+    /// Check if any identity can be picked
+    pub fn is_empty(&self) -> bool {
+        self.identities.iter().all(|(_, weight)| *weight == 0)
+    }
+
+    /// Pick the next identity
+    pub fn pick(&mut self) -> Option<EmailAddress> {
+        if self.is_empty() {
+            return None;
+        }
+
+        self.current.resize(self.identities.len(), 0);
+        let total: i64 = self.identities.iter().map(|(_, w)| *w as i64).sum();
+
+        let mut best = 0;
+        for (i, (_, weight)) in self.identities.iter().enumerate() {
+            self.current[i] += *weight as i64;
+            if self.current[i] > self.current[best] {
+                best = i;
+            }
+        }
+
+        self.current[best] -= total;
+        Some(self.identities[best].0.clone())
+    }
+}
+
+/// Daily send caps for warming up a new sending IP or domain.
+///
+/// Day `n` after `started_at` may send at most `day_limits[n]` emails; once
+/// the schedule runs out sending is unlimited.
+#[derive(Debug, Clone)]
+pub struct WarmupSchedule {
+    /// Maximum sends for each day of the warm-up
+    pub day_limits: Vec<u32>,
+    /// Start of the first warm-up day
+    pub started_at: DateTime<Utc>,
+    /// Warm-up day the current count belongs to
+    day: i64,
+    /// Sends counted so far on that day
+    sent: u32,
+}
+
+impl WarmupSchedule {
+    pub fn new(day_limits: Vec<u32>, started_at: DateTime<Utc>) -> Self {
+        Self {
+            day_limits,
+            started_at,
+            day: 0,
+            sent: 0,
+        }
+    }
+
+    /// Warm-up day containing `now`
+    fn day_of(&self, now: DateTime<Utc>) -> i64 {
+        (now - self.started_at).num_days().max(0)
+    }
+
+    /// Sends still allowed on the day containing `now`, or `None` if unlimited
+    pub fn remaining(&self, now: DateTime<Utc>) -> Option<u32> {
+        let day = self.day_of(now);
+        let limit = *self.day_limits.get(day as usize)?;
+        let sent = if day == self.day { self.sent } else { 0 };
+        Some(limit.saturating_sub(sent))
+    }
+
+    /// Count a send against the day containing `now`
+    pub fn record_send(&mut self, now: DateTime<Utc>) {
+        let day = self.day_of(now);
+        if day != self.day {
+            self.day = day;
+            self.sent = 0;
         }
+        self.sent += 1;
+    }
+
+    /// Start of the warm-up day after the one containing `now`
+    pub fn next_day(&self, now: DateTime<Utc>) -> DateTime<Utc> {
+        self.started_at + chrono::Duration::days(self.day_of(now) + 1)
     }
 }
 
@@ -65,14 +233,28 @@ impl Default for MailerConfig {
 pub struct MailerService {
     /// Configuration
     config: Arc<RwLock<MailerConfig>>,
-    /// SMTP transport
-    transport: Arc<RwLock<Option<SmtpTransport>>>,
+    /// Mail transport
+    transport: Arc<RwLock<Option<Box<dyn MailTransport>>>>,
+    /// Named backup transports, tried in order after the primary
+    fallback_transports: Arc<RwLock<Vec<NamedTransport>>>,
     /// Template service
     template_service: Arc<TemplateService>,
     /// Queue service
     queue_service: Arc<QueueService>,
     /// Log service
     log_service: Arc<LogService>,
+    /// Sending identities for mail without an explicit From
+    identity_pool: Arc<RwLock<SendingIdentityPool>>,
+    /// Daily send caps while warming up
+    warmup: Arc<RwLock<Option<WarmupSchedule>>>,
+    /// SMTP settings of the connected transport
+    smtp_config: Arc<RwLock<Option<SmtpConfig>>>,
+    /// Reason all outbound mail is stopped, if it is
+    halted: Arc<RwLock<Option<String>>>,
+    /// Recent send times per rate-limited template slug
+    template_sends: Arc<RwLock<HashMap<String, VecDeque<DateTime<Utc>>>>>,
+    /// Worker identity used unless the config sets one
+    worker_id: WorkerId,
 }
 
 impl MailerService {
@@ -80,29 +262,118 @@ impl MailerService {
         Self {
             config: Arc::new(RwLock::new(MailerConfig::default())),
             transport: Arc::new(RwLock::new(None)),
+            fallback_transports: Arc::new(RwLock::new(Vec::new())),
             template_service: Arc::new(TemplateService::new()),
             queue_service: Arc::new(QueueService::new()),
             log_service: Arc::new(LogService::new()),
+            identity_pool: Arc::new(RwLock::new(SendingIdentityPool::new())),
+            warmup: Arc::new(RwLock::new(None)),
+            smtp_config: Arc::new(RwLock::new(None)),
+            halted: Arc::new(RwLock::new(None)),
+            template_sends: Arc::new(RwLock::new(HashMap::new())),
+            worker_id: WorkerId::auto(),
         }
     }
 
+    /// Use a preconfigured queue service, e.g. with custom retry policy or limits
+    pub fn with_queue(mut self, queue: QueueService) -> Self {
+        self.queue_service = Arc::new(queue);
+        self
+    }
+
     /// Configure mailer
     pub async fn configure(&self, config: MailerConfig) {
         let mut current = self.config.write().await;
         *current = config;
     }
 
+    /// Get current mailer configuration
+    pub async fn config(&self) -> MailerConfig {
+        self.config.read().await.clone()
+    }
+
     /// Configure SMTP
     pub async fn configure_smtp(&self, smtp_config: SmtpConfig) -> Result<(), MailerError> {
-        let mut transport = SmtpTransport::new(smtp_config);
+        let transport = SmtpTransport::new(smtp_config.clone());
         transport.connect().await?;
 
-        let mut current = self.transport.write().await;
-        *current = Some(transport);
+        self.set_transport(transport).await;
+        *self.smtp_config.write().await = Some(smtp_config);
 
         Ok(())
     }
 
+    /// SMTP settings in use, if connected via `configure_smtp`
+    pub async fn smtp_config(&self) -> Option<SmtpConfig> {
+        self.smtp_config.read().await.clone()
+    }
+
+    /// Use a custom transport for delivery
+    pub async fn set_transport(&self, transport: impl MailTransport + 'static) {
+        let mut current = self.transport.write().await;
+        *current = Some(Box::new(transport));
+        *self.smtp_config.write().await = None;
+    }
+
+    /// Set the pool of sending identities to rotate between
+    pub async fn set_identity_pool(&self, pool: SendingIdentityPool) {
+        let mut current = self.identity_pool.write().await;
+        *current = pool;
+    }
+
+    /// Add a named backup transport for rerouted queue items
+    pub async fn add_fallback_transport(&self, name: &str, transport: impl MailTransport + 'static) {
+        let mut fallbacks = self.fallback_transports.write().await;
+        fallbacks.retain(|(existing, _)| existing != name);
+        fallbacks.push((name.to_string(), Box::new(transport)));
+    }
+
+    /// Provider names in the order they are tried
+    pub async fn providers(&self) -> Vec<String> {
+        std::iter::once(PRIMARY_PROVIDER.to_string())
+            .chain(self.fallback_transports.read().await.iter().map(|(name, _)| name.clone()))
+            .collect()
+    }
+
+    /// Cap daily queue throughput with a warm-up schedule, or remove the cap
+    pub async fn set_warmup_schedule(&self, schedule: Option<WarmupSchedule>) {
+        let mut current = self.warmup.write().await;
+        *current = schedule;
+    }
+
+    /// Stop all outbound mail, including immediate sends, until
+    /// [`resume`](Self::resume). Queued items stay where they are.
+    pub async fn halt(&self, reason: &str) {
+        *self.halted.write().await = Some(reason.to_string());
+    }
+
+    /// Lift a [`halt`](Self::halt)
+    pub async fn resume(&self) {
+        *self.halted.write().await = None;
+    }
+
+    /// Prefix the subject, unless an earlier delivery already did
+    fn apply_subject_prefix(email: &mut Email, prefix: &str) {
+        let prefix = prefix.trim();
+        if prefix.is_empty() || email.subject.starts_with(prefix) {
+            return;
+        }
+        email.subject = format!("{} {}", prefix, email.subject);
+    }
+
+    /// Check if outbound mail is halted
+    pub async fn is_halted(&self) -> bool {
+        self.halted.read().await.is_some()
+    }
+
+    /// Fail with the halt reason while halted
+    async fn check_halted(&self) -> Result<(), MailerError> {
+        match self.halted.read().await.as_ref() {
+            Some(reason) => Err(MailerError::Halted(reason.clone())),
+            None => Ok(()),
+        }
+    }
+
     /// Get template service
     pub fn templates(&self) -> &Arc<TemplateService> {
         &self.template_service
@@ -118,102 +389,543 @@ impl MailerService {
         &self.log_service
     }
 
-    /// Send email immediately
-    pub async fn send(&self, email: Email) -> Result<(), MailerError> {
-        // Check suppression
-        for recipient in email.to.iter().chain(email.cc.iter()).chain(email.bcc.iter()) {
-            if self.log_service.is_suppressed(&recipient.email).await {
-                return Err(MailerError::Suppressed(recipient.email.clone()));
+    /// Reject emails with a recipient suppressed globally or for the email's topic
+    async fn check_suppression(&self, email: &Email) -> Result<(), MailerError> {
+        let recipients: Vec<String> = email.to.iter()
+            .chain(email.cc.iter())
+            .chain(email.bcc.iter())
+            .map(|r| r.email.clone())
+            .collect();
+
+        let suppressed = match email.metadata.get(TOPIC_META) {
+            Some(topic) => self.log_service.filter_suppressed_for(&recipients, topic).await,
+            None => self.log_service.filter_suppressed(&recipients).await,
+        };
+
+        match suppressed.into_iter().next() {
+            Some(email) => Err(MailerError::Suppressed(email)),
+            None => Ok(()),
+        }
+    }
+
+    /// When a recipient has already been sent `cap` emails in the last 24
+    /// hours, the time at which another may go out
+    async fn frequency_cap_release(&self, email: &Email, cap: u32) -> Result<Option<DateTime<Utc>>, MailerError> {
+        let now = Utc::now();
+        let mut release: Option<DateTime<Utc>> = None;
+
+        for address in Self::recipients(email) {
+            let mut sent: Vec<DateTime<Utc>> = self.log_service.query(LogFilter {
+                recipient: Some(address.to_string()),
+                event: Some(EmailEvent::Sent),
+                from_date: Some(now - chrono::Duration::days(1)),
+                limit: u32::MAX,
+                ..Default::default()
+            }).await
+                .into_iter()
+                .filter(|log| log.recipient.eq_ignore_ascii_case(address))
+                .map(|log| log.timestamp)
+                .collect();
+
+            if sent.len() < cap as usize {
+                continue;
+            }
+            if cap == 0 {
+                return Err(MailerError::Invalid(format!("{} is over the frequency cap", address)));
             }
+
+            // Another send fits once enough of the window's sends age out
+            sent.sort();
+            let at = sent[sent.len() - cap as usize] + chrono::Duration::days(1);
+            release = release.max(Some(at));
         }
 
-        let transport = self.transport.read().await;
-        let transport = transport.as_ref()
-            .ok_or_else(|| MailerError::Configuration("SMTP not configured".to_string()))?;
+        Ok(release)
+    }
+
+    /// Reject From addresses outside the verified sending domains.
+    ///
+    /// Domains match exactly (case-insensitively); subdomains must be listed
+    /// on their own.
+    fn check_sender_domain(email: &Email, config: &MailerConfig) -> Result<(), MailerError> {
+        let allowed = match &config.allowed_from_domains {
+            Some(domains) if !domains.is_empty() => domains,
+            _ => return Ok(()),
+        };
+
+        let domain = email.from.domain().unwrap_or("");
+        if allowed.iter().any(|d| d.eq_ignore_ascii_case(domain)) {
+            Ok(())
+        } else {
+            Err(MailerError::Invalid("sender domain not allowed".to_string()))
+        }
+    }
+
+    /// Run a send of `slug` against its per-minute cap, failing once the
+    /// cap is used up. The slot is taken up front so concurrent sends can't
+    /// overshoot, and given back if the send fails.
+    async fn with_template_rate<T>(
+        &self,
+        slug: &str,
+        send: impl std::future::Future<Output = Result<T, MailerError>>,
+    ) -> Result<T, MailerError> {
+        let Some(limit) = self.config.read().await.template_rate_limits.get(slug).copied() else {
+            return send.await;
+        };
+
+        let now = Utc::now();
+        {
+            let mut sends = self.template_sends.write().await;
+            let recent = sends.entry(slug.to_string()).or_default();
+            while recent.front().is_some_and(|sent| *sent <= now - chrono::Duration::minutes(1)) {
+                recent.pop_front();
+            }
+
+            if recent.len() >= limit as usize {
+                return Err(MailerError::RateLimited(format!("template {} is capped at {} per minute", slug, limit)));
+            }
+            recent.push_back(now);
+        }
+
+        let result = send.await;
+        if result.is_err() {
+            let mut sends = self.template_sends.write().await;
+            if let Some(recent) = sends.get_mut(slug) {
+                if let Some(index) = recent.iter().position(|sent| *sent == now) {
+                    recent.remove(index);
+                }
+            }
+        }
+        result
+    }
+
+    /// Send email immediately
+    pub async fn send(&self, email: Email) -> Result<DeliveryOutcome, MailerError> {
+        self.send_via(email, None, true).await
+    }
+
+    /// Send through the named provider, or the primary transport
+    async fn send_via(&self, mut email: Email, provider: Option<&str>, check_suppression: bool) -> Result<DeliveryOutcome, MailerError> {
+        self.check_halted().await?;
+        self.apply_identity(&mut email).await?;
+        email.check_header_injection().map_err(MailerError::Invalid)?;
+
+        {
+            let config = self.config.read().await;
+            Self::check_sender_domain(&email, &config)?;
+        }
+
+        if check_suppression {
+            self.check_suppression(&email).await?;
+        }
+
+        let primary = self.transport.read().await;
+        let fallbacks = self.fallback_transports.read().await;
+        let (provider_name, transport) = match provider {
+            Some(name) if name != PRIMARY_PROVIDER => fallbacks.iter()
+                .find(|(fallback, _)| fallback == name)
+                .map(|(fallback, transport)| (fallback.as_str(), transport))
+                .ok_or_else(|| MailerError::Configuration(format!("Unknown provider: {}", name)))?,
+            _ => (PRIMARY_PROVIDER, primary.as_ref()
+                .ok_or_else(|| MailerError::Configuration("SMTP not configured".to_string()))?),
+        };
+
+        // Queued attachments left their content in the blob store; load it
+        // back so every transport sees the full message
+        self.queue_service.blobs().resolve_attachments(&mut email).await
+            .map_err(|e| SmtpError::Attachment(e.to_string()))?;
 
         // Log send attempt
-        for recipient in &email.to {
-            self.log_service.log_queued(email.id, &recipient.email, &email.subject).await;
+        for recipient in email.logged_recipients() {
+            let entry = self.log_entry(&email, EmailEvent::Queued, &recipient.email).await;
+            self.log_service.log(entry).await;
         }
 
-        // Send
-        let result = transport.send(&email).await;
+        // Send, abandoning servers that stall past the transport's timeout
+        let result = match tokio::time::timeout(transport.timeout(), transport.send(&email)).await {
+            Ok(result) => result,
+            Err(_) => Err(SmtpError::Send("timed out".to_string())),
+        };
 
         match result {
             Ok(send_result) => {
-                for recipient in &email.to {
-                    self.log_service.log_sent(
-                        email.id,
-                        &recipient.email,
-                        &email.subject,
-                        "smtp",
-                        send_result.message_id.as_deref(),
-                    ).await;
+                let rejected = |address: &str| send_result.rejected.iter()
+                    .find(|(r, _)| r.eq_ignore_ascii_case(address))
+                    .map(|(_, reply)| reply.as_str());
+
+                // Record what was sent so documents can be verified later
+                let checksums: Vec<_> = email.attachments.iter()
+                    .filter_map(|att| att.checksum().map(|sha256| serde_json::json!({
+                        "filename": att.filename,
+                        "sha256": sha256,
+                    })))
+                    .collect();
+
+                for recipient in email.logged_recipients() {
+                    if let Some(reply) = rejected(&recipient.email) {
+                        self.log_failure(&email, &recipient.email, reply, Some(reply)).await;
+                        continue;
+                    }
+                    let mut entry = self.log_entry(&email, EmailEvent::Sent, &recipient.email).await
+                        .with_provider(provider_name, send_result.message_id.as_deref());
+                    if let Some(size) = send_result.size {
+                        entry = entry.with_meta(SIZE_BYTES_META, &size.to_string());
+                    }
+                    if !checksums.is_empty() {
+                        entry = entry.with_meta_value(ATTACHMENT_SHA256_META, checksums.clone().into());
+                    }
+                    self.log_service.log(entry).await;
+                }
+                let unlogged = if email.is_broadcast() { &[][..] } else { &email.bcc[..] };
+                for recipient in email.cc.iter().chain(unlogged) {
+                    if let Some(reply) = rejected(&recipient.email) {
+                        self.log_failure(&email, &recipient.email, reply, Some(reply)).await;
+                    }
                 }
-                Ok(())
+
+                let recipients = Self::recipients(&email)
+                    .map(|address| match rejected(address) {
+                        Some(reply) => RecipientStatus {
+                            email: address.to_string(),
+                            state: Self::rejection_state(reply),
+                            error: Some(reply.to_string()),
+                        },
+                        None => RecipientStatus {
+                            email: address.to_string(),
+                            state: RecipientState::Delivered,
+                            error: None,
+                        },
+                    })
+                    .collect();
+
+                Ok(DeliveryOutcome {
+                    email_id: email.id,
+                    disposition: DeliveryDisposition::Sent { message_id: send_result.message_id },
+                    recipients,
+                })
             }
             Err(e) => {
-                for recipient in &email.to {
-                    self.log_service.log_failed(
-                        email.id,
-                        &recipient.email,
-                        &email.subject,
-                        &e.to_string(),
-                    ).await;
+                let reply = match &e {
+                    SmtpError::Send(message) => Some(message.as_str()),
+                    _ => None,
+                };
+
+                for recipient in email.logged_recipients() {
+                    self.log_failure(&email, &recipient.email, &e.to_string(), reply).await;
                 }
                 Err(MailerError::Smtp(e))
             }
         }
     }
 
+    /// Log a failed delivery, plus a bounce when the server's reply carries
+    /// a status code telling us whether the recipient is worth retrying
+    async fn log_failure(&self, email: &Email, recipient: &str, error: &str, reply: Option<&str>) {
+        let entry = self.log_entry(email, EmailEvent::Failed, recipient).await
+            .with_error(error);
+        self.log_service.log(entry).await;
+
+        let bounce = reply.and_then(|reply| extract_status_code(reply)
+            .map(|code| (classify_bounce(&code, reply), format!("smtp; {}", reply))));
+        if let Some((bounce_type, diagnostic)) = bounce {
+            let entry = self.log_entry(email, bounce_type.event(), recipient).await
+                .with_error(error)
+                .with_provider_response(&diagnostic);
+            self.log_service.log(entry).await;
+        }
+    }
+
+    /// Every envelope recipient of `email`
+    fn recipients(email: &Email) -> impl Iterator<Item = &str> {
+        email.to.iter().chain(&email.cc).chain(&email.bcc).map(|a| a.email.as_str())
+    }
+
+    /// Recipient state for a server reply or error
+    fn rejection_state(reply: &str) -> RecipientState {
+        if reply_code(reply).is_some() {
+            RecipientState::Bounced
+        } else {
+            RecipientState::Failed
+        }
+    }
+
     /// Queue email for sending
     pub async fn queue_email(&self, email: Email) -> Result<QueueItem, MailerError> {
-        // Check suppression
-        for recipient in email.to.iter().chain(email.cc.iter()).chain(email.bcc.iter()) {
-            if self.log_service.is_suppressed(&recipient.email).await {
-                return Err(MailerError::Suppressed(recipient.email.clone()));
-            }
-        }
+        self.enqueue_checked(email, None).await
+    }
 
-        let item = self.queue_service.enqueue(email).await?;
+    /// Queue email for sending at a later time
+    pub async fn schedule_email(&self, email: Email, send_at: DateTime<Utc>) -> Result<QueueItem, MailerError> {
+        self.enqueue_checked(email, Some(send_at)).await
+    }
+
+    /// Check suppression, enqueue and log
+    async fn enqueue_checked(&self, mut email: Email, send_at: Option<DateTime<Utc>>) -> Result<QueueItem, MailerError> {
+        self.check_halted().await?;
+        self.apply_identity(&mut email).await?;
+        email.check_header_injection().map_err(MailerError::Invalid)?;
+        self.check_suppression(&email).await?;
+
+        let item = match send_at {
+            Some(send_at) => self.queue_service.schedule(email, send_at).await?,
+            None => self.queue_service.enqueue(email).await?,
+        };
 
         // Log
-        for recipient in &item.email.to {
-            self.log_service.log_queued(item.email.id, &recipient.email, &item.email.subject).await;
+        for recipient in item.email.logged_recipients() {
+            let entry = self.log_entry(&item.email, EmailEvent::Queued, &recipient.email).await
+                .with_queue(item.id);
+            self.log_service.log(entry).await;
         }
 
         Ok(item)
     }
 
-    /// Send or queue based on config
-    pub async fn deliver(&self, email: Email) -> Result<(), MailerError> {
+    /// Build a log entry for an email, carrying over its metadata and tags
+    /// when configured, and always the sending identity
+    async fn log_entry(&self, email: &Email, event: EmailEvent, recipient: &str) -> EmailLog {
+        let mut entry = EmailLog::new(email.id, event, recipient, &email.subject);
+
+        if self.config.read().await.log_email_metadata {
+            for (key, value) in &email.metadata {
+                entry = entry.with_meta(key, value);
+            }
+            if !email.tags.is_empty() {
+                entry = entry.with_meta_value(TAGS_META, email.tags.clone().into());
+            }
+        } else if let Some(identity) = email.metadata.get(SENDING_IDENTITY_META) {
+            entry = entry.with_meta(SENDING_IDENTITY_META, identity);
+        }
+
+        entry
+    }
+
+    /// Resolve the From address for mail composed without an explicit sender.
+    /// When a sending identity pool is configured, an empty address is
+    /// returned and the actual identity is picked at delivery time.
+    async fn default_sender(&self) -> Result<EmailAddress, MailerError> {
+        if !self.identity_pool.read().await.is_empty() {
+            return Ok(EmailAddress::new(""));
+        }
+
         let config = self.config.read().await;
+        config.default_from.clone()
+            .ok_or_else(|| MailerError::Configuration("Default from address not set".to_string()))
+    }
 
-        if config.queue_by_default {
-            self.queue_email(email).await?;
-            Ok(())
+    /// Fall back to the configured Reply-To when neither the caller nor the
+    /// template set one
+    async fn apply_default_reply_to(&self, email: &mut Email) {
+        if email.reply_to.is_none() {
+            email.reply_to = self.config.read().await.default_reply_to.clone();
+        }
+    }
+
+    /// Stamp a sending identity onto an email that has no From address
+    async fn apply_identity(&self, email: &mut Email) -> Result<(), MailerError> {
+        if !email.from.email.is_empty() {
+            return Ok(());
+        }
+
+        let mut pool = self.identity_pool.write().await;
+        let identity = pool.pick()
+            .ok_or_else(|| MailerError::Configuration("No From address or sending identity available".to_string()))?;
+
+        email.metadata.insert(SENDING_IDENTITY_META.to_string(), identity.email.clone());
+        email.from = identity;
+        Ok(())
+    }
+
+    /// Check attachment count, filenames and content types
+    fn validate_attachments(email: &Email, config: &MailerConfig) -> Result<(), MailerError> {
+        if email.attachments.len() > config.max_attachments {
+            return Err(MailerError::Invalid(format!(
+                "Too many attachments: {} (max {})",
+                email.attachments.len(),
+                config.max_attachments
+            )));
+        }
+
+        for (index, att) in email.attachments.iter().enumerate() {
+            if att.filename.trim().is_empty() {
+                return Err(MailerError::Invalid(format!("Attachment {} has no filename", index)));
+            }
+            if att.content_type.parse::<lettre::message::header::ContentType>().is_err() {
+                return Err(MailerError::Invalid(format!(
+                    "Attachment {} has invalid content type: {}",
+                    att.filename, att.content_type
+                )));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Add open and click tracking to the HTML body as configured
+    fn apply_tracking(email: &mut Email, config: &MailerConfig) {
+        if !config.track_opens && !config.track_clicks {
+            return;
+        }
+
+        let base_url = config.tracking_base_url.as_deref().unwrap_or(&config.site_url);
+
+        if let Some(mut html) = email.html_body.take() {
+            if config.track_clicks {
+                html = tracking::rewrite_links(&html, base_url, email.id);
+            }
+            if config.track_opens {
+                html = tracking::inject_open_pixel(&html, base_url, email.id);
+            }
+            email.html_body = Some(html);
+        }
+    }
+
+    /// Send or queue based on config
+    pub async fn deliver(&self, email: Email) -> Result<DeliveryOutcome, MailerError> {
+        self.deliver_with(email, true).await
+    }
+
+    /// Like [`deliver`](Self::deliver); without `check_suppression` the
+    /// email skips the suppression list, frequency cap and queue and is
+    /// sent right away, since a queued item is checked again when sent
+    async fn deliver_with(&self, mut email: Email, check_suppression: bool) -> Result<DeliveryOutcome, MailerError> {
+        self.check_halted().await?;
+        self.apply_identity(&mut email).await?;
+        email.dedupe_recipients();
+
+        // Rendering can leave a template with nothing but whitespace
+        if !email.has_body() {
+            return Err(MailerError::Invalid("empty body".to_string()));
+        }
+
+        let (queue_by_default, frequency_cap) = {
+            let config = self.config.read().await;
+            // Show the site name rather than a bare address
+            if email.from.name.is_none() && !config.site_name.is_empty() {
+                email.from.name = Some(config.site_name.clone());
+            }
+            if config.embed_data_uri_images {
+                email.embed_data_uri_images();
+            }
+            if let Some(prefix) = &config.subject_prefix {
+                Self::apply_subject_prefix(&mut email, prefix);
+            }
+            for (name, value) in &config.global_headers {
+                if !email.headers.keys().any(|k| k.eq_ignore_ascii_case(name)) {
+                    email.headers.insert(name.clone(), value.clone());
+                }
+            }
+            Self::validate_attachments(&email, &config)?;
+            Self::check_sender_domain(&email, &config)?;
+            Self::apply_tracking(&mut email, &config);
+            (config.queue_by_default, config.max_per_recipient_per_day)
+        };
+
+        if !check_suppression {
+            return self.send_via(email, None, false).await;
+        }
+
+        if let Some(cap) = frequency_cap {
+            if let Some(send_at) = self.frequency_cap_release(&email, cap).await? {
+                let email_id = email.id;
+                let item = self.schedule_email(email, send_at).await?;
+                return Ok(DeliveryOutcome {
+                    email_id,
+                    disposition: DeliveryDisposition::Queued { queue_id: item.id },
+                    recipients: Vec::new(),
+                });
+            }
+        }
+
+        if queue_by_default {
+            let email_id = email.id;
+            let item = self.queue_email(email).await?;
+            Ok(DeliveryOutcome {
+                email_id,
+                disposition: DeliveryDisposition::Queued { queue_id: item.id },
+                recipients: Vec::new(),
+            })
         } else {
             self.send(email).await
         }
     }
 
-    /// Send email using template
+    /// Send email using template.
+    ///
+    /// With a topic, recipients who unsubscribed from that topic are
+    /// suppressed as well as globally suppressed ones.
     pub async fn send_template(
         &self,
         template_slug: &str,
         to: EmailAddress,
         data: serde_json::Value,
-    ) -> Result<(), MailerError> {
-        let config = self.config.read().await;
+        topic: Option<&str>,
+    ) -> Result<DeliveryOutcome, MailerError> {
+        self.send_template_with(template_slug, TemplateRecipients::new(to), data, topic).await
+    }
 
-        let from = config.default_from.clone()
-            .ok_or_else(|| MailerError::Configuration("Default from address not set".to_string()))?;
+    /// Send email using template, with CC, BCC and Reply-To as well as the
+    /// To recipient. All recipients go through the usual suppression checks.
+    pub async fn send_template_with(
+        &self,
+        template_slug: &str,
+        recipients: TemplateRecipients,
+        data: serde_json::Value,
+        topic: Option<&str>,
+    ) -> Result<DeliveryOutcome, MailerError> {
+        self.with_template_rate(template_slug, async {
+            let from = self.default_sender().await?;
 
-        let rendered = self.template_service.render_by_slug(template_slug, &data).await?;
-        let email = self.template_service.build_email(rendered, from, to);
+            let rendered = self.template_service.render_by_slug(template_slug, &data).await?;
+            let mut email = self.template_service.build_email(rendered, from, recipients.to);
+            email.cc = recipients.cc;
+            email.bcc = recipients.bcc;
+            if recipients.reply_to.is_some() {
+                email.reply_to = recipients.reply_to;
+            }
+            self.apply_default_reply_to(&mut email).await;
+            if let Some(topic) = topic {
+                email.metadata.insert(TOPIC_META.to_string(), topic.to_string());
+            }
 
-        drop(config);
-        self.deliver(email).await
+            self.deliver(email).await
+        }).await
+    }
+
+    /// Send a template rendered with its example data to `to`, with a
+    /// `[TEST]` subject prefix. It is sent right away without checking
+    /// suppression, so admins can always preview mail they have
+    /// unsubscribed from.
+    pub async fn send_template_test(&self, template_slug: &str, to: EmailAddress) -> Result<DeliveryOutcome, MailerError> {
+        let from = self.default_sender().await?;
+
+        let rendered = self.template_service.preview_with_examples(template_slug).await?;
+        let mut email = self.template_service.build_email(rendered, from, to);
+        Self::apply_subject_prefix(&mut email, TEST_SUBJECT_PREFIX);
+        self.apply_default_reply_to(&mut email).await;
+
+        self.deliver_with(email, false).await
+    }
+
+    /// Schedule a templated email for later delivery
+    pub async fn send_template_at(
+        &self,
+        template_slug: &str,
+        to: EmailAddress,
+        data: serde_json::Value,
+        when: ScheduleSpec,
+    ) -> Result<QueueItem, MailerError> {
+        let send_at = when.resolve(Utc::now()).map_err(MailerError::Invalid)?;
+        self.with_template_rate(template_slug, async {
+            let from = self.default_sender().await?;
+
+            let rendered = self.template_service.render_by_slug(template_slug, &data).await?;
+            let mut email = self.template_service.build_email(rendered, from, to);
+            self.apply_default_reply_to(&mut email).await;
+            self.apply_identity(&mut email).await?;
+
+            self.schedule_email(email, send_at).await
+        }).await
     }
 
     /// Send email to multiple recipients using template
@@ -221,26 +933,21 @@ impl MailerService {
         &self,
         template_slug: &str,
         recipients: Vec<(EmailAddress, serde_json::Value)>,
-    ) -> Vec<Result<(), MailerError>> {
-        let config = self.config.read().await;
-
-        let from = match &config.default_from {
-            Some(f) => f.clone(),
-            None => {
-                return vec![Err(MailerError::Configuration("Default from address not set".to_string()))];
-            }
+    ) -> Vec<Result<DeliveryOutcome, MailerError>> {
+        let from = match self.default_sender().await {
+            Ok(f) => f,
+            Err(e) => return vec![Err(e)],
         };
 
-        drop(config);
-
         let mut results = Vec::new();
 
         for (to, data) in recipients {
-            let result = async {
+            let result = self.with_template_rate(template_slug, async {
                 let rendered = self.template_service.render_by_slug(template_slug, &data).await?;
-                let email = self.template_service.build_email(rendered, from.clone(), to);
+                let mut email = self.template_service.build_email(rendered, from.clone(), to);
+                self.apply_default_reply_to(&mut email).await;
                 self.deliver(email).await
-            }.await;
+            }).await;
 
             results.push(result);
         }
@@ -248,17 +955,93 @@ impl MailerService {
         results
     }
 
+    /// Send email to multiple recipients using template, merging each
+    /// recipient's data over shared defaults
+    pub async fn send_template_bulk_with_defaults(
+        &self,
+        template_slug: &str,
+        defaults: serde_json::Value,
+        recipients: Vec<(EmailAddress, serde_json::Value)>,
+    ) -> Vec<Result<DeliveryOutcome, MailerError>> {
+        let recipients = recipients
+            .into_iter()
+            .map(|(to, data)| {
+                let mut merged = defaults.clone();
+                crate::services::template::merge_data(&mut merged, data);
+                (to, merged)
+            })
+            .collect();
+
+        self.send_template_bulk(template_slug, recipients).await
+    }
+
     /// Process queue (call this periodically)
     pub async fn process_queue(&self, batch_size: usize) -> ProcessResult {
+        // Recover items left behind by crashed workers
+        self.queue_service.reclaim_expired().await;
+
         let items = self.queue_service.get_pending(batch_size).await;
+        let (reroute, worker_id, frequency_cap) = {
+            let config = self.config.read().await;
+            (
+                config.reroute_on_failure,
+                config.worker_id.clone().unwrap_or_else(|| self.worker_id.clone()),
+                config.max_per_recipient_per_day,
+            )
+        };
+        let providers = if reroute {
+            self.providers().await
+        } else {
+            Vec::new()
+        };
 
         let mut sent = 0;
         let mut failed = 0;
+        let mut deferred = 0;
         let mut errors = Vec::new();
 
         for item in items {
+            // Leave the rest queued if sending was halted mid-batch
+            if self.is_halted().await {
+                break;
+            }
+
+            // Hold back items beyond today's warm-up budget until tomorrow
+            {
+                let warmup = self.warmup.read().await;
+                if let Some(schedule) = warmup.as_ref() {
+                    let now = Utc::now();
+                    if schedule.remaining(now) == Some(0) {
+                        if self.queue_service.reschedule(item.id, schedule.next_day(now)).await.is_ok() {
+                            deferred += 1;
+                        }
+                        continue;
+                    }
+                }
+            }
+
+            // Queued mail wasn't counted when it was accepted, so hold back
+            // items whose recipients have reached the cap since
+            if let Some(cap) = frequency_cap {
+                match self.frequency_cap_release(&item.email, cap).await {
+                    Ok(None) => {}
+                    Ok(Some(send_at)) => {
+                        if self.queue_service.reschedule(item.id, send_at).await.is_ok() {
+                            deferred += 1;
+                        }
+                        continue;
+                    }
+                    Err(e) => {
+                        let _ = self.queue_service.cancel(item.id).await;
+                        errors.push((item.id, e.to_string()));
+                        failed += 1;
+                        continue;
+                    }
+                }
+            }
+
             // Claim item
-            let claimed = match self.queue_service.claim(item.id, "worker").await {
+            let claimed = match self.queue_service.claim(item.id, worker_id.as_str()).await {
                 Ok(item) => item,
                 Err(e) => {
                     errors.push((item.id, e.to_string()));
@@ -267,13 +1050,42 @@ impl MailerService {
                 }
             };
 
-            // Send
-            match self.send(claimed.email.clone()).await {
-                Ok(()) => {
+            // Send on the first provider that has not failed this item yet
+            let provider = providers.iter()
+                .find(|name| !claimed.tried_providers.contains(*name));
+            match self.send_via(claimed.email.clone(), provider.map(String::as_str), true).await {
+                Ok(outcome) => {
+                    // Only mail that actually went out uses warm-up budget
+                    if let Some(schedule) = self.warmup.write().await.as_mut() {
+                        schedule.record_send(Utc::now());
+                    }
+                    let _ = self.queue_service.set_recipient_statuses(item.id, outcome.recipients).await;
                     let _ = self.queue_service.mark_sent(item.id).await;
                     sent += 1;
                 }
                 Err(e) => {
+                    let error = e.to_string();
+                    let statuses = Self::recipients(&claimed.email)
+                        .map(|address| RecipientStatus {
+                            email: address.to_string(),
+                            state: Self::rejection_state(&error),
+                            error: Some(error.clone()),
+                        })
+                        .collect();
+                    let _ = self.queue_service.set_recipient_statuses(item.id, statuses).await;
+
+                    // Hand the item to the next untried provider instead of backing off
+                    if let (Some(current), MailerError::Smtp(_)) = (provider, &e) {
+                        let untried = providers.iter()
+                            .any(|name| name != current && !claimed.tried_providers.contains(name));
+                        if untried {
+                            let _ = self.queue_service.reroute(item.id, current, &error).await;
+                            errors.push((item.id, error));
+                            deferred += 1;
+                            continue;
+                        }
+                    }
+
                     let _ = self.queue_service.mark_failed(item.id, &e.to_string()).await;
                     errors.push((item.id, e.to_string()));
                     failed += 1;
@@ -281,7 +1093,7 @@ impl MailerService {
             }
         }
 
-        ProcessResult { sent, failed, errors }
+        ProcessResult { sent, failed, deferred, errors }
     }
 
     /// Create an email builder with defaults
@@ -290,7 +1102,9 @@ impl MailerService {
 
         let mut builder = EmailBuilder::new();
 
-        if let Some(from) = &config.default_from {
+        if !self.identity_pool.read().await.is_empty() {
+            builder = builder.from(EmailAddress::new(""));
+        } else if let Some(from) = &config.default_from {
             builder = builder.from(from.clone());
         }
 
@@ -307,13 +1121,8 @@ impl MailerService {
         to: &str,
         subject: &str,
         body: &str,
-    ) -> Result<(), MailerError> {
-        let config = self.config.read().await;
-
-        let from = config.default_from.clone()
-            .ok_or_else(|| MailerError::Configuration("Default from address not set".to_string()))?;
-
-        drop(config);
+    ) -> Result<DeliveryOutcome, MailerError> {
+        let from = self.default_sender().await?;
 
         let email = EmailBuilder::new()
             .from(from)
@@ -321,11 +1130,21 @@ impl MailerService {
             .subject(subject)
             .text(body)
             .build()
-            .map_err(|e| MailerError::Invalid(e))?;
+            .map_err(MailerError::Invalid)?;
 
         self.deliver(email).await
     }
 
+    /// Render the exact RFC 822 message that would be sent, without sending it
+    pub async fn render_mime(&self, email: &Email) -> Result<String, MailerError> {
+        let mut email = email.clone();
+        self.queue_service.blobs().resolve_attachments(&mut email).await
+            .map_err(|e| SmtpError::Attachment(e.to_string()))?;
+
+        let message = SmtpTransport::new(SmtpConfig::default()).build_message(&email)?;
+        Ok(String::from_utf8_lossy(&message.formatted()).into_owned())
+    }
+
     /// Test connection
     pub async fn test_connection(&self) -> Result<bool, MailerError> {
         let transport = self.transport.read().await;
@@ -366,11 +1185,85 @@ impl Default for MailerService {
     }
 }
 
+/// Recipients of a templated email
+#[derive(Debug, Clone)]
+pub struct TemplateRecipients {
+    pub to: EmailAddress,
+    pub cc: Vec<EmailAddress>,
+    pub bcc: Vec<EmailAddress>,
+    pub reply_to: Option<EmailAddress>,
+}
+
+impl TemplateRecipients {
+    pub fn new(to: EmailAddress) -> Self {
+        Self {
+            to,
+            cc: Vec::new(),
+            bcc: Vec::new(),
+            reply_to: None,
+        }
+    }
+
+    pub fn cc(mut self, address: impl Into<EmailAddress>) -> Self {
+        self.cc.push(address.into());
+        self
+    }
+
+    pub fn bcc(mut self, address: impl Into<EmailAddress>) -> Self {
+        self.bcc.push(address.into());
+        self
+    }
+
+    pub fn reply_to(mut self, address: impl Into<EmailAddress>) -> Self {
+        self.reply_to = Some(address.into());
+        self
+    }
+}
+
+/// What happened to an email handed to the mailer
+#[derive(Debug, Clone)]
+pub struct DeliveryOutcome {
+    /// ID of the email
+    pub email_id: Uuid,
+    /// Whether it was sent or queued
+    pub disposition: DeliveryDisposition,
+    /// Per-recipient outcome when sent; empty when queued
+    pub recipients: Vec<RecipientStatus>,
+}
+
+/// How an email left the mailer
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DeliveryDisposition {
+    /// Accepted by the SMTP server
+    Sent {
+        /// Message ID reported by the server, if any
+        message_id: Option<String>,
+    },
+    /// Added to the queue for later delivery
+    Queued {
+        /// ID of the queue item
+        queue_id: Uuid,
+    },
+}
+
+impl DeliveryOutcome {
+    /// Queue item ID, if the email was queued
+    pub fn queue_id(&self) -> Option<Uuid> {
+        match self.disposition {
+            DeliveryDisposition::Queued { queue_id } => Some(queue_id),
+            DeliveryDisposition::Sent { .. } => None,
+        }
+    }
+}
+
 /// Result of queue processing
 #[derive(Debug)]
 pub struct ProcessResult {
     pub sent: usize,
     pub failed: usize,
+    /// Items held back by the warm-up schedule or frequency cap, or
+    /// rerouted to another provider
+    pub deferred: usize,
     pub errors: Vec<(Uuid, String)>,
 }
 
@@ -5,9 +5,12 @@ pub mod template;
 pub mod queue;
 pub mod log;
 pub mod smtp;
+pub mod blob;
+pub mod tracking;
 
 pub use mailer::MailerService;
 pub use template::TemplateService;
-pub use queue::QueueService;
+pub use queue::{QueueService, WorkerId};
 pub use log::LogService;
-pub use smtp::SmtpTransport;
+pub use smtp::{SmtpTransport, SmtpConfig, SmtpError, TlsMode, MailTransport, TlsSettings, MinTlsVersion};
+pub use blob::BlobStore;
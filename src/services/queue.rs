@@ -2,14 +2,16 @@
 
 use std::collections::HashMap;
 use std::sync::Arc;
-use tokio::sync::RwLock;
+use tokio::sync::{mpsc, RwLock};
 use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
 use crate::models::{
-    Email, QueueItem, QueueStatus, QueueStats,
-    BatchSendRequest, BatchSendResult, BatchError, RetryPolicy,
+    Email, EmailPriority, QueueItem, QueueStatus, QueueStats, QueueEvent, QueueFilter,
+    BatchSendRequest, BatchSendResult, BatchError, Paginated, RetryPolicy, RecipientStatus,
 };
+use crate::services::BlobStore;
 
 /// Queue service error
 #[derive(Debug, thiserror::Error)]
@@ -20,8 +22,50 @@ pub enum QueueError {
     QueueFull,
     #[error("Invalid operation: {0}")]
     Invalid(String),
+    #[error("Duplicate of queue item {0}")]
+    Duplicate(Uuid),
 }
 
+/// Identity a queue worker records on the items it claims
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct WorkerId(String);
+
+impl WorkerId {
+    pub fn new(id: &str) -> Self {
+        Self(id.to_string())
+    }
+
+    /// `<hostname>-<pid>-<random suffix>`, so a stuck lease shows which
+    /// machine and process held it
+    pub fn auto() -> Self {
+        use rand::{distributions::Alphanumeric, Rng};
+
+        // lettre resolves the local hostname for EHLO
+        let host = lettre::transport::smtp::extension::ClientId::default().to_string();
+        let suffix: String = rand::thread_rng()
+            .sample_iter(&Alphanumeric)
+            .take(6)
+            .map(|c| char::from(c).to_ascii_lowercase())
+            .collect();
+
+        Self(format!("{}-{}-{}", host, std::process::id(), suffix))
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl std::fmt::Display for WorkerId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+/// Item id and enqueue time, keyed by email content hash
+type RecentEnqueues = HashMap<String, (Uuid, DateTime<Utc>)>;
+
 /// Queue service
 pub struct QueueService {
     /// Queue items
@@ -30,14 +74,42 @@ pub struct QueueService {
     retry_policy: RetryPolicy,
     /// Maximum queue size
     max_size: usize,
+    /// How long a claimed item may stay processing before it is reclaimed
+    lease_duration: chrono::Duration,
+    /// Waiting time that raises an item's priority by one
+    priority_aging: Option<chrono::Duration>,
+    /// Translates an email's priority into its queue priority
+    priority_map: fn(EmailPriority) -> i32,
+    /// How long an enqueued email's content blocks identical enqueues
+    dedupe_window: Option<chrono::Duration>,
+    /// Content hash of recent enqueues, with the item and when it was added
+    recent_enqueues: Arc<RwLock<RecentEnqueues>>,
+    /// Deduplicated attachment content
+    blobs: Arc<BlobStore>,
+    /// Queue change subscribers
+    subscribers: Arc<RwLock<Vec<mpsc::Sender<QueueEvent>>>>,
 }
 
+/// Minutes of completed sends used to estimate throughput for
+/// [`QueueService::backlog_pressure`]
+pub const BACKLOG_WINDOW_MINUTES: i64 = 15;
+
+/// Buffered events per subscriber before new events are dropped
+const SUBSCRIBER_BUFFER: usize = 1024;
+
 impl QueueService {
     pub fn new() -> Self {
         Self {
             items: Arc::new(RwLock::new(HashMap::new())),
             retry_policy: RetryPolicy::default(),
             max_size: 100_000,
+            lease_duration: chrono::Duration::minutes(10),
+            priority_aging: None,
+            priority_map: EmailPriority::queue_priority,
+            dedupe_window: None,
+            recent_enqueues: Arc::new(RwLock::new(HashMap::new())),
+            blobs: Arc::new(BlobStore::new()),
+            subscribers: Arc::new(RwLock::new(Vec::new())),
         }
     }
 
@@ -51,36 +123,94 @@ impl QueueService {
         self
     }
 
+    pub fn with_lease_duration(mut self, lease: chrono::Duration) -> Self {
+        self.lease_duration = lease;
+        self
+    }
+
+    /// Raise the priority of waiting items by one per `interval`, so old
+    /// low-priority mail is not starved by a steady stream of urgent mail
+    pub fn with_priority_aging(mut self, interval: chrono::Duration) -> Self {
+        self.priority_aging = Some(interval);
+        self
+    }
+
+    /// Reject enqueues of an email identical to one enqueued within `window`
+    pub fn with_dedupe_window(mut self, window: chrono::Duration) -> Self {
+        self.dedupe_window = Some(window);
+        self
+    }
+
+    /// Override how `EmailPriority` maps onto queue priority
+    pub fn with_priority_map(mut self, map: fn(EmailPriority) -> i32) -> Self {
+        self.priority_map = map;
+        self
+    }
+
     /// Add email to queue
-    pub async fn enqueue(&self, email: Email) -> Result<QueueItem, QueueError> {
+    pub async fn enqueue(&self, mut email: Email) -> Result<QueueItem, QueueError> {
         let items = self.items.read().await;
         if items.len() >= self.max_size {
             return Err(QueueError::QueueFull);
         }
         drop(items);
 
+        self.blobs.store_attachments(&mut email).await;
+
+        let priority = (self.priority_map)(email.priority);
         let item = QueueItem::new(email)
+            .with_priority(priority)
             .with_max_attempts(self.retry_policy.max_attempts);
 
         let mut items = self.items.write().await;
+        if let Err(e) = self.record_enqueue(&item).await {
+            drop(items);
+            self.blobs.release_attachments(&item.email).await;
+            return Err(e);
+        }
         items.insert(item.id, item.clone());
+        self.emit(&item).await;
 
         Ok(item)
     }
 
+    /// Remember `item`'s content, failing if identical content was enqueued
+    /// within the dedupe window
+    async fn record_enqueue(&self, item: &QueueItem) -> Result<(), QueueError> {
+        let Some(window) = self.dedupe_window else {
+            return Ok(());
+        };
+
+        let now = Utc::now();
+        let hash = item.email.content_hash();
+        let mut recent = self.recent_enqueues.write().await;
+        recent.retain(|_, (_, at)| now - *at < window);
+
+        if let Some((existing, _)) = recent.get(&hash) {
+            return Err(QueueError::Duplicate(*existing));
+        }
+        recent.insert(hash, (item.id, now));
+        Ok(())
+    }
+
     /// Schedule email for later
-    pub async fn schedule(&self, email: Email, send_at: DateTime<Utc>) -> Result<QueueItem, QueueError> {
+    pub async fn schedule(&self, mut email: Email, send_at: DateTime<Utc>) -> Result<QueueItem, QueueError> {
         let items = self.items.read().await;
         if items.len() >= self.max_size {
             return Err(QueueError::QueueFull);
         }
         drop(items);
 
+        self.blobs.store_attachments(&mut email).await;
+
+        let priority = (self.priority_map)(email.priority);
         let item = QueueItem::scheduled(email, send_at)
+            .with_priority(priority)
             .with_max_attempts(self.retry_policy.max_attempts);
 
         let mut items = self.items.write().await;
         items.insert(item.id, item.clone());
+        self.emit(&item).await;
 
         Ok(item)
     }
@@ -131,6 +261,86 @@ impl QueueService {
         }
     }
 
+    /// Add a batch of emails all-or-nothing.
+    ///
+    /// Every email is validated and capacity is checked before anything is
+    /// inserted; on failure the queue is left unchanged and the first error
+    /// is returned.
+    pub async fn enqueue_batch_atomic(&self, request: BatchSendRequest) -> Result<BatchSendResult, QueueError> {
+        for (index, email) in request.emails.iter().enumerate() {
+            email.validate()
+                .map_err(|e| QueueError::Invalid(format!("Email {}: {}", index, e)))?;
+        }
+
+        if !self.has_capacity(request.emails.len()).await {
+            return Err(QueueError::QueueFull);
+        }
+
+        let mut batch = Vec::with_capacity(request.emails.len());
+        for mut email in request.emails {
+            email.tags.extend(request.tags.clone());
+            self.blobs.store_attachments(&mut email).await;
+
+            let priority = (self.priority_map)(email.priority);
+            let mut item = match request.scheduled_at {
+                Some(send_at) => QueueItem::scheduled(email, send_at),
+                None => QueueItem::new(email),
+            };
+            item.max_attempts = request.max_attempts.unwrap_or(self.retry_policy.max_attempts);
+            item.priority = request.priority.unwrap_or(priority);
+            batch.push(item);
+        }
+
+        let mut items = self.items.write().await;
+
+        // Re-check now that we hold the lock
+        if items.len() + batch.len() > self.max_size {
+            drop(items);
+            for item in &batch {
+                self.blobs.release_attachments(&item.email).await;
+            }
+            return Err(QueueError::QueueFull);
+        }
+
+        let queue_ids: Vec<Uuid> = batch.iter().map(|item| item.id).collect();
+        for item in batch {
+            self.emit(&item).await;
+            items.insert(item.id, item);
+        }
+
+        Ok(BatchSendResult {
+            queued: queue_ids.len(),
+            failed: 0,
+            queue_ids,
+            errors: Vec::new(),
+        })
+    }
+
+    /// Subscribe to queue changes.
+    ///
+    /// Each receiver gets an event whenever an item is added or changes
+    /// status. Delivery never blocks the queue: if a receiver falls more than
+    /// its buffer behind, new events are dropped for it until it catches up.
+    pub async fn subscribe(&self) -> mpsc::Receiver<QueueEvent> {
+        let (tx, rx) = mpsc::channel(SUBSCRIBER_BUFFER);
+        let mut subscribers = self.subscribers.write().await;
+        subscribers.push(tx);
+        rx
+    }
+
+    /// Notify subscribers of an item's new state
+    async fn emit(&self, item: &QueueItem) {
+        let mut subscribers = self.subscribers.write().await;
+        if subscribers.is_empty() {
+            return;
+        }
+
+        let event = QueueEvent::new(item);
+        subscribers.retain(|tx| {
+            !matches!(tx.try_send(event.clone()), Err(mpsc::error::TrySendError::Closed(_)))
+        });
+    }
+
     /// Get item by ID
     pub async fn get(&self, id: Uuid) -> Option<QueueItem> {
         let items = self.items.read().await;
@@ -139,6 +349,12 @@ impl QueueService {
 
     /// Get next items to process
     pub async fn get_pending(&self, limit: usize) -> Vec<QueueItem> {
+        self.pending_page(limit, 0).await.items
+    }
+
+    /// Page of items ready to process, in processing order, with the
+    /// number ready in total
+    pub async fn pending_page(&self, limit: usize, offset: usize) -> Paginated<QueueItem> {
         let items = self.items.read().await;
         let now = Utc::now();
 
@@ -146,22 +362,22 @@ impl QueueService {
             .filter(|item| {
                 matches!(item.status, QueueStatus::Pending | QueueStatus::Deferred)
                     && item.scheduled_at <= now
-                    && item.next_retry_at.map_or(true, |t| t <= now)
+                    && item.next_retry_at.is_none_or(|t| t <= now)
             })
-            .cloned()
             .collect();
 
-        // Sort by priority (descending) then scheduled time (ascending)
-        pending.sort_by(|a, b| {
-            b.priority.cmp(&a.priority)
-                .then(a.scheduled_at.cmp(&b.scheduled_at))
-        });
+        // Sort by aged priority (descending) then how long each item has been due
+        pending.sort_by(|a, b| a.cmp_ready_at(b, now, self.priority_aging));
 
-        pending.truncate(limit);
-        pending
+        Paginated::from_matches(pending, limit, offset).map(QueueItem::clone)
     }
 
-    /// Claim item for processing
+    /// Claim item for processing.
+    ///
+    /// This is a conditional update: the item moves to processing only if it
+    /// is still pending or deferred when the write lock is held, so of several
+    /// workers racing for the same item exactly one wins and the rest get
+    /// `QueueError::Invalid`.
     pub async fn claim(&self, id: Uuid, worker_id: &str) -> Result<QueueItem, QueueError> {
         let mut items = self.items.write().await;
 
@@ -173,9 +389,37 @@ impl QueueService {
         }
 
         item.start_processing(worker_id);
+        self.emit(item).await;
         Ok(item.clone())
     }
 
+    /// Return items whose processing lease has expired to the pending queue
+    pub async fn reclaim_expired(&self) -> usize {
+        self.reclaim_expired_at(Utc::now()).await
+    }
+
+    /// Reclaim items whose lease had expired as of `now`
+    pub async fn reclaim_expired_at(&self, now: DateTime<Utc>) -> usize {
+        let mut items = self.items.write().await;
+        let mut count = 0;
+
+        for item in items.values_mut() {
+            let expired = item.status == QueueStatus::Processing
+                && item.started_at.is_some_and(|t| t + self.lease_duration <= now);
+
+            if expired {
+                item.status = QueueStatus::Pending;
+                item.worker_id = None;
+                item.started_at = None;
+                item.last_error = Some("Processing lease expired".to_string());
+                self.emit(item).await;
+                count += 1;
+            }
+        }
+
+        count
+    }
+
     /// Mark item as sent
     pub async fn mark_sent(&self, id: Uuid) -> Result<(), QueueError> {
         let mut items = self.items.write().await;
@@ -184,17 +428,48 @@ impl QueueService {
             .ok_or_else(|| QueueError::NotFound(id.to_string()))?;
 
         item.mark_sent();
+        self.emit(item).await;
+        Ok(())
+    }
+
+    /// Record per-recipient outcomes of the latest send attempt
+    pub async fn set_recipient_statuses(&self, id: Uuid, statuses: Vec<RecipientStatus>) -> Result<(), QueueError> {
+        let mut items = self.items.write().await;
+
+        let item = items.get_mut(&id)
+            .ok_or_else(|| QueueError::NotFound(id.to_string()))?;
+
+        item.recipient_statuses = statuses;
         Ok(())
     }
 
-    /// Mark item as failed
+    /// Mark item as failed, deferring it for retry only if the error is transient
     pub async fn mark_failed(&self, id: Uuid, error: &str) -> Result<(), QueueError> {
         let mut items = self.items.write().await;
 
         let item = items.get_mut(&id)
             .ok_or_else(|| QueueError::NotFound(id.to_string()))?;
 
-        item.mark_failed(error);
+        if self.retry_policy.is_retryable(error) {
+            // Jitter spreads out retries of items that failed together
+            let delay = self.retry_policy.get_delay(item.attempts.saturating_sub(1));
+            item.mark_failed_with_delay(error, self.retry_policy.apply_jitter(delay));
+        } else {
+            item.mark_failed_permanently(error);
+        }
+        self.emit(item).await;
+        Ok(())
+    }
+
+    /// Hand a failed item over to another provider instead of backing off
+    pub async fn reroute(&self, id: Uuid, provider: &str, error: &str) -> Result<(), QueueError> {
+        let mut items = self.items.write().await;
+
+        let item = items.get_mut(&id)
+            .ok_or_else(|| QueueError::NotFound(id.to_string()))?;
+
+        item.mark_rerouted(provider, error);
+        self.emit(item).await;
         Ok(())
     }
 
@@ -210,11 +485,17 @@ impl QueueService {
         }
 
         item.cancel();
+        self.emit(item).await;
         Ok(())
     }
 
     /// Retry a failed item
     pub async fn retry(&self, id: Uuid) -> Result<(), QueueError> {
+        self.retry_at(id, None).await
+    }
+
+    /// Retry a failed item at `at`, or right away when `None`
+    pub async fn retry_at(&self, id: Uuid, at: Option<DateTime<Utc>>) -> Result<(), QueueError> {
         let mut items = self.items.write().await;
 
         let item = items.get_mut(&id)
@@ -224,49 +505,122 @@ impl QueueService {
             return Err(QueueError::Invalid("Item must be failed or cancelled".to_string()));
         }
 
-        item.status = QueueStatus::Pending;
-        item.attempts = 0;
-        item.last_error = None;
-        item.next_retry_at = None;
-        item.scheduled_at = Utc::now();
+        item.requeue_at(at.unwrap_or_else(Utc::now));
+        self.emit(item).await;
 
         Ok(())
     }
 
+    /// Cancel every unsent item matching `predicate`, returning how many
+    /// were cancelled
+    pub async fn cancel_where(&self, predicate: impl Fn(&QueueItem) -> bool) -> usize {
+        let mut items = self.items.write().await;
+        let mut count = 0;
+
+        for item in items.values_mut() {
+            if matches!(item.status, QueueStatus::Sent | QueueStatus::Cancelled) || !predicate(item) {
+                continue;
+            }
+            item.cancel();
+            self.emit(item).await;
+            count += 1;
+        }
+
+        count
+    }
+
+    /// Requeue every failed or cancelled item matching `predicate`,
+    /// returning how many were requeued
+    pub async fn retry_where(&self, predicate: impl Fn(&QueueItem) -> bool) -> usize {
+        let mut items = self.items.write().await;
+        let mut count = 0;
+
+        for item in items.values_mut() {
+            if !matches!(item.status, QueueStatus::Failed | QueueStatus::Cancelled) || !predicate(item) {
+                continue;
+            }
+            item.requeue();
+            self.emit(item).await;
+            count += 1;
+        }
+
+        count
+    }
+
+    /// Cancel every item currently in `status`
+    pub async fn cancel_by_status(&self, status: QueueStatus) -> usize {
+        self.cancel_where(|item| item.status == status).await
+    }
+
+    /// Requeue every failed item
+    pub async fn retry_failed(&self) -> usize {
+        self.retry_where(|item| matches!(item.status, QueueStatus::Failed)).await
+    }
+
     /// Get queue statistics
     pub async fn stats(&self) -> QueueStats {
         let items = self.items.read().await;
-        let now = Utc::now();
-        let day_ago = now - chrono::Duration::hours(24);
+        QueueStats::compute(items.values(), Utc::now())
+    }
 
-        let mut stats = QueueStats::default();
+    /// Estimated minutes to drain the queue at the recent send rate, as a
+    /// single autoscaling signal.
+    ///
+    /// Counts pending, deferred and processing items against the sends
+    /// completed in the last [`BACKLOG_WINDOW_MINUTES`]. With no recent
+    /// sends, one per window is assumed so a stalled queue still reports a
+    /// large but finite value.
+    pub async fn backlog_pressure(&self) -> f64 {
+        self.backlog_pressure_at(Utc::now()).await
+    }
 
-        for item in items.values() {
-            match item.status {
-                QueueStatus::Pending => stats.pending += 1,
-                QueueStatus::Processing => stats.processing += 1,
-                QueueStatus::Sent => {
-                    if item.completed_at.map_or(false, |t| t > day_ago) {
-                        stats.sent += 1;
-                    }
-                }
-                QueueStatus::Failed => {
-                    if item.completed_at.map_or(false, |t| t > day_ago) {
-                        stats.failed += 1;
-                    }
-                }
-                QueueStatus::Deferred => stats.deferred += 1,
-                QueueStatus::Cancelled => {}
-            }
+    /// [`backlog_pressure`](Self::backlog_pressure) as of `now`
+    pub async fn backlog_pressure_at(&self, now: DateTime<Utc>) -> f64 {
+        let items = self.items.read().await;
+        let window_start = now - chrono::Duration::minutes(BACKLOG_WINDOW_MINUTES);
+
+        let backlog = items.values()
+            .filter(|item| matches!(item.status, QueueStatus::Pending | QueueStatus::Deferred | QueueStatus::Processing))
+            .count();
+        if backlog == 0 {
+            return 0.0;
         }
 
-        // Calculate rates
-        let total = stats.sent + stats.failed;
-        if total > 0 {
-            stats.success_rate = (stats.sent as f64 / total as f64) * 100.0;
+        let recent_sends = items.values()
+            .filter(|item| item.status == QueueStatus::Sent
+                && item.completed_at.is_some_and(|t| t > window_start && t <= now))
+            .count()
+            .max(1);
+
+        let per_minute = recent_sends as f64 / BACKLOG_WINDOW_MINUTES as f64;
+        backlog as f64 / per_minute
+    }
+
+    /// How long the oldest pending or deferred item has been waiting
+    pub async fn oldest_pending_age(&self) -> Option<chrono::Duration> {
+        self.oldest_pending_age_at(Utc::now()).await
+    }
+
+    /// [`oldest_pending_age`](Self::oldest_pending_age) as of `now`
+    pub async fn oldest_pending_age_at(&self, now: DateTime<Utc>) -> Option<chrono::Duration> {
+        let items = self.items.read().await;
+        items.values()
+            .filter(|item| matches!(item.status, QueueStatus::Pending | QueueStatus::Deferred))
+            .map(|item| item.created_at)
+            .min()
+            .map(|created_at| now - created_at)
+    }
+
+    /// Count all items by status, without any time window
+    pub async fn counts_by_status(&self) -> HashMap<QueueStatus, u64> {
+        let items = self.items.read().await;
+        let mut counts = HashMap::new();
+
+        for item in items.values() {
+            *counts.entry(item.status).or_insert(0) += 1;
         }
 
-        stats
+        counts
     }
 
     /// List items by status
@@ -281,47 +635,136 @@ impl QueueService {
             .collect()
     }
 
+    /// List items whose email carries `tag`, ordered by scheduled time
+    pub async fn list_by_tag(&self, tag: &str, limit: usize, offset: usize) -> Vec<QueueItem> {
+        self.query(QueueFilter {
+            tag: Some(tag.to_string()),
+            offset: offset as u32,
+            limit: limit as u32,
+            ..Default::default()
+        }).await
+    }
+
     /// Search items
     pub async fn search(&self, query: &str, limit: usize) -> Vec<QueueItem> {
+        self.search_page(query, limit, 0).await.items
+    }
+
+    /// Search items by subject or To address, ordered by scheduled time,
+    /// with the number of matches before pagination
+    pub async fn search_page(&self, query: &str, limit: usize, offset: usize) -> Paginated<QueueItem> {
         let items = self.items.read().await;
         let query_lower = query.to_lowercase();
 
-        items.values()
+        let mut matching: Vec<&QueueItem> = items.values()
             .filter(|item| {
                 item.email.subject.to_lowercase().contains(&query_lower)
                     || item.email.to.iter().any(|a| a.email.to_lowercase().contains(&query_lower))
             })
-            .take(limit)
-            .cloned()
-            .collect()
+            .collect();
+        matching.sort_by(|a, b| a.scheduled_at.cmp(&b.scheduled_at).then(a.id.cmp(&b.id)));
+
+        Paginated::from_matches(matching, limit, offset).map(QueueItem::clone)
+    }
+
+    /// Query items, ordered by scheduled time
+    pub async fn query(&self, filter: QueueFilter) -> Vec<QueueItem> {
+        self.query_page(filter).await.items
+    }
+
+    /// Query items, ordered by scheduled time, with the number of matches
+    /// before pagination
+    pub async fn query_page(&self, filter: QueueFilter) -> Paginated<QueueItem> {
+        let items = self.items.read().await;
+
+        let mut matching: Vec<&QueueItem> = items.values()
+            .filter(|item| filter.matches(item))
+            .collect();
+        matching.sort_by(|a, b| a.scheduled_at.cmp(&b.scheduled_at).then(a.id.cmp(&b.id)));
+
+        Paginated::from_matches(matching, filter.limit as usize, filter.offset as usize)
+            .map(QueueItem::clone)
     }
 
     /// Clear completed items older than duration
     pub async fn cleanup(&self, older_than: chrono::Duration) -> usize {
         let mut items = self.items.write().await;
-        let cutoff = Utc::now() - older_than;
 
-        let to_remove: Vec<Uuid> = items.iter()
-            .filter(|(_, item)| {
-                matches!(item.status, QueueStatus::Sent | QueueStatus::Failed | QueueStatus::Cancelled)
-                    && item.completed_at.map_or(false, |t| t < cutoff)
-            })
-            .map(|(id, _)| *id)
-            .collect();
+        let to_remove = Self::expired(&items, older_than);
+        let count = to_remove.len();
+        for id in to_remove {
+            if let Some(item) = items.remove(&id) {
+                self.blobs.release_attachments(&item.email).await;
+            }
+        }
+
+        count
+    }
+
+    /// Like [`cleanup`](Self::cleanup), but hand each removed item to
+    /// `archiver` first, with attachment content restored from the blob
+    /// store. Returns the number archived.
+    pub async fn cleanup_with_archive(
+        &self,
+        older_than: chrono::Duration,
+        archiver: impl Fn(QueueItem),
+    ) -> usize {
+        let mut items = self.items.write().await;
 
+        let to_remove = Self::expired(&items, older_than);
         let count = to_remove.len();
         for id in to_remove {
-            items.remove(&id);
+            if let Some(mut item) = items.remove(&id) {
+                // Restore content before the blobs are released; a missing
+                // blob shouldn't stop the rest being archived
+                let _ = self.blobs.resolve_attachments(&mut item.email).await;
+                self.blobs.release_attachments(&item.email).await;
+                archiver(item);
+            }
         }
 
         count
     }
 
+    /// Completed items finished before the cutoff
+    fn expired(items: &HashMap<Uuid, QueueItem>, older_than: chrono::Duration) -> Vec<Uuid> {
+        let cutoff = Utc::now() - older_than;
+
+        items.iter()
+            .filter(|(_, item)| {
+                matches!(item.status, QueueStatus::Sent | QueueStatus::Failed | QueueStatus::Cancelled)
+                    && item.completed_at.is_some_and(|t| t < cutoff)
+            })
+            .map(|(id, _)| *id)
+            .collect()
+    }
+
+    /// Get attachment blob store
+    pub fn blobs(&self) -> &Arc<BlobStore> {
+        &self.blobs
+    }
+
     /// Get retry policy
     pub fn retry_policy(&self) -> &RetryPolicy {
         &self.retry_policy
     }
 
+    /// Move a waiting item to a new send time
+    pub async fn reschedule(&self, id: Uuid, send_at: DateTime<Utc>) -> Result<(), QueueError> {
+        let mut items = self.items.write().await;
+
+        let item = items.get_mut(&id)
+            .ok_or_else(|| QueueError::NotFound(id.to_string()))?;
+
+        if !matches!(item.status, QueueStatus::Pending | QueueStatus::Deferred) {
+            return Err(QueueError::Invalid(format!("Item status is {:?}", item.status)));
+        }
+
+        item.scheduled_at = send_at;
+        self.emit(item).await;
+        Ok(())
+    }
+
     /// Update item priority
     pub async fn set_priority(&self, id: Uuid, priority: i32) -> Result<(), QueueError> {
         let mut items = self.items.write().await;
@@ -3,14 +3,20 @@
 use std::time::Duration;
 use lettre::{
     AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor,
-    message::{header::ContentType, Attachment as LettreAttachment, MultiPart, SinglePart},
+    message::{header::{ContentDisposition, ContentType, HeaderName, HeaderValue}, Attachment as LettreAttachment, MultiPart, SinglePart},
     transport::smtp::{
-        authentication::Credentials,
-        client::{Tls, TlsParameters},
+        authentication::{Credentials, Mechanism},
+        client::{AsyncSmtpConnection, Tls, TlsParameters, TlsParametersBuilder, TlsVersion},
+        commands::{Data, Ehlo, Mail, Rcpt},
+        extension::{ClientId, Extension, MailBodyParameter, MailParameter, RcptParameter},
+        response::Response,
     },
 };
 
-use crate::models::{Email, Attachment, EmailPriority};
+use std::sync::{LazyLock, RwLock};
+use serde::{Deserialize, Serialize};
+
+use crate::models::{Email, Attachment, CalendarInvite, Disposition, DsnRequest, EmailPriority, UNDISCLOSED_RECIPIENTS};
 
 /// SMTP transport error
 #[derive(Debug, thiserror::Error)]
@@ -25,10 +31,13 @@ pub enum SmtpError {
     InvalidEmail(String),
     #[error("Configuration error: {0}")]
     Configuration(String),
+    #[error("Attachment error: {0}")]
+    Attachment(String),
 }
 
 /// SMTP configuration
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
 pub struct SmtpConfig {
     /// SMTP host
     pub host: String,
@@ -44,9 +53,12 @@ pub struct SmtpConfig {
     pub timeout_secs: u64,
     /// Max connections in pool
     pub pool_size: u32,
+    /// Certificate checks and protocol versions for TLS connections
+    pub tls_settings: TlsSettings,
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
 pub enum TlsMode {
     /// No TLS
     None,
@@ -56,6 +68,49 @@ pub enum TlsMode {
     Tls,
 }
 
+/// Connection-level TLS settings
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TlsSettings {
+    /// Oldest TLS version to negotiate
+    pub min_version: MinTlsVersion,
+    /// Accept expired, self-signed or otherwise invalid certificates.
+    ///
+    /// **Development only.** This disables certificate verification and
+    /// leaves the connection open to interception; never enable it against
+    /// a production mail server.
+    pub accept_invalid_certs: bool,
+    /// Server name to send (SNI) and verify the certificate against;
+    /// defaults to the SMTP host
+    pub sni_name: Option<String>,
+}
+
+/// Minimum TLS protocol version
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MinTlsVersion {
+    /// TLS 1.0, for legacy servers only
+    Tls10,
+    /// TLS 1.1, for legacy servers only
+    Tls11,
+    /// TLS 1.2
+    #[default]
+    Tls12,
+    /// TLS 1.3
+    Tls13,
+}
+
+impl From<MinTlsVersion> for TlsVersion {
+    fn from(version: MinTlsVersion) -> Self {
+        match version {
+            MinTlsVersion::Tls10 => TlsVersion::Tlsv10,
+            MinTlsVersion::Tls11 => TlsVersion::Tlsv11,
+            MinTlsVersion::Tls12 => TlsVersion::Tlsv12,
+            MinTlsVersion::Tls13 => TlsVersion::Tlsv13,
+        }
+    }
+}
+
 impl Default for SmtpConfig {
     fn default() -> Self {
         Self {
@@ -66,6 +121,7 @@ impl Default for SmtpConfig {
             tls: TlsMode::StartTls,
             timeout_secs: 30,
             pool_size: 10,
+            tls_settings: TlsSettings::default(),
         }
     }
 }
@@ -90,6 +146,31 @@ impl SmtpConfig {
         self
     }
 
+    pub fn with_tls_settings(mut self, settings: TlsSettings) -> Self {
+        self.tls_settings = settings;
+        self
+    }
+
+    /// TLS parameters described by [`tls_settings`](Self::tls_settings)
+    pub fn tls_parameters_builder(&self) -> TlsParametersBuilder {
+        let settings = &self.tls_settings;
+        let domain = settings.sni_name.clone().unwrap_or_else(|| self.host.clone());
+
+        TlsParameters::builder(domain)
+            .set_min_tls_version(settings.min_version.into())
+            .dangerous_accept_invalid_certs(settings.accept_invalid_certs)
+    }
+
+    /// Build the TLS parameters for a connection
+    pub fn tls_parameters(&self) -> Result<TlsParameters, SmtpError> {
+        if self.tls_settings.accept_invalid_certs {
+            tracing::warn!(host = %self.host, "accepting invalid TLS certificates; development use only");
+        }
+        self.tls_parameters_builder()
+            .build()
+            .map_err(|e| SmtpError::Configuration(e.to_string()))
+    }
+
     /// Common configurations
     pub fn gmail(username: &str, password: &str) -> Self {
         Self::new("smtp.gmail.com", 587)
@@ -125,35 +206,36 @@ impl SmtpConfig {
 /// SMTP transport service
 pub struct SmtpTransport {
     config: SmtpConfig,
-    transport: Option<AsyncSmtpTransport<Tokio1Executor>>,
+    transport: RwLock<Option<AsyncSmtpTransport<Tokio1Executor>>>,
 }
 
 impl SmtpTransport {
     pub fn new(config: SmtpConfig) -> Self {
         Self {
             config,
-            transport: None,
+            transport: RwLock::new(None),
         }
     }
 
-    /// Connect to SMTP server
-    pub async fn connect(&mut self) -> Result<(), SmtpError> {
+    /// Connect to SMTP server, replacing any existing connection
+    pub async fn connect(&self) -> Result<(), SmtpError> {
         let builder = match self.config.tls {
             TlsMode::None => {
                 AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(&self.config.host)
             }
             TlsMode::StartTls => {
-                let tls = TlsParameters::builder(self.config.host.clone())
-                    .build()
-                    .map_err(|e| SmtpError::Configuration(e.to_string()))?;
+                let tls = self.config.tls_parameters()?;
 
                 AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(&self.config.host)
                     .map_err(|e| SmtpError::Connection(e.to_string()))?
                     .tls(Tls::Required(tls))
             }
             TlsMode::Tls => {
+                let tls = self.config.tls_parameters()?;
+
                 AsyncSmtpTransport::<Tokio1Executor>::relay(&self.config.host)
                     .map_err(|e| SmtpError::Connection(e.to_string()))?
+                    .tls(Tls::Wrapper(tls))
             }
         };
 
@@ -174,33 +256,174 @@ impl SmtpTransport {
         transport.test_connection().await
             .map_err(|e| SmtpError::Connection(e.to_string()))?;
 
-        self.transport = Some(transport);
+        *self.transport.write().unwrap() = Some(transport);
         Ok(())
     }
 
-    /// Send an email
-    pub async fn send(&self, email: &Email) -> Result<SendResult, SmtpError> {
-        let transport = self.transport.as_ref()
-            .ok_or_else(|| SmtpError::Connection("Not connected".to_string()))?;
+    /// Current connection, if any
+    fn current(&self) -> Result<AsyncSmtpTransport<Tokio1Executor>, SmtpError> {
+        self.transport.read().unwrap().clone()
+            .ok_or_else(|| SmtpError::Connection("Not connected".to_string()))
+    }
+
+    /// Check the server still answers on the current connection
+    pub async fn healthy(&self) -> bool {
+        self.test_connection().await.unwrap_or(false)
+    }
 
+    /// Send an email.
+    ///
+    /// Messages to a single recipient go over the pooled connection, which
+    /// reconnects once and retries if it has dropped. Messages to several
+    /// recipients, or asking for DSN, get a session of their own so each
+    /// recipient can be accepted or refused separately.
+    pub async fn send(&self, email: &Email) -> Result<SendResult, SmtpError> {
         let message = self.build_message(email)?;
 
+        if email.request_dsn.is_some() || message.envelope().to().len() > 1 {
+            return self.send_session(&message, email.request_dsn).await;
+        }
+
+        match self.send_message(message.clone()).await {
+            Err(SmtpError::Connection(_)) => {
+                self.connect().await?;
+                self.send_message(message).await
+            }
+            result => result,
+        }
+    }
+
+    /// Send a built message over the current connection
+    async fn send_message(&self, message: Message) -> Result<SendResult, SmtpError> {
+        let transport = self.current()?;
+
+        let size = message.formatted().len();
         let response = transport.send(message).await
-            .map_err(|e| SmtpError::Send(e.to_string()))?;
+            .map_err(send_error)?;
+
+        Ok(send_result(&response, size))
+    }
+
+    /// Send over a dedicated session, issuing RCPT for each recipient.
+    ///
+    /// The pooled transport gives up on the whole message at the first
+    /// refused recipient and can't set MAIL/RCPT parameters, so this opens
+    /// its own connection. Recipients refused with a permanent reply are
+    /// reported in [`SendResult::rejected`] and the message still goes to
+    /// the rest. DSN parameters are only sent if the server advertises DSN.
+    async fn send_session(&self, message: &Message, dsn: Option<DsnRequest>) -> Result<SendResult, SmtpError> {
+        let hello = ClientId::default();
+        let tls = || self.config.tls_parameters();
+
+        let implicit_tls = match self.config.tls {
+            TlsMode::Tls => Some(tls()?),
+            _ => None,
+        };
+
+        let mut conn = AsyncSmtpConnection::connect_tokio1(
+            (self.config.host.as_str(), self.config.port),
+            Some(Duration::from_secs(self.config.timeout_secs)),
+            &hello,
+            implicit_tls,
+            None,
+        ).await
+            .map_err(|e| SmtpError::Connection(e.to_string()))?;
+
+        if self.config.tls == TlsMode::StartTls {
+            conn.starttls(tls()?, &hello).await
+                .map_err(|e| SmtpError::Connection(e.to_string()))?;
+        }
+
+        // lettre doesn't track the DSN extension, so check the EHLO reply
+        let dsn = match dsn {
+            Some(dsn) => {
+                let ehlo = conn.command(Ehlo::new(hello)).await
+                    .map_err(|e| SmtpError::Connection(e.to_string()))?;
+                let supports_dsn = ehlo.message()
+                    .any(|line| line.split_whitespace().next().is_some_and(|k| k.eq_ignore_ascii_case("DSN")));
+                supports_dsn.then_some(dsn)
+            }
+            None => None,
+        };
+
+        if let (Some(username), Some(password)) = (&self.config.username, &self.config.password) {
+            let creds = Credentials::new(username.clone(), password.clone());
+            conn.auth(&[Mechanism::Plain, Mechanism::Login], &creds).await
+                .map_err(|e| SmtpError::Authentication(e.to_string()))?;
+        }
+
+        let envelope = message.envelope();
+        let body = message.formatted();
+
+        let mut mail_params = Vec::new();
+        if dsn.is_some() {
+            mail_params.push(MailParameter::Other {
+                keyword: "RET".to_string(),
+                value: Some("HDRS".to_string()),
+            });
+        }
+        if !body.is_ascii() && conn.server_info().supports_feature(Extension::EightBitMime) {
+            mail_params.push(MailParameter::Body(MailBodyParameter::EightBitMime));
+        }
+        let rcpt_params: Vec<_> = dsn.iter()
+            .map(|dsn| RcptParameter::Other {
+                keyword: "NOTIFY".to_string(),
+                value: Some(dsn.notify_value()),
+            })
+            .collect();
+
+        conn.command(Mail::new(envelope.from().cloned(), mail_params)).await
+            .map_err(send_error)?;
+
+        let mut rejected = Vec::new();
+        for to in envelope.to() {
+            match conn.command(Rcpt::new(to.clone(), rcpt_params.clone())).await {
+                Ok(_) => {}
+                Err(e) if e.is_permanent() => rejected.push((to.to_string(), reply_text(&e))),
+                Err(e) => {
+                    conn.abort().await;
+                    return Err(send_error(e));
+                }
+            }
+        }
+
+        // Nobody left to deliver to
+        if rejected.len() == envelope.to().len() {
+            conn.abort().await;
+            let reply = rejected.into_iter().next().map(|(_, reply)| reply).unwrap_or_default();
+            return Err(SmtpError::Send(reply));
+        }
+
+        conn.command(Data).await
+            .map_err(send_error)?;
+        let response = conn.message(&body).await
+            .map_err(send_error)?;
+
+        let _ = conn.quit().await;
 
         Ok(SendResult {
-            message_id: response.message().map(|m| m.to_string()),
-            code: response.code().as_str().to_string(),
-            message: response.message().map(|m| m.to_string()),
+            rejected,
+            ..send_result(&response, body.len())
         })
     }
 
     /// Build lettre Message from our Email
-    fn build_message(&self, email: &Email) -> Result<Message, SmtpError> {
+    pub(crate) fn build_message(&self, email: &Email) -> Result<Message, SmtpError> {
+        email.check_header_injection().map_err(SmtpError::InvalidEmail)?;
+
+        // Blank bodies are left out rather than sent as empty parts
+        let text = email.text_body.as_deref().filter(|body| !body.trim().is_empty());
+        let html = email.html_body.as_deref().filter(|body| !body.trim().is_empty());
+        let calendar = email.calendar.as_ref().map(Self::calendar_part).transpose()?;
+        let mut part = Self::body_part(text, html, calendar)
+            .ok_or_else(|| SmtpError::InvalidEmail("Email has no body".to_string()))?;
+
         let from_mailbox: lettre::message::Mailbox = email.from.formatted()
             .parse()
             .map_err(|e: lettre::address::AddressError| SmtpError::InvalidEmail(e.to_string()))?;
 
+        // lettre encodes non-ASCII subjects and display names as UTF-8
+        // RFC 2047 encoded words
         let mut builder = Message::builder()
             .from(from_mailbox)
             .subject(&email.subject);
@@ -227,6 +450,14 @@ impl SmtpTransport {
             builder = builder.bcc(mailbox);
         }
 
+        // Broadcasts name no visible recipient
+        if email.is_broadcast() {
+            builder = builder.raw_header(HeaderValue::new(
+                HeaderName::new_from_ascii_str("To"),
+                UNDISCLOSED_RECIPIENTS.to_string(),
+            ));
+        }
+
         // Reply-to
         if let Some(reply_to) = &email.reply_to {
             let mailbox: lettre::message::Mailbox = reply_to.formatted()
@@ -235,103 +466,123 @@ impl SmtpTransport {
             builder = builder.reply_to(mailbox);
         }
 
+        // Threading headers
+        if let Some(in_reply_to) = &email.in_reply_to {
+            builder = builder.in_reply_to(angle_message_id(in_reply_to));
+        }
+        if !email.references.is_empty() {
+            let references: Vec<String> = email.references.iter()
+                .map(|id| angle_message_id(id))
+                .collect();
+            builder = builder.references(references.join(" "));
+        }
+
         // Custom headers
         for (name, value) in &email.headers {
-            builder = builder.header(lettre::message::header::HeaderName::new_from_ascii_str(name)
-                .map_err(|e| SmtpError::InvalidEmail(e.to_string()))?
-                .into_owned());
+            let name = HeaderName::new_from_ascii(name.clone())
+                .map_err(|e| SmtpError::InvalidEmail(e.to_string()))?;
+            builder = builder.raw_header(HeaderValue::new(name, value.clone()));
         }
 
         // Priority header
         if email.priority != EmailPriority::Normal {
-            builder = builder.header(lettre::message::header::HeaderName::new_from_ascii_str("X-Priority")
-                .map_err(|e| SmtpError::InvalidEmail(e.to_string()))?
-                .into_owned());
+            builder = builder.raw_header(HeaderValue::new(
+                HeaderName::new_from_ascii_str("X-Priority"),
+                email.priority.to_header_value().to_string(),
+            ));
         }
 
-        // Build body
-        let message = if !email.attachments.is_empty() {
-            // Multipart with attachments
-            let mut multipart = if email.html_body.is_some() && email.text_body.is_some() {
-                MultiPart::alternative()
-                    .singlepart(
-                        SinglePart::builder()
-                            .content_type(ContentType::TEXT_PLAIN)
-                            .body(email.text_body.clone().unwrap_or_default())
-                    )
-                    .singlepart(
-                        SinglePart::builder()
-                            .content_type(ContentType::TEXT_HTML)
-                            .body(email.html_body.clone().unwrap_or_default())
-                    )
-            } else if let Some(html) = &email.html_body {
-                MultiPart::mixed()
-                    .singlepart(
-                        SinglePart::builder()
-                            .content_type(ContentType::TEXT_HTML)
-                            .body(html.clone())
-                    )
-            } else {
-                MultiPart::mixed()
-                    .singlepart(
-                        SinglePart::builder()
-                            .content_type(ContentType::TEXT_PLAIN)
-                            .body(email.text_body.clone().unwrap_or_default())
-                    )
-            };
-
-            // Add attachments
-            let mut mixed = MultiPart::mixed().singlepart(
-                SinglePart::builder()
-                    .content_type(ContentType::TEXT_PLAIN)
-                    .body(email.text_body.clone().unwrap_or_default())
-            );
-
-            for att in &email.attachments {
-                let content_type = att.content_type.parse::<ContentType>()
-                    .unwrap_or(ContentType::TEXT_PLAIN);
-
-                let attachment = LettreAttachment::new(att.filename.clone())
-                    .body(att.content.clone(), content_type);
-
-                mixed = mixed.singlepart(attachment);
+        // mixed(related(alternative(text, html), inline...), attachment...),
+        // dropping the layers that would be empty
+        let (inline, regular): (Vec<&Attachment>, Vec<&Attachment>) = email.attachments.iter()
+            .partition(|a| a.is_inline() && a.content_id.is_some());
+
+        if !inline.is_empty() {
+            let mut related = part.nest_in(MultiPart::related());
+            for att in inline {
+                related = related.singlepart(Self::attachment_part(att)?);
             }
+            part = MimePart::Multi(related);
+        }
 
-            builder.multipart(mixed)
-                .map_err(|e| SmtpError::InvalidEmail(e.to_string()))?
-        } else if email.html_body.is_some() && email.text_body.is_some() {
-            // Alternative multipart (text + HTML)
-            let multipart = MultiPart::alternative()
-                .singlepart(
-                    SinglePart::builder()
-                        .content_type(ContentType::TEXT_PLAIN)
-                        .body(email.text_body.clone().unwrap_or_default())
-                )
-                .singlepart(
-                    SinglePart::builder()
-                        .content_type(ContentType::TEXT_HTML)
-                        .body(email.html_body.clone().unwrap_or_default())
-                );
-
-            builder.multipart(multipart)
-                .map_err(|e| SmtpError::InvalidEmail(e.to_string()))?
-        } else if let Some(html) = &email.html_body {
-            builder.header(ContentType::TEXT_HTML)
-                .body(html.clone())
-                .map_err(|e| SmtpError::InvalidEmail(e.to_string()))?
-        } else {
-            builder.header(ContentType::TEXT_PLAIN)
-                .body(email.text_body.clone().unwrap_or_default())
-                .map_err(|e| SmtpError::InvalidEmail(e.to_string()))?
-        };
+        if !regular.is_empty() {
+            let mut mixed = part.nest_in(MultiPart::mixed());
+            for att in regular {
+                mixed = mixed.singlepart(Self::attachment_part(att)?);
+            }
+            part = MimePart::Multi(mixed);
+        }
+
+        let message = match part {
+            MimePart::Single(single) => builder.singlepart(single),
+            MimePart::Multi(multi) => builder.multipart(multi),
+        }
+            .map_err(|e| SmtpError::InvalidEmail(e.to_string()))?;
 
         Ok(message)
     }
 
+    /// Text, HTML and calendar bodies, as an alternative when there is more
+    /// than one; `None` without any
+    fn body_part(text: Option<&str>, html: Option<&str>, calendar: Option<SinglePart>) -> Option<MimePart> {
+        let text_part = |text: &str| SinglePart::builder().content_type(ContentType::TEXT_PLAIN).body(text.to_string());
+        let html_part = |html: &str| SinglePart::builder().content_type(ContentType::TEXT_HTML).body(html.to_string());
+
+        let mut parts = text.map(text_part).into_iter()
+            .chain(html.map(html_part))
+            .chain(calendar);
+
+        let first = parts.next()?;
+        let rest: Vec<SinglePart> = parts.collect();
+        if rest.is_empty() {
+            return Some(MimePart::Single(first));
+        }
+
+        let alternative = rest.into_iter()
+            .fold(MultiPart::alternative().singlepart(first), |multi, part| multi.singlepart(part));
+        Some(MimePart::Multi(alternative))
+    }
+
+    /// `text/calendar` part carrying the invite's iTIP method
+    fn calendar_part(invite: &CalendarInvite) -> Result<SinglePart, SmtpError> {
+        if invite.method.is_empty() || !invite.method.chars().all(|c| c.is_ascii_alphanumeric() || c == '-') {
+            return Err(SmtpError::InvalidEmail(format!("Invalid calendar method: {}", invite.method)));
+        }
+
+        let content_type = ContentType::parse(&format!("text/calendar; charset=utf-8; method={}", invite.method))
+            .map_err(|e| SmtpError::InvalidEmail(e.to_string()))?;
+
+        Ok(SinglePart::builder().content_type(content_type).body(invite.ics.clone()))
+    }
+
+    /// Attachment part with a `Content-Disposition` naming the file;
+    /// inline parts with a Content-ID are referenced by it instead
+    fn attachment_part(att: &Attachment) -> Result<SinglePart, SmtpError> {
+        let content_type = att.content_type.parse::<ContentType>()
+            .unwrap_or(ContentType::TEXT_PLAIN);
+
+        let content = att.read_content()
+            .map_err(|e| SmtpError::Attachment(format!("{}: {}", att.filename, e)))?;
+
+        let part = match (att.disposition, &att.content_id) {
+            (Disposition::Inline, Some(cid)) => {
+                LettreAttachment::new_inline_with_name(cid.clone(), att.filename.clone())
+                    .body(content, content_type)
+            }
+            (Disposition::Inline, None) => SinglePart::builder()
+                .header(ContentDisposition::inline_with_name(&att.filename))
+                .content_type(content_type)
+                .body(content),
+            (Disposition::Attachment, _) => LettreAttachment::new(att.filename.clone())
+                .body(content, content_type),
+        };
+
+        Ok(part)
+    }
+
     /// Test connection
     pub async fn test_connection(&self) -> Result<bool, SmtpError> {
-        let transport = self.transport.as_ref()
-            .ok_or_else(|| SmtpError::Connection("Not connected".to_string()))?;
+        let transport = self.current()?;
 
         transport.test_connection().await
             .map_err(|e| SmtpError::Connection(e.to_string()))
@@ -344,7 +595,143 @@ impl SmtpTransport {
 
     /// Check if connected
     pub fn is_connected(&self) -> bool {
-        self.transport.is_some()
+        self.transport.read().unwrap().is_some()
+    }
+}
+
+/// A MIME body part, either a leaf or a nested multipart
+enum MimePart {
+    Single(SinglePart),
+    Multi(MultiPart),
+}
+
+impl MimePart {
+    /// Make this the first part of a new multipart
+    fn nest_in(self, parent: lettre::message::MultiPartBuilder) -> MultiPart {
+        match self {
+            Self::Single(part) => parent.singlepart(part),
+            Self::Multi(part) => parent.multipart(part),
+        }
+    }
+}
+
+/// Reply code leading a 250 response
+static REPLY_CODE_RE: LazyLock<regex::Regex> = LazyLock::new(|| {
+    regex::Regex::new(r"^2\d\d[ -]").unwrap()
+});
+/// Enhanced status code following the reply code
+static ENHANCED_CODE_RE: LazyLock<regex::Regex> = LazyLock::new(|| {
+    regex::Regex::new(r"^\d\.\d{1,3}\.\d{1,3}\s+").unwrap()
+});
+
+/// Extract the provider's message id from a 250 response to DATA.
+///
+/// Handles the common shapes:
+/// - Postfix and SendGrid: `2.0.0 Ok: queued as 4XyZ12abc`
+/// - Amazon SES: `Ok 0100018c2f...-000000`
+/// - Servers that echo an id in angle brackets: `2.0.0 OK <id@host>`
+pub fn parse_queued_id(response: &str) -> Option<String> {
+    let mut text = response.trim();
+
+    // Drop the reply code and enhanced status code if present
+    for re in [&*REPLY_CODE_RE, &*ENHANCED_CODE_RE] {
+        if let Some(m) = re.find(text) {
+            text = text[m.end()..].trim_start();
+        }
+    }
+
+    let clean = |token: &str| {
+        let token = token.trim_matches(|c: char| matches!(c, '<' | '>' | '[' | ']' | '(' | ')' | ',' | ';' | '.'));
+        (!token.is_empty()).then(|| token.to_string())
+    };
+
+    let lower = text.to_lowercase();
+    if let Some(pos) = lower.find("queued as ") {
+        return text[pos + "queued as ".len()..].split_whitespace().next().and_then(clean);
+    }
+
+    if let (Some(start), Some(end)) = (text.find('<'), text.rfind('>')) {
+        if start < end {
+            return clean(&text[start + 1..end]);
+        }
+    }
+
+    // SES style: a single id token after "Ok"
+    let mut tokens = text.split_whitespace();
+    match (tokens.next(), tokens.next(), tokens.next()) {
+        (Some(ok), Some(id), None) if ok.trim_end_matches(':').eq_ignore_ascii_case("ok") => clean(id),
+        _ => None,
+    }
+}
+
+/// Map a lettre send error: if the server answered it's a send failure,
+/// anything else means the connection failed
+fn send_error(e: lettre::transport::smtp::Error) -> SmtpError {
+    match e.status() {
+        Some(_) => SmtpError::Send(reply_text(&e)),
+        None => SmtpError::Connection(e.to_string()),
+    }
+}
+
+/// Server reply carried by a negative response, as `550 5.1.1 User unknown`
+fn reply_text(e: &lettre::transport::smtp::Error) -> String {
+    let message = std::error::Error::source(e).map(|source| source.to_string()).unwrap_or_default();
+    match e.status() {
+        Some(code) => format!("{} {}", code, message).trim_end().to_string(),
+        None => message,
+    }
+}
+
+/// Build a [`SendResult`] from the server's reply to DATA
+fn send_result(response: &Response, size: usize) -> SendResult {
+    let message = Some(response.message().collect::<Vec<_>>().join(" "));
+
+    SendResult {
+        message_id: message.as_deref().and_then(parse_queued_id),
+        code: response.code().to_string(),
+        message,
+        size: Some(size),
+        rejected: Vec::new(),
+    }
+}
+
+/// Wrap a Message-ID in angle brackets if it isn't already
+fn angle_message_id(id: &str) -> String {
+    let id = id.trim();
+    if id.starts_with('<') && id.ends_with('>') {
+        id.to_string()
+    } else {
+        format!("<{}>", id)
+    }
+}
+
+/// Transport used by the mailer to deliver messages
+#[async_trait::async_trait]
+pub trait MailTransport: Send + Sync {
+    /// Send an email
+    async fn send(&self, email: &Email) -> Result<SendResult, SmtpError>;
+
+    /// Test connection
+    async fn test_connection(&self) -> Result<bool, SmtpError>;
+
+    /// Deadline for a single send before it is abandoned
+    fn timeout(&self) -> Duration {
+        Duration::from_secs(30)
+    }
+}
+
+#[async_trait::async_trait]
+impl MailTransport for SmtpTransport {
+    async fn send(&self, email: &Email) -> Result<SendResult, SmtpError> {
+        SmtpTransport::send(self, email).await
+    }
+
+    async fn test_connection(&self) -> Result<bool, SmtpError> {
+        SmtpTransport::test_connection(self).await
+    }
+
+    fn timeout(&self) -> Duration {
+        Duration::from_secs(self.config.timeout_secs)
     }
 }
 
@@ -357,6 +744,10 @@ pub struct SendResult {
     pub code: String,
     /// Response message
     pub message: Option<String>,
+    /// Size of the message as transmitted, in bytes
+    pub size: Option<usize>,
+    /// Recipients the server refused while accepting the rest, with its reply
+    pub rejected: Vec<(String, String)>,
 }
 
 impl SendResult {
@@ -1,12 +1,15 @@
 //! Template Service
 
 use std::collections::HashMap;
-use std::sync::Arc;
+use std::sync::{Arc, LazyLock};
 use tokio::sync::RwLock;
 use uuid::Uuid;
 use handlebars::Handlebars;
 
-use crate::models::{EmailTemplate, EmailLayout, Email, EmailAddress, TemplateBuilder};
+use crate::models::{
+    EmailTemplate, EmailLayout, Email, EmailAddress, Attachment, TemplateBuilder, TemplateType, VariableType,
+    TemplateBundle, ImportMode, ImportReport, TEMPLATE_BUNDLE_VERSION,
+};
 
 /// Template service error
 #[derive(Debug, thiserror::Error)]
@@ -17,8 +20,16 @@ pub enum TemplateError {
     LayoutNotFound(String),
     #[error("Render error: {0}")]
     RenderError(String),
+    #[error("render error in '{slug}' ({field}): {message}")]
+    Render {
+        slug: String,
+        field: &'static str,
+        message: String,
+    },
     #[error("Invalid template: {0}")]
     Invalid(String),
+    #[error("Template slug already exists: {0}")]
+    SlugExists(String),
     #[error("Missing variable: {0}")]
     MissingVariable(String),
 }
@@ -35,8 +46,42 @@ pub struct TemplateService {
     default_layout: Arc<RwLock<Option<Uuid>>>,
     /// Handlebars engine
     handlebars: Arc<RwLock<Handlebars<'static>>>,
+    /// Copy of the engine without HTML escaping, for subjects and text bodies
+    plain_handlebars: Arc<RwLock<Handlebars<'static>>>,
+    /// Require unsubscribe link and postal address in marketing templates
+    marketing_compliance: bool,
+    /// Generate a plain text body from the HTML when a template has none
+    text_fallback: bool,
+    /// Sanitize values bound to HTML variables before rendering
+    sanitize_html: bool,
+    /// Maximum bytes a single rendered field may produce
+    max_output_bytes: Option<usize>,
+    /// Maximum total array elements in render data
+    max_iterations: Option<usize>,
+    /// Fail rendering when a template's layout no longer exists
+    strict_layouts: bool,
 }
 
+/// Variable that must be present in compliant marketing templates for the unsubscribe link
+pub const UNSUBSCRIBE_VAR: &str = "unsubscribe_url";
+/// Variable that must be present in compliant marketing templates for the postal address
+pub const PHYSICAL_ADDRESS_VAR: &str = "physical_address";
+
+/// Double or triple-stash placeholder for `var`
+fn placeholder_regex(var: &str) -> regex::Regex {
+    regex::Regex::new(&format!(r"\{{\{{\{{?\s*{}\s*\}}?\}}\}}", var)).unwrap()
+}
+static UNSUBSCRIBE_RE: LazyLock<regex::Regex> = LazyLock::new(|| placeholder_regex(UNSUBSCRIBE_VAR));
+static PHYSICAL_ADDRESS_RE: LazyLock<regex::Regex> = LazyLock::new(|| placeholder_regex(PHYSICAL_ADDRESS_VAR));
+
+/// Inline style that hides the preheader across clients, including Outlook
+const PREHEADER_STYLE: &str = "display:none;font-size:1px;line-height:1px;max-height:0;max-width:0;opacity:0;overflow:hidden;mso-hide:all;";
+/// Invisible filler placed after the preheader so clients do not pull body
+/// text into the inbox preview
+const PREHEADER_PADDING: &str = "&#847;&zwnj;&nbsp;";
+/// Number of filler repetitions after the preheader
+const PREHEADER_PADDING_REPEAT: usize = 90;
+
 impl TemplateService {
     pub fn new() -> Self {
         let mut handlebars = Handlebars::new();
@@ -44,6 +89,7 @@ impl TemplateService {
 
         // Register helpers
         Self::register_helpers(&mut handlebars);
+        let plain_handlebars = Self::plain_copy(&handlebars);
 
         Self {
             templates: Arc::new(RwLock::new(HashMap::new())),
@@ -51,11 +97,101 @@ impl TemplateService {
             layouts: Arc::new(RwLock::new(HashMap::new())),
             default_layout: Arc::new(RwLock::new(None)),
             handlebars: Arc::new(RwLock::new(handlebars)),
+            plain_handlebars: Arc::new(RwLock::new(plain_handlebars)),
+            marketing_compliance: false,
+            text_fallback: false,
+            sanitize_html: false,
+            max_output_bytes: None,
+            max_iterations: None,
+            strict_layouts: true,
+        }
+    }
+
+    /// Enforce CAN-SPAM style footers (unsubscribe link and postal address)
+    /// on marketing templates at registration
+    pub fn with_marketing_compliance(mut self, enabled: bool) -> Self {
+        self.marketing_compliance = enabled;
+        self
+    }
+
+    /// Generate a plain text body from the rendered HTML for templates
+    /// that only define an HTML body
+    pub fn with_text_fallback(mut self, enabled: bool) -> Self {
+        self.text_fallback = enabled;
+        self
+    }
+
+    /// Strip scripts, event handlers and other unsafe markup from values
+    /// bound to `VariableType::Html` variables before rendering
+    pub fn with_sanitize_html(mut self, enabled: bool) -> Self {
+        self.sanitize_html = enabled;
+        self
+    }
+
+    /// Abort rendering once a field's output exceeds `bytes`
+    pub fn with_max_output_bytes(mut self, bytes: usize) -> Self {
+        self.max_output_bytes = Some(bytes);
+        self
+    }
+
+    /// Reject render data containing more than `count` array elements in total
+    pub fn with_max_iterations(mut self, count: usize) -> Self {
+        self.max_iterations = Some(count);
+        self
+    }
+
+    /// Whether a template referencing a missing layout fails to render
+    /// (the default) or renders unwrapped with a warning
+    pub fn with_strict_layouts(mut self, enabled: bool) -> Self {
+        self.strict_layouts = enabled;
+        self
+    }
+
+    /// Sanitize string values of a template's HTML variables
+    fn sanitize_data(template: &EmailTemplate, data: &serde_json::Value) -> serde_json::Value {
+        let mut data = data.clone();
+
+        if let Some(object) = data.as_object_mut() {
+            for var in template.variables.iter().filter(|v| v.var_type == VariableType::Html) {
+                if let Some(serde_json::Value::String(value)) = object.get_mut(&var.name) {
+                    *value = ammonia::clean(value);
+                }
+            }
         }
+
+        data
+    }
+
+    /// Check a marketing template for the required compliance placeholders
+    fn check_marketing_compliance(template: &EmailTemplate) -> Result<(), TemplateError> {
+        if template.template_type != TemplateType::Marketing {
+            return Ok(());
+        }
+
+        let body = template.html_body.as_deref()
+            .or(template.text_body.as_deref())
+            .unwrap_or("");
+
+        let mut missing = Vec::new();
+        for (var, re) in [(UNSUBSCRIBE_VAR, &*UNSUBSCRIBE_RE), (PHYSICAL_ADDRESS_VAR, &*PHYSICAL_ADDRESS_RE)] {
+            if !re.is_match(body) {
+                missing.push(var);
+            }
+        }
+
+        if !missing.is_empty() {
+            return Err(TemplateError::Invalid(format!(
+                "Marketing template must include {{{{{}}}}}",
+                missing.join("}}, {{")
+            )));
+        }
+
+        Ok(())
     }
 
     fn register_helpers(handlebars: &mut Handlebars<'static>) {
-        // Date formatting helper
+        // Date formatting helper: {{date value format timezone}}, where the
+        // timezone is an IANA name or an offset such as +05:30 (default UTC)
         handlebars.register_helper(
             "date",
             Box::new(|h: &handlebars::Helper,
@@ -69,12 +205,14 @@ impl TemplateService {
                         .and_then(|p| p.value().as_str())
                         .unwrap_or("%Y-%m-%d");
 
+                    let tz = h.param(2)
+                        .and_then(|p| p.value().as_str())
+                        .unwrap_or("UTC");
+
                     if let Some(date_str) = param.value().as_str() {
-                        if let Ok(date) = chrono::DateTime::parse_from_rfc3339(date_str) {
-                            out.write(&date.format(format).to_string())?;
-                        } else {
-                            out.write(date_str)?;
-                        }
+                        let formatted = chrono::DateTime::parse_from_rfc3339(date_str).ok()
+                            .and_then(|date| format_in_timezone(date, format, tz));
+                        out.write(formatted.as_deref().unwrap_or(date_str))?;
                     }
                 }
                 Ok(())
@@ -139,6 +277,29 @@ impl TemplateService {
             }),
         );
 
+        // Line item helpers. Tabular data is rendered with the built-in
+        // `{{#each}}` block, and totals are computed with `sum`, which
+        // composes with `currency` as a subexpression:
+        //
+        //   {{#each items}}<tr><td>{{name}}</td><td>{{currency (multiply price quantity)}}</td></tr>{{/each}}
+        //   Total: {{currency (sum items field="price" qty="quantity")}}
+        //
+        // `field` picks the value to add from each item (the item itself if
+        // empty) and `qty`, when set, multiplies it by another field.
+        handlebars::handlebars_helper!(sum: |items: array, {field: str = "", qty: str = ""}| {
+            items.iter()
+                .map(|item| {
+                    let value = if field.is_empty() { item } else { &item[field] };
+                    let quantity = if qty.is_empty() { 1.0 } else { item[qty].as_f64().unwrap_or(0.0) };
+                    value.as_f64().unwrap_or(0.0) * quantity
+                })
+                .sum::<f64>()
+        });
+        handlebars.register_helper("sum", Box::new(sum));
+
+        handlebars::handlebars_helper!(multiply: |a: f64, b: f64| a * b);
+        handlebars.register_helper("multiply", Box::new(multiply));
+
         // Truncate helper
         handlebars.register_helper(
             "truncate",
@@ -166,20 +327,61 @@ impl TemplateService {
         );
     }
 
-    /// Register a template
-    pub async fn register(&self, template: EmailTemplate) -> Result<(), TemplateError> {
-        // Validate template
+    /// Register a custom Handlebars helper, replacing any existing helper
+    /// (including built-ins) with the same name
+    pub async fn register_helper(
+        &self,
+        name: &str,
+        helper: Box<dyn handlebars::HelperDef + Send + Sync>,
+    ) {
+        let mut handlebars = self.handlebars.write().await;
+        handlebars.register_helper(name, helper);
+        *self.plain_handlebars.write().await = Self::plain_copy(&handlebars);
+    }
+
+    /// The engine with HTML escaping turned off
+    fn plain_copy(handlebars: &Handlebars<'static>) -> Handlebars<'static> {
+        let mut plain = handlebars.clone();
+        plain.register_escape_fn(handlebars::no_escape);
+        plain
+    }
+
+    /// Check a template can be registered
+    fn validate(&self, template: &EmailTemplate) -> Result<(), TemplateError> {
         if template.text_body.is_none() && template.html_body.is_none() {
             return Err(TemplateError::Invalid("Template must have a body".to_string()));
         }
 
+        if !crate::models::template::is_slug(&template.slug) {
+            return Err(TemplateError::Invalid(format!("Invalid template slug: '{}'", template.slug)));
+        }
+
+        template.check_syntax().map_err(TemplateError::Invalid)?;
+
+        if self.marketing_compliance {
+            Self::check_marketing_compliance(template)?;
+        }
+
+        Ok(())
+    }
+
+    /// Register a template
+    pub async fn register(&self, template: EmailTemplate) -> Result<(), TemplateError> {
+        self.validate(&template)?;
+
         let id = template.id;
         let slug = template.slug.clone();
 
         let mut templates = self.templates.write().await;
         let mut by_slug = self.templates_by_slug.write().await;
 
-        templates.insert(id, template);
+        if by_slug.get(&slug).is_some_and(|existing| *existing != id) {
+            return Err(TemplateError::SlugExists(slug));
+        }
+
+        if let Some(previous) = templates.insert(id, template) {
+            by_slug.remove(&previous.slug);
+        }
         by_slug.insert(slug, id);
 
         Ok(())
@@ -220,6 +422,31 @@ impl TemplateService {
         }
     }
 
+    /// Duplicate a template under a new name
+    pub async fn duplicate(&self, id: Uuid, new_name: &str) -> Result<EmailTemplate, TemplateError> {
+        let source = self.get(id).await
+            .ok_or_else(|| TemplateError::NotFound(id.to_string()))?;
+
+        let slug = crate::models::template::slugify(new_name);
+        if self.get_by_slug(&slug).await.is_some() {
+            return Err(TemplateError::SlugExists(slug));
+        }
+
+        let now = chrono::Utc::now();
+        let mut copy = source;
+        copy.id = Uuid::now_v7();
+        copy.name = new_name.to_string();
+        copy.slug = slug;
+        copy.title = new_name.to_string();
+        copy.version = 1;
+        copy.created_at = now;
+        copy.updated_at = now;
+
+        self.register(copy.clone()).await?;
+
+        Ok(copy)
+    }
+
     /// Register a layout
     pub async fn register_layout(&self, layout: EmailLayout) {
         let id = layout.id;
@@ -240,6 +467,106 @@ impl TemplateService {
         layouts.get(&id).cloned()
     }
 
+    /// Get layout by slug
+    pub async fn get_layout_by_slug(&self, slug: &str) -> Option<EmailLayout> {
+        let layouts = self.layouts.read().await;
+        layouts.values().find(|l| l.slug == slug).cloned()
+    }
+
+    /// List all layouts
+    pub async fn list_layouts(&self) -> Vec<EmailLayout> {
+        let layouts = self.layouts.read().await;
+        layouts.values().cloned().collect()
+    }
+
+    /// Remove all templates and layouts
+    pub async fn clear(&self) {
+        self.templates.write().await.clear();
+        self.templates_by_slug.write().await.clear();
+        self.layouts.write().await.clear();
+        *self.default_layout.write().await = None;
+    }
+
+    /// Export all templates and layouts as a JSON bundle
+    pub async fn export_all(&self) -> String {
+        let mut layouts = self.list_layouts().await;
+        layouts.sort_by(|a, b| a.slug.cmp(&b.slug));
+        let mut templates = self.list().await;
+        templates.sort_by(|a, b| a.slug.cmp(&b.slug));
+
+        let bundle = TemplateBundle {
+            version: TEMPLATE_BUNDLE_VERSION,
+            exported_at: chrono::Utc::now(),
+            layouts,
+            templates,
+        };
+        serde_json::to_string_pretty(&bundle).unwrap_or_default()
+    }
+
+    /// Import a bundle produced by [`export_all`](Self::export_all).
+    ///
+    /// Every template is validated (including compiling its Handlebars
+    /// sources) before anything is stored, so an invalid bundle leaves the
+    /// service untouched.
+    pub async fn import_bundle(&self, json: &str, mode: ImportMode) -> Result<ImportReport, TemplateError> {
+        let bundle: TemplateBundle = serde_json::from_str(json)
+            .map_err(|e| TemplateError::Invalid(format!("Invalid bundle: {}", e)))?;
+
+        if bundle.version > TEMPLATE_BUNDLE_VERSION {
+            return Err(TemplateError::Invalid(format!(
+                "Unsupported bundle version: {}", bundle.version
+            )));
+        }
+
+        for template in &bundle.templates {
+            self.validate(template)
+                .map_err(|e| TemplateError::Invalid(format!("{}: {}", template.slug, e)))?;
+
+            let sources = [Some(&template.subject), template.text_body.as_ref(), template.html_body.as_ref()];
+            for source in sources.into_iter().flatten() {
+                handlebars::Template::compile(source)
+                    .map_err(|e| TemplateError::Invalid(format!("{}: {}", template.slug, e)))?;
+            }
+        }
+
+        let mut report = ImportReport::default();
+
+        for layout in bundle.layouts {
+            let existing = self.list_layouts().await.into_iter()
+                .find(|l| l.slug == layout.slug);
+
+            if let Some(existing) = existing {
+                if mode == ImportMode::Merge {
+                    report.layouts_skipped += 1;
+                    continue;
+                }
+                self.layouts.write().await.remove(&existing.id);
+                let mut default = self.default_layout.write().await;
+                if *default == Some(existing.id) {
+                    *default = None;
+                }
+            }
+
+            self.register_layout(layout).await;
+            report.layouts_imported += 1;
+        }
+
+        for template in bundle.templates {
+            if let Some(existing) = self.get_by_slug(&template.slug).await {
+                if mode == ImportMode::Merge {
+                    report.templates_skipped += 1;
+                    continue;
+                }
+                self.delete(existing.id).await?;
+            }
+
+            self.register(template).await?;
+            report.templates_imported += 1;
+        }
+
+        Ok(report)
+    }
+
     /// Render a template with data
     pub async fn render(
         &self,
@@ -264,6 +591,57 @@ impl TemplateService {
         self.render_template(&template, data).await
     }
 
+    /// Render a template with the sample data from its variable examples
+    pub async fn preview_with_examples(&self, slug: &str) -> Result<RenderedEmail, TemplateError> {
+        let template = self.get_by_slug(slug).await
+            .ok_or_else(|| TemplateError::NotFound(slug.to_string()))?;
+
+        self.render_template(&template, &template.sample_data()).await
+    }
+
+    /// Render a template once per named sample dataset. A failing sample
+    /// reports its own error without affecting the others.
+    pub async fn preview_samples(
+        &self,
+        slug: &str,
+        samples: HashMap<String, serde_json::Value>,
+    ) -> Result<HashMap<String, Result<RenderedEmail, TemplateError>>, TemplateError> {
+        let template = self.get_by_slug(slug).await
+            .ok_or_else(|| TemplateError::NotFound(slug.to_string()))?;
+
+        let mut previews = HashMap::with_capacity(samples.len());
+        for (name, data) in samples {
+            let rendered = self.render_template(&template, &data).await;
+            previews.insert(name, rendered);
+        }
+
+        Ok(previews)
+    }
+
+    /// Render a one-off template string with the shared helpers. Like
+    /// subjects, the output is not HTML-escaped.
+    pub async fn render_string(
+        &self,
+        template: &str,
+        data: &serde_json::Value,
+    ) -> Result<String, TemplateError> {
+        if let Some(max) = self.max_iterations {
+            if count_array_elements(data) > max {
+                return Err(TemplateError::RenderError("iteration limit exceeded".to_string()));
+            }
+        }
+
+        let handlebars = self.plain_handlebars.read().await;
+        let mut writer = LimitedWriter::new(self.max_output_bytes);
+        match handlebars.render_template_to_write(template, data, &mut writer) {
+            Ok(()) => Ok(String::from_utf8_lossy(&writer.buf).into_owned()),
+            Err(_) if writer.exceeded => {
+                Err(TemplateError::RenderError("output limit exceeded".to_string()))
+            }
+            Err(e) => Err(TemplateError::RenderError(e.to_string())),
+        }
+    }
+
     /// Render template
     async fn render_template(
         &self,
@@ -276,34 +654,85 @@ impl TemplateService {
             return Err(TemplateError::MissingVariable(missing.join(", ")));
         }
 
+        let sanitized;
+        let data = if self.sanitize_html {
+            sanitized = Self::sanitize_data(template, data);
+            &sanitized
+        } else {
+            data
+        };
+
+        if let Some(max) = self.max_iterations {
+            if count_array_elements(data) > max {
+                return Err(TemplateError::RenderError("iteration limit exceeded".to_string()));
+            }
+        }
+
+        // Only HTML is escaped; subjects and text bodies render literally
         let handlebars = self.handlebars.read().await;
+        let plain_handlebars = self.plain_handlebars.read().await;
+        let render = |field: &'static str, source: &str| {
+            let handlebars = match field {
+                "subject" | "text" => &*plain_handlebars,
+                _ => &*handlebars,
+            };
+            let mut writer = LimitedWriter::new(self.max_output_bytes);
+            match handlebars.render_template_to_write(source, data, &mut writer) {
+                Ok(()) => Ok(String::from_utf8_lossy(&writer.buf).into_owned()),
+                Err(_) if writer.exceeded => {
+                    Err(TemplateError::RenderError("output limit exceeded".to_string()))
+                }
+                Err(e) => Err(TemplateError::Render {
+                    slug: template.slug.clone(),
+                    field,
+                    message: e.to_string(),
+                }),
+            }
+        };
 
         // Render subject
-        let subject = handlebars.render_template(&template.subject, data)
-            .map_err(|e| TemplateError::RenderError(e.to_string()))?;
+        let subject = render("subject", &template.subject)?;
 
         // Render text body
         let text_body = if let Some(text) = &template.text_body {
-            Some(handlebars.render_template(text, data)
-                .map_err(|e| TemplateError::RenderError(e.to_string()))?)
+            Some(render("text", text)?)
         } else {
             None
         };
 
         // Render HTML body
         let mut html_body = if let Some(html) = &template.html_body {
-            Some(handlebars.render_template(html, data)
-                .map_err(|e| TemplateError::RenderError(e.to_string()))?)
+            Some(render("html", html)?)
         } else {
             None
         };
 
+        // Derive text from the HTML content (before any layout chrome)
+        let text_body = match (text_body, &html_body) {
+            (None, Some(html)) if self.text_fallback => Some(html_to_text(html)),
+            (text, _) => text,
+        };
+
         // Apply layout if set
-        if let Some(layout_id) = template.layout_id {
-            if let Some(layout) = self.get_layout(layout_id).await {
-                if let Some(html) = &html_body {
-                    html_body = Some(layout.apply_html(html));
+        if template.use_layout == Some(false) {
+            // Template opted out of layouts entirely
+        } else if let Some(layout_id) = template.layout_id {
+            match self.get_layout(layout_id).await {
+                Some(layout) => {
+                    if let Some(html) = &html_body {
+                        html_body = Some(layout.apply_html(html));
+                    }
+                }
+                None => self.missing_layout(template, &layout_id.to_string())?,
+            }
+        } else if let Some(slug) = &template.layout_slug {
+            match self.get_layout_by_slug(slug).await {
+                Some(layout) => {
+                    if let Some(html) = &html_body {
+                        html_body = Some(layout.apply_html(html));
+                    }
                 }
+                None => self.missing_layout(template, slug)?,
             }
         } else {
             // Try default layout
@@ -319,8 +748,7 @@ impl TemplateService {
 
         // Render preheader
         let preheader = if let Some(ph) = &template.preheader {
-            Some(handlebars.render_template(ph, data)
-                .map_err(|e| TemplateError::RenderError(e.to_string()))?)
+            Some(render("preheader", ph)?)
         } else {
             None
         };
@@ -332,9 +760,21 @@ impl TemplateService {
             text_body,
             html_body,
             preheader,
+            attachments: template.attachments.iter().map(|a| a.to_attachment()).collect(),
+            default_reply_to: template.default_reply_to.clone(),
         })
     }
 
+    /// Handle a template whose layout can't be found
+    fn missing_layout(&self, template: &EmailTemplate, layout: &str) -> Result<(), TemplateError> {
+        if self.strict_layouts {
+            return Err(TemplateError::LayoutNotFound(layout.to_string()));
+        }
+
+        tracing::warn!(template = %template.slug, layout, "layout not found, rendering without it");
+        Ok(())
+    }
+
     /// Build an email from a rendered template
     pub fn build_email(
         &self,
@@ -345,6 +785,7 @@ impl TemplateService {
         let mut email = Email::new(from, to, &rendered.subject);
 
         email.template_id = Some(rendered.template_id);
+        email.reply_to = rendered.default_reply_to.as_deref().map(EmailAddress::new);
 
         if let Some(text) = rendered.text_body {
             email.text_body = Some(text);
@@ -352,20 +793,37 @@ impl TemplateService {
 
         if let Some(html) = rendered.html_body {
             // Insert preheader if present
-            let final_html = if let Some(preheader) = rendered.preheader {
-                format!(
-                    r#"<div style="display:none;max-height:0;overflow:hidden;">{}</div>{}"#,
-                    preheader, html
-                )
-            } else {
-                html
+            let final_html = match rendered.preheader.as_deref() {
+                Some(preheader) => Self::insert_preheader(preheader, &html),
+                None => html,
             };
             email.html_body = Some(final_html);
         }
 
+        email.attachments.extend(rendered.attachments);
+
         email
     }
 
+    /// Place a hidden preheader, followed by invisible padding, at the start
+    /// of the visible body (just after `<body>` when the HTML has one)
+    fn insert_preheader(preheader: &str, html: &str) -> String {
+        let block = format!(
+            r#"<div style="{style}">{preheader}</div><div style="{style}">{padding}</div>"#,
+            style = PREHEADER_STYLE,
+            preheader = preheader,
+            padding = PREHEADER_PADDING.repeat(PREHEADER_PADDING_REPEAT),
+        );
+
+        let body_start = html.to_ascii_lowercase().find("<body")
+            .and_then(|start| html[start..].find('>').map(|end| start + end + 1));
+
+        match body_start {
+            Some(at) => format!("{}{}{}", &html[..at], block, &html[at..]),
+            None => format!("{}{}", block, html),
+        }
+    }
+
     /// Register system templates
     pub async fn register_system_templates(&self) {
         // Password reset template
@@ -376,6 +834,8 @@ impl TemplateService {
             .subject("Reset Your Password")
             .required_var("reset_link", "Password reset URL")
             .required_var("user_name", "User's name")
+            .example("reset_link", "https://example.com/reset?token=abc123")
+            .example("user_name", "Jane Doe")
             .optional_var("expiry_hours", "24")
             .text(r#"Hi {{user_name}},
 
@@ -420,6 +880,8 @@ The Team"#)
             .subject("Verify Your Email Address")
             .required_var("verify_link", "Verification URL")
             .required_var("user_name", "User's name")
+            .example("verify_link", "https://example.com/verify?token=abc123")
+            .example("user_name", "Jane Doe")
             .text(r#"Hi {{user_name}},
 
 Please verify your email address by clicking the link below:
@@ -457,6 +919,8 @@ The Team"#)
             .subject("Welcome to {{site_name}}!")
             .required_var("user_name", "User's name")
             .required_var("site_name", "Site name")
+            .example("user_name", "Jane Doe")
+            .example("site_name", "Example Site")
             .optional_var("login_link", "/login")
             .text(r#"Welcome {{user_name}}!
 
@@ -495,6 +959,172 @@ impl Default for TemplateService {
     }
 }
 
+/// Convert HTML to a readable plain text approximation.
+///
+/// Block elements become line breaks, list items are bulleted and links keep
+/// their target in parentheses; scripts, styles and the document head are
+/// dropped.
+pub fn html_to_text(html: &str) -> String {
+    use scraper::{ElementRef, Html, Node};
+
+    fn walk(element: ElementRef, out: &mut String) {
+        let name = element.value().name();
+
+        if matches!(name, "script" | "style" | "head" | "title") {
+            return;
+        }
+
+        let is_block = matches!(
+            name,
+            "p" | "div" | "h1" | "h2" | "h3" | "h4" | "h5" | "h6" | "ul" | "ol" | "li"
+                | "table" | "tr" | "blockquote" | "section" | "article" | "header"
+                | "footer" | "hr" | "pre"
+        );
+
+        if is_block {
+            out.push('\n');
+        }
+        match name {
+            "br" => out.push('\n'),
+            "li" => out.push_str("- "),
+            "td" | "th" => out.push(' '),
+            _ => {}
+        }
+
+        for child in element.children() {
+            match child.value() {
+                Node::Text(text) => {
+                    let collapsed = text.split_whitespace().collect::<Vec<_>>().join(" ");
+                    if !collapsed.is_empty() {
+                        if text.starts_with(char::is_whitespace) && !out.ends_with(char::is_whitespace) {
+                            out.push(' ');
+                        }
+                        out.push_str(&collapsed);
+                        if text.ends_with(char::is_whitespace) {
+                            out.push(' ');
+                        }
+                    }
+                }
+                Node::Element(_) => {
+                    if let Some(child) = ElementRef::wrap(child) {
+                        walk(child, out);
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        if name == "a" {
+            if let Some(href) = element.value().attr("href") {
+                let label = element.text().collect::<String>();
+                if !href.starts_with('#') && label.trim() != href {
+                    out.push_str(&format!(" ({})", href));
+                }
+            }
+        }
+
+        if is_block {
+            out.push('\n');
+        }
+    }
+
+    let document = Html::parse_document(html);
+    let mut out = String::new();
+    walk(document.root_element(), &mut out);
+
+    // Trim each line and collapse runs of blank lines
+    let mut text = String::new();
+    let mut blank = true;
+    for line in out.lines().map(str::trim) {
+        if line.is_empty() {
+            if !blank {
+                text.push('\n');
+            }
+            blank = true;
+        } else {
+            text.push_str(line);
+            text.push('\n');
+            blank = false;
+        }
+    }
+
+    text.trim().to_string()
+}
+
+/// Deep-merge `overrides` into `base`; object keys are merged recursively
+/// and any other value in `overrides` replaces the one in `base`
+pub fn merge_data(base: &mut serde_json::Value, overrides: serde_json::Value) {
+    match (base, overrides) {
+        (serde_json::Value::Object(base_map), serde_json::Value::Object(override_map)) => {
+            for (key, value) in override_map {
+                match base_map.get_mut(&key) {
+                    Some(existing) => merge_data(existing, value),
+                    None => {
+                        base_map.insert(key, value);
+                    }
+                }
+            }
+        }
+        (base, overrides) => *base = overrides,
+    }
+}
+
+/// Format a date in a timezone given as an IANA name or a fixed offset.
+/// Returns `None` if the timezone isn't recognised.
+fn format_in_timezone(date: chrono::DateTime<chrono::FixedOffset>, format: &str, tz: &str) -> Option<String> {
+    if let Ok(tz) = tz.parse::<chrono_tz::Tz>() {
+        return Some(date.with_timezone(&tz).format(format).to_string());
+    }
+
+    tz.parse::<chrono::FixedOffset>().ok()
+        .map(|offset| date.with_timezone(&offset).format(format).to_string())
+}
+
+/// Total number of array elements anywhere in a JSON value
+fn count_array_elements(value: &serde_json::Value) -> usize {
+    match value {
+        serde_json::Value::Array(items) => {
+            items.len() + items.iter().map(count_array_elements).sum::<usize>()
+        }
+        serde_json::Value::Object(map) => map.values().map(count_array_elements).sum(),
+        _ => 0,
+    }
+}
+
+/// Render output buffer that fails writes past a size limit
+struct LimitedWriter {
+    buf: Vec<u8>,
+    limit: Option<usize>,
+    exceeded: bool,
+}
+
+impl LimitedWriter {
+    fn new(limit: Option<usize>) -> Self {
+        Self {
+            buf: Vec::new(),
+            limit,
+            exceeded: false,
+        }
+    }
+}
+
+impl std::io::Write for LimitedWriter {
+    fn write(&mut self, data: &[u8]) -> std::io::Result<usize> {
+        if let Some(limit) = self.limit {
+            if self.buf.len() + data.len() > limit {
+                self.exceeded = true;
+                return Err(std::io::Error::other("output limit exceeded"));
+            }
+        }
+        self.buf.extend_from_slice(data);
+        Ok(data.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
 /// Rendered email content
 #[derive(Debug, Clone)]
 pub struct RenderedEmail {
@@ -504,4 +1134,8 @@ pub struct RenderedEmail {
     pub text_body: Option<String>,
     pub html_body: Option<String>,
     pub preheader: Option<String>,
+    /// The template's static attachments
+    pub attachments: Vec<Attachment>,
+    /// The template's default Reply-To address
+    pub default_reply_to: Option<String>,
 }
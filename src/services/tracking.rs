@@ -0,0 +1,52 @@
+//! Open and Click Tracking
+//!
+//! Rewrites outgoing HTML so opens and clicks can be attributed to an email.
+
+use std::sync::LazyLock;
+
+use regex::Regex;
+use uuid::Uuid;
+
+/// Path under the tracking base URL that serves the open pixel
+pub const OPEN_PATH: &str = "/rustmail/track/open";
+/// Path under the tracking base URL that records a click and redirects
+pub const CLICK_PATH: &str = "/rustmail/track/click";
+
+/// Absolute http(s) link in the double-quoted `href` of an `<a>` tag.
+/// Other tags with an `href`, such as `<link>` and `<base>`, are left alone.
+static LINK_RE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r#"(?i)(<a\s(?:[^>]*?\s)?href\s*=\s*)"(https?://[^"]+)""#).unwrap()
+});
+
+/// URL of the open tracking pixel for an email
+pub fn open_pixel_url(base_url: &str, email_id: Uuid) -> String {
+    format!("{}{}/{}", base_url.trim_end_matches('/'), OPEN_PATH, email_id)
+}
+
+/// URL that records a click on `target` before redirecting to it
+pub fn click_url(base_url: &str, email_id: Uuid, target: &str) -> String {
+    let encoded: String = url::form_urlencoded::byte_serialize(target.as_bytes()).collect();
+    format!("{}{}/{}?url={}", base_url.trim_end_matches('/'), CLICK_PATH, email_id, encoded)
+}
+
+/// Insert an open tracking pixel before `</body>`, or at the end if there is none
+pub fn inject_open_pixel(html: &str, base_url: &str, email_id: Uuid) -> String {
+    let pixel = format!(
+        r#"<img src="{}" width="1" height="1" alt="" style="display:none">"#,
+        open_pixel_url(base_url, email_id)
+    );
+
+    // ASCII lowercasing keeps byte offsets valid for slicing `html`
+    match html.to_ascii_lowercase().rfind("</body>") {
+        Some(pos) => format!("{}{}{}", &html[..pos], pixel, &html[pos..]),
+        None => format!("{}{}", html, pixel),
+    }
+}
+
+/// Route absolute http(s) links through the click tracker
+pub fn rewrite_links(html: &str, base_url: &str, email_id: Uuid) -> String {
+    LINK_RE.replace_all(html, |caps: &regex::Captures| {
+        format!(r#"{}"{}""#, &caps[1], click_url(base_url, email_id, &caps[2]))
+    })
+    .into_owned()
+}